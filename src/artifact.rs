@@ -0,0 +1,827 @@
+//! proof artifacts bundling the proofs of mpt circuit and its accompanied hash circuit, or
+//! the proof of the combined circuit, with the public inputs and the parameters they are
+//! created under, so they can be stored and verified elsewhere
+
+use crate::{
+    operation::to_hash,
+    serde::{Hash, HexBytes},
+    CircuitMode, CombinedCircuit, CommitmentIndexs, EthTrieCircuit, HashCircuit,
+};
+use ethers_core::utils::keccak256;
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{create_proof, verify_proof, Circuit, Error as PlonkError, ProvingKey, VerifyingKey},
+    poly::{
+        commitment::{Params, ParamsProver},
+        kzg::{
+            commitment::{KZGCommitmentScheme, ParamsKZG},
+            multiopen::{ProverSHPLONK, VerifierSHPLONK},
+            strategy::SingleStrategy,
+        },
+    },
+    transcript::{
+        Blake2bRead, Blake2bWrite, Challenge255, PoseidonRead, PoseidonWrite, TranscriptRead,
+        TranscriptReadBuffer, TranscriptWriterBuffer,
+    },
+    SerdeFormat,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use thiserror::Error;
+
+/// The version of artifact format written by this crate
+pub const ARTIFACT_VERSION: u32 = 1;
+
+// leading bytes of the binary form
+const MAGIC: &[u8; 4] = b"MPTA";
+
+/// Errors raised when creating, storing or verifying artifacts
+#[derive(Error, Debug)]
+pub enum ArtifactError {
+    /// reading or writing the artifact fails
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// the json form of artifact is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// the binary form does not start with the magic bytes
+    #[error("not an mpt proof artifact")]
+    BadMagic,
+    /// the artifact is written in another version of format
+    #[error("artifact version {0} is not supported, expect {ARTIFACT_VERSION}")]
+    Version(u32),
+    /// a public input is not a canonical field element
+    #[error("{0:?} is not a canonical field element")]
+    NonCanonical(Hash),
+    /// the vk differs from the one proofs are created under
+    #[error(
+        "{circuit} vk has fingerprint {actual:?}, but the proof is created under {expected:?}"
+    )]
+    VkMismatch {
+        /// the circuit of vk, "mpt", "hash" or "combined"
+        circuit: &'static str,
+        /// fingerprint recorded in the artifact
+        expected: Hash,
+        /// fingerprint of the given vk
+        actual: Hash,
+    },
+    /// the params can not support the circuit
+    #[error("params of k = {params} is too small for circuit of k = {circuit}")]
+    ParamsTooSmall {
+        /// k of params
+        params: u32,
+        /// k of circuit
+        circuit: u32,
+    },
+    /// the hash table commitments in mpt proof differ from the ones in hash proof
+    #[error("hash table column {column} is committed differently in mpt and hash proof")]
+    HashTableMismatch {
+        /// index of column in hash table
+        column: usize,
+    },
+    /// the commitment indexes recorded in the artifact are not the ones of circuit
+    #[error("artifact records commitment indexes {stored:?}, but the circuit has {expected:?}")]
+    IndexMismatch {
+        /// hash table begin, at mpt and at hash circuit, and mpt table begin of circuit
+        expected: (usize, usize, Option<usize>),
+        /// the ones recorded in the artifact
+        stored: (usize, usize, Option<usize>),
+    },
+    /// the hash table does not fit in the advice commitments of proof
+    #[error("hash table beginning at commitment {0} is out of the advice commitments")]
+    BadCommitmentIndex(usize),
+    /// the artifact is verified as the proofs of another mode
+    #[error("the artifact is proven under {0:?} mode")]
+    Mode(CircuitMode),
+    /// creating or verifying the proof fails
+    #[error("{circuit} proof: {source}")]
+    Proof {
+        /// the circuit of proof, "mpt", "hash" or "combined"
+        circuit: &'static str,
+        /// the error from prover or verifier
+        source: PlonkError,
+    },
+}
+
+/// The transcript proofs are created with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TranscriptKind {
+    /// blake2b transcript
+    Blake2b,
+    /// poseidon transcript, for the proofs to be verified in circuits
+    Poseidon,
+}
+
+/// The randomness of storage keys and values in mpt table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TableRandomness {
+    /// no mpt table, i.e. the lite circuit
+    None,
+    /// the challenge drawn after the first phase
+    Challenge,
+}
+
+/// The proofs of a pair of mpt and hash circuit, or the proof of `CombinedCircuit`, along with
+/// everything required to verify them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MptProofArtifact {
+    /// version of format, `ARTIFACT_VERSION` for the artifacts created by this crate
+    pub version: u32,
+    /// k of mpt circuit, or of the combined circuit
+    pub mpt_k: u32,
+    /// k of hash circuit, 0 for the combined circuit
+    pub hash_k: u32,
+    /// rows of both circuits, i.e. `EthTrieCircuit::calcs`
+    pub calcs: usize,
+    /// if mpt circuit is under lite mode
+    pub lite: bool,
+    /// if the proof is of `CombinedCircuit`, which has no hash proof and commitment indexes
+    #[serde(default)]
+    pub combined: bool,
+    /// randomness of mpt table
+    pub randomness: TableRandomness,
+    /// transcript of both proofs
+    pub transcript: TranscriptKind,
+    /// proof of mpt circuit, or of the combined circuit
+    #[serde(with = "hex_vec")]
+    pub mpt_proof: Vec<u8>,
+    /// proof of hash circuit, empty for the combined circuit
+    #[serde(with = "hex_vec")]
+    pub hash_proof: Vec<u8>,
+    /// public inputs of mpt circuit, the hash circuit has none
+    pub instances: Vec<Hash>,
+    /// beginning of hash table commitments in mpt proof
+    pub hash_tbl_begin: usize,
+    /// beginning of hash table commitments in hash proof
+    pub hash_tbl_begin_at_hash_circuit: usize,
+    /// beginning of mpt table commitments in mpt proof, none for lite circuit
+    pub mpt_tbl_begin: Option<usize>,
+    /// fingerprint of mpt circuit vk, or of the combined circuit vk
+    pub mpt_vk_fingerprint: Hash,
+    /// fingerprint of hash circuit vk, zero for the combined circuit
+    pub hash_vk_fingerprint: Hash,
+}
+
+/// The fingerprint of vk, i.e. keccak256 over its raw bytes
+pub fn vk_fingerprint(vk: &VerifyingKey<G1Affine>) -> Hash {
+    let mut buf = Vec::new();
+    vk.write(&mut buf, SerdeFormat::RawBytes)
+        .expect("write to vec should not fail");
+    HexBytes(keccak256(buf))
+}
+
+// params of exactly k, none if [params] is too small
+pub(crate) fn downsize(params: &ParamsKZG<Bn256>, k: u32) -> Option<ParamsKZG<Bn256>> {
+    if params.k() < k {
+        return None;
+    }
+    let mut params = params.clone();
+    params.downsize(k);
+    Some(params)
+}
+
+fn downsize_for(params: &ParamsKZG<Bn256>, k: u32) -> Result<ParamsKZG<Bn256>, ArtifactError> {
+    downsize(params, k).ok_or(ArtifactError::ParamsTooSmall {
+        params: params.k(),
+        circuit: k,
+    })
+}
+
+fn create<C: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    instance: &[Fr],
+    transcript: TranscriptKind,
+    rng: impl RngCore,
+) -> Result<Vec<u8>, PlonkError> {
+    match transcript {
+        TranscriptKind::Blake2b => {
+            let mut transcript = Blake2bWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+                params,
+                pk,
+                &[circuit],
+                &[&[instance]],
+                rng,
+                &mut transcript,
+            )?;
+            Ok(transcript.finalize())
+        }
+        TranscriptKind::Poseidon => {
+            let mut transcript = PoseidonWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+            create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+                params,
+                pk,
+                &[circuit],
+                &[&[instance]],
+                rng,
+                &mut transcript,
+            )?;
+            Ok(transcript.finalize())
+        }
+    }
+}
+
+fn check(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    instance: &[Fr],
+    transcript: TranscriptKind,
+) -> Result<(), PlonkError> {
+    match transcript {
+        TranscriptKind::Blake2b => {
+            let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+            verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+                params.verifier_params(),
+                vk,
+                SingleStrategy::new(params),
+                &[&[instance]],
+                &mut transcript,
+            )
+        }
+        TranscriptKind::Poseidon => {
+            let mut transcript = PoseidonRead::<_, G1Affine, Challenge255<_>>::init(proof);
+            verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+                params.verifier_params(),
+                vk,
+                SingleStrategy::new(params),
+                &[&[instance]],
+                &mut transcript,
+            )
+        }
+    }
+}
+
+// read the advice commitments at [positions] in proof
+fn read_commitments(
+    proof: &[u8],
+    positions: &[usize],
+    transcript: TranscriptKind,
+) -> io::Result<Vec<G1Affine>> {
+    let count = positions.iter().max().map_or(0, |pos| pos + 1);
+    let points = match transcript {
+        TranscriptKind::Blake2b => {
+            let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(proof);
+            (0..count)
+                .map(|_| transcript.read_point())
+                .collect::<io::Result<Vec<_>>>()?
+        }
+        TranscriptKind::Poseidon => {
+            let mut transcript = PoseidonRead::<_, G1Affine, Challenge255<_>>::init(proof);
+            (0..count)
+                .map(|_| transcript.read_point())
+                .collect::<io::Result<Vec<_>>>()?
+        }
+    };
+    Ok(positions.iter().map(|pos| points[*pos]).collect())
+}
+
+/// Check that the proofs of mpt circuit and its accompanied hash circuit commit to the same
+/// hash table, i.e. the advice commitments of hash table at `CommitmentIndexs::hash_tbl_begin`
+/// and `CommitmentIndexs::hash_tbl_begin_at_accompanied_circuit` are identical, so an mpt proof
+/// can not be accepted along with the proof of an unrelated hash table
+pub fn check_hash_table_commitments(
+    (mpt_proof, mpt_vk): (&[u8], &VerifyingKey<G1Affine>),
+    (hash_proof, hash_vk): (&[u8], &VerifyingKey<G1Affine>),
+    indexs: &CommitmentIndexs,
+    transcript: TranscriptKind,
+) -> Result<(), ArtifactError> {
+    // the hash table has 5 columns, whose commitments are written one by one
+    let positions = |vk: &VerifyingKey<G1Affine>, begin: usize| {
+        if begin + 5 > vk.cs().num_advice_columns() {
+            return Err(ArtifactError::BadCommitmentIndex(begin));
+        }
+        Ok((begin..begin + 5).collect::<Vec<_>>())
+    };
+    let mpt_commitments = read_commitments(mpt_proof, &positions(mpt_vk, indexs.0)?, transcript)?;
+    let hash_commitments =
+        read_commitments(hash_proof, &positions(hash_vk, indexs.1)?, transcript)?;
+
+    match mpt_commitments
+        .iter()
+        .zip(&hash_commitments)
+        .position(|(a, b)| a != b)
+    {
+        Some(column) => Err(ArtifactError::HashTableMismatch { column }),
+        None => Ok(()),
+    }
+}
+
+impl MptProofArtifact {
+    /// create the proofs of circuits with their proving keys, [params] can be of any k
+    /// no less than the circuits
+    pub fn prove<const LITE: bool>(
+        params: &ParamsKZG<Bn256>,
+        (mpt_pk, hash_pk): (&ProvingKey<G1Affine>, &ProvingKey<G1Affine>),
+        (mpt_circuit, hash_circuit): (EthTrieCircuit<Fr, LITE>, HashCircuit<Fr>),
+        transcript: TranscriptKind,
+        mut rng: impl RngCore,
+    ) -> Result<Self, ArtifactError> {
+        let mpt_k = mpt_pk.get_vk().get_domain().k();
+        let hash_k = hash_pk.get_vk().get_domain().k();
+        let indexs = if LITE {
+            CommitmentIndexs::new::<Fr>()
+        } else {
+            CommitmentIndexs::new_full_circuit::<Fr>()
+        };
+        let calcs = mpt_circuit.calcs;
+        let instance = mpt_circuit.instance().remove(0);
+
+        let mpt_proof = create(
+            &downsize_for(params, mpt_k)?,
+            mpt_pk,
+            mpt_circuit,
+            &instance,
+            transcript,
+            &mut rng,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "mpt",
+            source,
+        })?;
+        let hash_proof = create(
+            &downsize_for(params, hash_k)?,
+            hash_pk,
+            hash_circuit,
+            &[],
+            transcript,
+            &mut rng,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "hash",
+            source,
+        })?;
+
+        Ok(Self {
+            version: ARTIFACT_VERSION,
+            mpt_k,
+            hash_k,
+            calcs,
+            lite: LITE,
+            combined: false,
+            randomness: if LITE {
+                TableRandomness::None
+            } else {
+                TableRandomness::Challenge
+            },
+            transcript,
+            mpt_proof,
+            hash_proof,
+            instances: instance.into_iter().map(to_hash).collect(),
+            hash_tbl_begin: indexs.0,
+            hash_tbl_begin_at_hash_circuit: indexs.1,
+            mpt_tbl_begin: indexs.2,
+            mpt_vk_fingerprint: vk_fingerprint(mpt_pk.get_vk()),
+            hash_vk_fingerprint: vk_fingerprint(hash_pk.get_vk()),
+        })
+    }
+
+    /// create the proof of combined circuit with its proving key, [params] can be of any k
+    /// no less than the circuit
+    pub fn prove_combined(
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+        circuit: CombinedCircuit<Fr>,
+        transcript: TranscriptKind,
+        rng: impl RngCore,
+    ) -> Result<Self, ArtifactError> {
+        let k = pk.get_vk().get_domain().k();
+        let calcs = circuit.mpt_circuit().calcs;
+        let instance = circuit.instance().remove(0);
+
+        let proof = create(
+            &downsize_for(params, k)?,
+            pk,
+            circuit,
+            &instance,
+            transcript,
+            rng,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "combined",
+            source,
+        })?;
+
+        Ok(Self {
+            version: ARTIFACT_VERSION,
+            mpt_k: k,
+            hash_k: 0,
+            calcs,
+            lite: false,
+            combined: true,
+            randomness: TableRandomness::Challenge,
+            transcript,
+            mpt_proof: proof,
+            hash_proof: Vec::new(),
+            instances: instance.into_iter().map(to_hash).collect(),
+            hash_tbl_begin: 0,
+            hash_tbl_begin_at_hash_circuit: 0,
+            mpt_tbl_begin: None,
+            mpt_vk_fingerprint: vk_fingerprint(pk.get_vk()),
+            hash_vk_fingerprint: Hash::default(),
+        })
+    }
+
+    /// the mode the artifact is proven under
+    pub fn mode(&self) -> CircuitMode {
+        if self.combined {
+            CircuitMode::Combined
+        } else {
+            CircuitMode::Split
+        }
+    }
+
+    /// the public inputs of mpt circuit
+    pub fn instance(&self) -> Result<Vec<Fr>, ArtifactError> {
+        self.instances
+            .iter()
+            .map(|h| {
+                Option::<Fr>::from(Fr::from_bytes(&h.0)).ok_or(ArtifactError::NonCanonical(*h))
+            })
+            .collect()
+    }
+
+    /// verify the proofs with the vks of mpt and hash circuit, [params] can be of any k
+    /// no less than the circuits. the proofs must commit to the same hash table, at the
+    /// indexes of circuit under the mode of artifact
+    pub fn verify(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        (mpt_vk, hash_vk): (&VerifyingKey<G1Affine>, &VerifyingKey<G1Affine>),
+    ) -> Result<(), ArtifactError> {
+        if self.version != ARTIFACT_VERSION {
+            return Err(ArtifactError::Version(self.version));
+        }
+        if self.combined {
+            return Err(ArtifactError::Mode(CircuitMode::Combined));
+        }
+        for (circuit, vk, expected) in [
+            ("mpt", mpt_vk, self.mpt_vk_fingerprint),
+            ("hash", hash_vk, self.hash_vk_fingerprint),
+        ] {
+            let actual = vk_fingerprint(vk);
+            if actual != expected {
+                return Err(ArtifactError::VkMismatch {
+                    circuit,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        // the indexes come from the circuit, the recorded ones are only cross checked
+        let indexs = if self.lite {
+            CommitmentIndexs::new::<Fr>()
+        } else {
+            CommitmentIndexs::new_full_circuit::<Fr>()
+        };
+        let expected = (indexs.0, indexs.1, indexs.2);
+        let stored = (
+            self.hash_tbl_begin,
+            self.hash_tbl_begin_at_hash_circuit,
+            self.mpt_tbl_begin,
+        );
+        if stored != expected {
+            return Err(ArtifactError::IndexMismatch { expected, stored });
+        }
+        check_hash_table_commitments(
+            (&self.mpt_proof, mpt_vk),
+            (&self.hash_proof, hash_vk),
+            &indexs,
+            self.transcript,
+        )?;
+
+        let instance = self.instance()?;
+        check(
+            &downsize_for(params, self.mpt_k)?,
+            mpt_vk,
+            &self.mpt_proof,
+            &instance,
+            self.transcript,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "mpt",
+            source,
+        })?;
+        check(
+            &downsize_for(params, self.hash_k)?,
+            hash_vk,
+            &self.hash_proof,
+            &[],
+            self.transcript,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "hash",
+            source,
+        })
+    }
+
+    /// verify the proof of combined circuit with its vk, [params] can be of any k no less
+    /// than the circuit
+    pub fn verify_combined(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        vk: &VerifyingKey<G1Affine>,
+    ) -> Result<(), ArtifactError> {
+        if self.version != ARTIFACT_VERSION {
+            return Err(ArtifactError::Version(self.version));
+        }
+        if !self.combined {
+            return Err(ArtifactError::Mode(CircuitMode::Split));
+        }
+        let actual = vk_fingerprint(vk);
+        if actual != self.mpt_vk_fingerprint {
+            return Err(ArtifactError::VkMismatch {
+                circuit: "combined",
+                expected: self.mpt_vk_fingerprint,
+                actual,
+            });
+        }
+
+        let instance = self.instance()?;
+        check(
+            &downsize_for(params, self.mpt_k)?,
+            vk,
+            &self.mpt_proof,
+            &instance,
+            self.transcript,
+        )
+        .map_err(|source| ArtifactError::Proof {
+            circuit: "combined",
+            source,
+        })
+    }
+
+    /// write the binary form
+    pub fn write(&self, mut writer: impl Write) -> Result<(), ArtifactError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.mpt_k.to_le_bytes())?;
+        writer.write_all(&self.hash_k.to_le_bytes())?;
+        for v in [
+            self.calcs,
+            self.hash_tbl_begin,
+            self.hash_tbl_begin_at_hash_circuit,
+        ] {
+            writer.write_all(&(v as u64).to_le_bytes())?;
+        }
+        writer.write_all(&[
+            self.lite as u8,
+            self.randomness as u8,
+            self.transcript as u8,
+            self.mpt_tbl_begin.is_some() as u8,
+            self.combined as u8,
+        ])?;
+        writer.write_all(&(self.mpt_tbl_begin.unwrap_or_default() as u64).to_le_bytes())?;
+        writer.write_all(&self.mpt_vk_fingerprint.0)?;
+        writer.write_all(&self.hash_vk_fingerprint.0)?;
+
+        writer.write_all(&(self.instances.len() as u32).to_le_bytes())?;
+        for h in &self.instances {
+            writer.write_all(&h.0)?;
+        }
+        for proof in [&self.mpt_proof, &self.hash_proof] {
+            writer.write_all(&(proof.len() as u32).to_le_bytes())?;
+            writer.write_all(proof)?;
+        }
+        Ok(())
+    }
+
+    /// read the binary form
+    pub fn read(mut reader: impl Read) -> Result<Self, ArtifactError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ArtifactError::BadMagic);
+        }
+        let version = read_u32(&mut reader)?;
+        if version != ARTIFACT_VERSION {
+            return Err(ArtifactError::Version(version));
+        }
+        let mpt_k = read_u32(&mut reader)?;
+        let hash_k = read_u32(&mut reader)?;
+        let [calcs, hash_tbl_begin, hash_tbl_begin_at_hash_circuit] =
+            [(); 3].map(|_| read_u64(&mut reader).map(|v| v as usize));
+        let mut flags = [0u8; 5];
+        reader.read_exact(&mut flags)?;
+        let mpt_tbl_begin = read_u64(&mut reader)? as usize;
+        let mpt_vk_fingerprint = read_hash(&mut reader)?;
+        let hash_vk_fingerprint = read_hash(&mut reader)?;
+
+        let instances = (0..read_u32(&mut reader)?)
+            .map(|_| read_hash(&mut reader))
+            .collect::<Result<_, _>>()?;
+        let mpt_proof = read_vec(&mut reader)?;
+        let hash_proof = read_vec(&mut reader)?;
+
+        let invalid = |field| io::Error::new(io::ErrorKind::InvalidData, format!("bad {field}"));
+        Ok(Self {
+            version,
+            mpt_k,
+            hash_k,
+            calcs: calcs?,
+            lite: flags[0] != 0,
+            combined: flags[4] != 0,
+            randomness: match flags[1] {
+                0 => TableRandomness::None,
+                1 => TableRandomness::Challenge,
+                _ => return Err(invalid("randomness").into()),
+            },
+            transcript: match flags[2] {
+                0 => TranscriptKind::Blake2b,
+                1 => TranscriptKind::Poseidon,
+                _ => return Err(invalid("transcript").into()),
+            },
+            mpt_proof,
+            hash_proof,
+            instances,
+            hash_tbl_begin: hash_tbl_begin?,
+            hash_tbl_begin_at_hash_circuit: hash_tbl_begin_at_hash_circuit?,
+            mpt_tbl_begin: (flags[3] != 0).then_some(mpt_tbl_begin),
+            mpt_vk_fingerprint,
+            hash_vk_fingerprint,
+        })
+    }
+
+    /// save into [path], in json if its extension is `json` and the binary form otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ArtifactError> {
+        let writer = io::BufWriter::new(std::fs::File::create(path.as_ref())?);
+        if is_json(path.as_ref()) {
+            serde_json::to_writer_pretty(writer, self)?;
+            Ok(())
+        } else {
+            self.write(writer)
+        }
+    }
+
+    /// load from [path], in json if its extension is `json` and the binary form otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArtifactError> {
+        let reader = io::BufReader::new(std::fs::File::open(path.as_ref())?);
+        if is_json(path.as_ref()) {
+            let artifact: Self = serde_json::from_reader(reader)?;
+            if artifact.version != ARTIFACT_VERSION {
+                return Err(ArtifactError::Version(artifact.version));
+            }
+            Ok(artifact)
+        } else {
+            Self::read(reader)
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "json")
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_hash(reader: &mut impl Read) -> io::Result<Hash> {
+    let mut hash = Hash::default();
+    reader.read_exact(&mut hash.0)?;
+    Ok(hash)
+}
+
+// the length is untrusted, so only what the input really holds is allocated
+fn read_vec(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)? as u64;
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+// proofs are encoded as "0x..." in json
+mod hex_vec {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s.strip_prefix("0x").unwrap_or(&s)).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_artifact() -> MptProofArtifact {
+        MptProofArtifact {
+            version: ARTIFACT_VERSION,
+            mpt_k: 10,
+            hash_k: 12,
+            calcs: 200,
+            lite: false,
+            combined: false,
+            randomness: TableRandomness::Challenge,
+            transcript: TranscriptKind::Poseidon,
+            mpt_proof: vec![1, 2, 3, 4],
+            hash_proof: vec![5, 6],
+            instances: vec![HexBytes([1; 32]), HexBytes([2; 32])],
+            hash_tbl_begin: 3,
+            hash_tbl_begin_at_hash_circuit: 0,
+            mpt_tbl_begin: Some(42),
+            mpt_vk_fingerprint: HexBytes([7; 32]),
+            hash_vk_fingerprint: HexBytes([8; 32]),
+        }
+    }
+
+    #[test]
+    fn artifact_forms() {
+        let artifact = mock_artifact();
+
+        let json = serde_json::to_string(&artifact).unwrap();
+        assert!(json.contains("\"mptProof\":\"0x01020304\""));
+        assert_eq!(
+            serde_json::from_str::<MptProofArtifact>(&json).unwrap(),
+            artifact
+        );
+
+        let mut bin = Vec::new();
+        artifact.write(&mut bin).unwrap();
+        assert!(bin.len() < json.len());
+        assert_eq!(MptProofArtifact::read(bin.as_slice()).unwrap(), artifact);
+
+        let lite = MptProofArtifact {
+            lite: true,
+            randomness: TableRandomness::None,
+            mpt_tbl_begin: None,
+            ..artifact
+        };
+        let mut bin = Vec::new();
+        lite.write(&mut bin).unwrap();
+        assert_eq!(MptProofArtifact::read(bin.as_slice()).unwrap(), lite);
+
+        let combined = MptProofArtifact {
+            hash_k: 0,
+            combined: true,
+            hash_proof: vec![],
+            hash_tbl_begin: 0,
+            mpt_tbl_begin: None,
+            hash_vk_fingerprint: HexBytes::default(),
+            ..mock_artifact()
+        };
+        let mut combined_bin = Vec::new();
+        combined.write(&mut combined_bin).unwrap();
+        assert_eq!(
+            MptProofArtifact::read(combined_bin.as_slice()).unwrap(),
+            combined
+        );
+        // a json artifact written before the combined mode is a split one
+        let json = serde_json::to_string(&mock_artifact())
+            .unwrap()
+            .replace("\"combined\":false,", "");
+        assert!(!json.contains("combined"));
+        assert_eq!(
+            serde_json::from_str::<MptProofArtifact>(&json).unwrap(),
+            mock_artifact()
+        );
+
+        // other versions are rejected
+        bin[4] += 1;
+        assert!(matches!(
+            MptProofArtifact::read(bin.as_slice()),
+            Err(ArtifactError::Version(2))
+        ));
+        bin[0] = 0;
+        assert!(matches!(
+            MptProofArtifact::read(bin.as_slice()),
+            Err(ArtifactError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn artifact_truncated_proof() {
+        let mut bin = Vec::new();
+        mock_artifact().write(&mut bin).unwrap();
+        // claim a huge hash proof which the input does not hold
+        let hash_proof_len = bin.len() - 2 - 4;
+        bin[hash_proof_len..hash_proof_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            MptProofArtifact::read(bin.as_slice()),
+            Err(ArtifactError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+}
@@ -0,0 +1,503 @@
+//! Command line prover for the mpt circuit and its accompanied hash circuit, or the circuit
+//! combining both of them
+use halo2_mpt_circuits::{
+    artifact::TranscriptKind,
+    keystore::{CircuitShape, KeyStore},
+    operation::{ops_from_traces, to_hash},
+    serde::{HexBytes, SMTTrace},
+    CircuitMode, CircuitPair, CircuitSize, CombinedCircuit, EthTrie, EthTrieCircuit, Hash,
+    HashCircuit, MptProofArtifact, CAPACITY_TIERS,
+};
+use halo2_proofs::{
+    dev::MockProver,
+    halo2curves::bn256::{Bn256, Fr as Fp, G1Affine},
+    plonk::ProvingKey,
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+};
+use rand::rngs::OsRng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const USAGE: &str = "\
+usage: mpt-prover <command> [options] [trace files]
+
+commands:
+  mock    <traces>...                               mock-check the circuits for traces
+  setup   --k <k> --params <file>                   generate params (unsafe, for testing only)
+  keygen  --params <file> --keys <dir>              generate and cache proving and verifying keys
+  prove   --params <file> --keys <dir> --out <dir> <traces>...
+                                                    write the proof artifacts of traces into <dir>
+  verify  --params <file> --keys <dir> --proofs <dir>
+                                                    verify the proof artifacts in <dir>
+
+options:
+  --capacity <rows>     rows of each pair of circuits, default to the largest capacity tier
+  --transcript <kind>   transcript of proofs, blake2b (default) or poseidon
+  --segments            let a trace file whose roots do not follow the previous one start a
+                        new segment, instead of failing
+  --combined            check, generate keys for or prove the combined circuit, instead of
+                        the pair of mpt and hash circuit
+
+a trace file is either an array of SMTTrace or a block result with `mptwitness`, the
+operations beyond the capacity are split into chunks, each proven by a pair of circuits,
+or by one combined circuit under --combined. verify accepts the artifacts of both modes";
+
+// the options without value
+const FLAGS: [&str; 2] = ["segments", "combined"];
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Deserialize)]
+struct BlockResult {
+    #[serde(rename = "mptwitness", default)]
+    mpt_witness: Vec<SMTTrace>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TraceFile {
+    Traces(Vec<SMTTrace>),
+    Block(BlockResult),
+}
+
+// the positional arguments and `--name value` options
+struct Args {
+    files: Vec<PathBuf>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut files = Vec::new();
+        let mut options = HashMap::new();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = if FLAGS.contains(&name) {
+                    String::new()
+                } else {
+                    args.next().ok_or(format!("missing value for --{name}"))?
+                };
+                options.insert(name.to_string(), value);
+            } else {
+                files.push(PathBuf::from(arg));
+            }
+        }
+        Ok(Self { files, options })
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        self.options
+            .get(name)
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("--{name} is required").into())
+    }
+
+    fn mode(&self) -> CircuitMode {
+        if self.flag("combined") {
+            CircuitMode::Combined
+        } else {
+            CircuitMode::Split
+        }
+    }
+
+    fn capacity(&self) -> Result<usize> {
+        match self.options.get("capacity") {
+            Some(v) => Ok(v.parse()?),
+            None => Ok(*CAPACITY_TIERS.last().expect("not empty")),
+        }
+    }
+
+    fn traces(&self) -> Result<&[PathBuf]> {
+        if self.files.is_empty() {
+            Err("no trace file".into())
+        } else {
+            Ok(&self.files)
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_default();
+    let ret = Args::parse(args).and_then(|args| match command.as_str() {
+        "mock" => mock(&args),
+        "setup" => setup(&args),
+        "keygen" => keygen(&args),
+        "prove" => prove(&args),
+        "verify" => verify(&args),
+        "" | "help" | "--help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown command {command}").into()),
+    });
+
+    if let Err(e) = ret {
+        eprintln!("error: {e}\n\n{USAGE}");
+        std::process::exit(1);
+    }
+}
+
+// load the operations of trace files, the file whose roots do not follow the previous
+// one is rejected, or starts a new segment if [segments] is set
+fn load_trie(files: &[PathBuf], segments: bool) -> Result<EthTrie<Fp>> {
+    let mut trie = EthTrie::<Fp>::default();
+    for file in files {
+        let traces = match serde_json::from_reader(BufReader::new(File::open(file)?))? {
+            TraceFile::Traces(traces) => traces,
+            TraceFile::Block(block) => block.mpt_witness,
+        };
+        let ops = ops_from_traces::<Fp>(&traces)
+            .map_err(|e| format!("invalid trace in {}: {e}", file.display()))?;
+        if let Some(op) = ops.first() {
+            if !trie.get_ops().is_empty() && trie.final_root() != op.account_root_before() {
+                if !segments {
+                    return Err(format!(
+                        "{} starts from root {}, not the final root {} of previous traces, \
+                         pass --segments to prove it as a new segment",
+                        file.display(),
+                        to_hash(op.account_root_before()),
+                        to_hash(trie.final_root()),
+                    )
+                    .into());
+                }
+                trie.start_segment();
+            }
+        }
+        println!("{}: {} operations", file.display(), ops.len());
+        trie.add_ops(ops);
+    }
+    Ok(trie)
+}
+
+// split the operations into chunks of circuits under capacity, with the row usage
+fn load_chunks(args: &Args, capacity: usize) -> Result<Vec<CircuitPair<Fp>>> {
+    let timer = Instant::now();
+    let trie = load_trie(args.traces()?, args.flag("segments"))?;
+    let (rows, hash_rows) = trie.use_rows();
+    let required = trie.circuit_size();
+    let (chunks, manifest) = trie.split(capacity, &[])?;
+    println!(
+        "{} operations use {rows} rows and {hash_rows} hash rows, require {} rows (k = {}, \
+         hash k = {}), split into {} chunks of capacity {capacity} in {:?}",
+        trie.get_ops().len(),
+        required.calcs,
+        required.mpt_k,
+        required.hash_k,
+        chunks.len(),
+        timer.elapsed(),
+    );
+    for (index, chunk) in manifest.iter().enumerate() {
+        println!(
+            "chunk {index}: operations {:?}, roots {} -> {}",
+            chunk.ops,
+            to_hash(chunk.start_root),
+            to_hash(chunk.final_root),
+        );
+    }
+    Ok(chunks)
+}
+
+fn mock(args: &Args) -> Result<()> {
+    let capacity = args.capacity()?;
+    let size = CircuitSize::new::<Fp>(capacity);
+    for (index, (mpt_circuit, hash_circuit)) in load_chunks(args, capacity)?.into_iter().enumerate()
+    {
+        let timer = Instant::now();
+        if args.mode() == CircuitMode::Combined {
+            let circuit = CombinedCircuit::from((mpt_circuit, hash_circuit));
+            MockProver::<Fp>::run(size.combined_k, &circuit, circuit.instance())?
+                .verify()
+                .map_err(|e| format!("combined circuit of chunk {index} fails: {e:?}"))?;
+            println!(
+                "chunk {index} passes mock checking in {:?}",
+                timer.elapsed()
+            );
+            continue;
+        }
+        MockProver::<Fp>::run(size.mpt_k, &mpt_circuit, mpt_circuit.instance())?
+            .verify()
+            .map_err(|e| format!("mpt circuit of chunk {index} fails: {e:?}"))?;
+        MockProver::<Fp>::run(size.hash_k, &hash_circuit, vec![])?
+            .verify()
+            .map_err(|e| format!("hash circuit of chunk {index} fails: {e:?}"))?;
+        println!(
+            "chunk {index} passes mock checking in {:?}",
+            timer.elapsed()
+        );
+    }
+    Ok(())
+}
+
+fn setup(args: &Args) -> Result<()> {
+    let k: u32 = args.options.get("k").ok_or("--k is required")?.parse()?;
+    let timer = Instant::now();
+    let params = ParamsKZG::<Bn256>::setup(k, OsRng);
+    params.write(&mut BufWriter::new(File::create(args.path("params")?)?))?;
+    println!("params of k = {k} generated in {:?}", timer.elapsed());
+    Ok(())
+}
+
+fn read_params(args: &Args) -> Result<ParamsKZG<Bn256>> {
+    let mut reader = BufReader::new(File::open(args.path("params")?)?);
+    Ok(ParamsKZG::<Bn256>::read(&mut reader)?)
+}
+
+// the proving keys of circuits under capacity, loaded from the key store or generated
+fn proving_keys(
+    args: &Args,
+    params: &ParamsKZG<Bn256>,
+    capacity: usize,
+) -> Result<(ProvingKey<G1Affine>, ProvingKey<G1Affine>)> {
+    let size = CircuitSize::new::<Fp>(capacity);
+    let store = KeyStore::new(args.path("keys")?);
+    let timer = Instant::now();
+    let (mpt_circuit, hash_circuit) =
+        EthTrie::<Fp>::default().to_circuits((capacity, None), &[])?;
+    let mpt_pk = store.get_or_generate(
+        params,
+        &CircuitShape::mpt::<false>(capacity, size.mpt_k),
+        &mpt_circuit,
+    )?;
+    let hash_pk = store.get_or_generate(
+        params,
+        &CircuitShape::hash(capacity, size.hash_k),
+        &hash_circuit,
+    )?;
+    println!("keys of capacity {capacity} ready in {:?}", timer.elapsed());
+    Ok((mpt_pk, hash_pk))
+}
+
+// the proving key of combined circuit under capacity, loaded from the key store or generated
+fn combined_proving_key(
+    args: &Args,
+    params: &ParamsKZG<Bn256>,
+    capacity: usize,
+) -> Result<ProvingKey<G1Affine>> {
+    let size = CircuitSize::new::<Fp>(capacity);
+    let store = KeyStore::new(args.path("keys")?);
+    let timer = Instant::now();
+    let circuit =
+        CombinedCircuit::from(EthTrie::<Fp>::default().to_circuits((capacity, None), &[])?);
+    let pk = store.get_or_generate(
+        params,
+        &CircuitShape::combined(capacity, size.combined_k),
+        &circuit,
+    )?;
+    println!(
+        "combined key of capacity {capacity} ready in {:?}",
+        timer.elapsed()
+    );
+    Ok(pk)
+}
+
+fn keygen(args: &Args) -> Result<()> {
+    let params = read_params(args)?;
+    match args.mode() {
+        CircuitMode::Split => {
+            proving_keys(args, &params, args.capacity()?)?;
+        }
+        CircuitMode::Combined => {
+            combined_proving_key(args, &params, args.capacity()?)?;
+        }
+    }
+    Ok(())
+}
+
+// if the instances, i.e. (start root, final root, count, (kind, root)...), end in an
+// ordinary checkpoint (kind 0) at the final root
+fn ends_in_checkpoint(instances: &[Hash]) -> bool {
+    match instances {
+        [_, final_root, _, .., kind, root] => *kind == HexBytes([0; 32]) && root == final_root,
+        _ => false,
+    }
+}
+
+// if the instances start with a segment break (kind 1) at the start root
+fn starts_segment(instances: &[Hash]) -> bool {
+    match instances {
+        [start_root, _, _, kind, root, ..] => *kind == to_hash(Fp::from(1)) && root == start_root,
+        _ => false,
+    }
+}
+
+// if a chunk follows the previous one, i.e. it starts from the final root of previous
+// chunk, or it starts a segment while the previous chunk ends in a checkpoint
+fn follows(prev: &[Hash], instances: &[Hash]) -> bool {
+    match (prev, instances) {
+        ([_, final_root, ..], [start_root, ..]) if final_root == start_root => true,
+        _ => ends_in_checkpoint(prev) && starts_segment(instances),
+    }
+}
+
+fn artifact_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("chunk-{index}.artifact"))
+}
+
+fn prove(args: &Args) -> Result<()> {
+    let capacity = args.capacity()?;
+    let transcript = match args.options.get("transcript").map(String::as_str) {
+        None | Some("blake2b") => TranscriptKind::Blake2b,
+        Some("poseidon") => TranscriptKind::Poseidon,
+        Some(other) => return Err(format!("unknown transcript {other}").into()),
+    };
+    let params = read_params(args)?;
+    let out = args.path("out")?;
+    std::fs::create_dir_all(&out)?;
+
+    match args.mode() {
+        CircuitMode::Split => {
+            let (mpt_pk, hash_pk) = proving_keys(args, &params, capacity)?;
+            for (index, circuits) in load_chunks(args, capacity)?.into_iter().enumerate() {
+                let timer = Instant::now();
+                let artifact = MptProofArtifact::prove(
+                    &params,
+                    (&mpt_pk, &hash_pk),
+                    circuits,
+                    transcript,
+                    OsRng,
+                )?;
+                artifact.save(artifact_path(&out, index))?;
+                println!("chunk {index} proven in {:?}", timer.elapsed());
+            }
+        }
+        CircuitMode::Combined => {
+            let pk = combined_proving_key(args, &params, capacity)?;
+            for (index, circuits) in load_chunks(args, capacity)?.into_iter().enumerate() {
+                let timer = Instant::now();
+                let artifact = MptProofArtifact::prove_combined(
+                    &params,
+                    &pk,
+                    circuits.into(),
+                    transcript,
+                    OsRng,
+                )?;
+                artifact.save(artifact_path(&out, index))?;
+                println!(
+                    "chunk {index} proven by combined circuit in {:?}",
+                    timer.elapsed()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<()> {
+    let params = read_params(args)?;
+    let store = KeyStore::new(args.path("keys")?);
+    let proofs = args.path("proofs")?;
+
+    // the instances of previous chunk
+    let mut prev: Option<Vec<Hash>> = None;
+    let mut index = 0;
+    while artifact_path(&proofs, index).exists() {
+        let timer = Instant::now();
+        let artifact = MptProofArtifact::load(artifact_path(&proofs, index))?;
+        if artifact.lite {
+            return Err(format!("chunk {index} is proven by lite circuit").into());
+        }
+        let verified = match artifact.mode() {
+            CircuitMode::Split => {
+                let mpt_shape = CircuitShape::mpt::<false>(artifact.calcs, artifact.mpt_k);
+                let hash_shape = CircuitShape::hash(artifact.calcs, artifact.hash_k);
+                let mpt_vk = store
+                    .load_vk::<EthTrieCircuit<Fp, false>>(&mpt_shape)?
+                    .ok_or_else(|| format!("no keys for {}", mpt_shape.key()))?;
+                let hash_vk = store
+                    .load_vk::<HashCircuit<Fp>>(&hash_shape)?
+                    .ok_or_else(|| format!("no keys for {}", hash_shape.key()))?;
+                artifact.verify(&params, (&mpt_vk, &hash_vk))
+            }
+            CircuitMode::Combined => {
+                let shape = CircuitShape::combined(artifact.calcs, artifact.mpt_k);
+                let vk = store
+                    .load_vk::<CombinedCircuit<Fp>>(&shape)?
+                    .ok_or_else(|| format!("no keys for {}", shape.key()))?;
+                artifact.verify_combined(&params, &vk)
+            }
+        };
+        verified.map_err(|e| format!("chunk {index} is invalid: {e}"))?;
+
+        let (start_root, final_root) = match artifact.instances.as_slice() {
+            [start_root, final_root, ..] => (*start_root, *final_root),
+            _ => return Err(format!("chunk {index} has no roots in its instances").into()),
+        };
+        // a chunk can only break the root chain by a segment, which is exposed at both
+        // sides of the break
+        if let Some(prev) = &prev {
+            if !follows(prev, &artifact.instances) {
+                return Err(format!(
+                    "chunk {index} starts from root {start_root}, not the final root \
+                     {} of chunk {}",
+                    prev[1],
+                    index - 1
+                )
+                .into());
+            }
+        }
+        prev = Some(artifact.instances.clone());
+
+        println!(
+            "chunk {index} verified in {:?}, roots {start_root} -> {final_root}",
+            timer.elapsed(),
+        );
+        index += 1;
+    }
+
+    if index == 0 {
+        return Err(format!("no proof in {}", proofs.display()).into());
+    }
+    println!("{index} chunks verified");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // the instances of a chunk from [start] to [end] root, with its (kind, root) checkpoints
+    fn instances(start: u8, end: u8, checkpoints: &[(u64, u8)]) -> Vec<Hash> {
+        let mut instances = vec![HexBytes([start; 32]), HexBytes([end; 32])];
+        if !checkpoints.is_empty() {
+            instances.push(to_hash(Fp::from(checkpoints.len() as u64)));
+            for (kind, root) in checkpoints {
+                instances.extend([to_hash(Fp::from(*kind)), HexBytes([*root; 32])]);
+            }
+        }
+        instances
+    }
+
+    #[test]
+    fn chunks_follow() {
+        assert!(follows(&instances(1, 2, &[]), &instances(2, 3, &[])));
+        assert!(follows(
+            &instances(1, 2, &[(0, 2)]),
+            &instances(3, 4, &[(1, 3)])
+        ));
+
+        // two unrelated chunks
+        assert!(!follows(&instances(1, 2, &[]), &instances(3, 4, &[])));
+        // a checkpoint ending previous chunk does not break the chain alone
+        assert!(!follows(&instances(1, 2, &[(0, 2)]), &instances(3, 4, &[])));
+        assert!(!follows(
+            &instances(1, 2, &[(0, 2)]),
+            &instances(3, 4, &[(0, 4)])
+        ));
+        // nor a segment without the checkpoint before it
+        assert!(!follows(&instances(1, 2, &[]), &instances(3, 4, &[(1, 3)])));
+        // the segment must be exposed at the start root
+        assert!(!follows(
+            &instances(1, 2, &[(0, 2)]),
+            &instances(3, 4, &[(1, 5)])
+        ));
+    }
+}
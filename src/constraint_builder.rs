@@ -1,4 +1,7 @@
-use halo2_proofs::{arithmetic::FieldExt, plonk::ConstraintSystem};
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    plonk::{ConstraintSystem, SecondPhase},
+};
 
 mod binary_column;
 mod binary_query;
@@ -7,7 +10,7 @@ mod query;
 
 pub use binary_column::BinaryColumn;
 pub use binary_query::BinaryQuery;
-pub use column::{AdviceColumn, FixedColumn, SelectorColumn};
+pub use column::{AdviceColumn, FixedColumn, SecondPhaseAdviceColumn, SelectorColumn};
 pub use query::Query;
 
 pub struct ConstraintBuilder<F: FieldExt> {
@@ -61,13 +64,23 @@ impl<F: FieldExt> ConstraintBuilder<F> {
         self.conditions.pop().unwrap();
     }
 
+    // the input is gated by the current conditions, so it becomes all zeros on the rows
+    // they are off and the table must contain the all zero row
     pub fn add_lookup<const N: usize>(
         &mut self,
         name: &'static str,
         left: [Query<F>; N],
         right: [Query<F>; N],
     ) {
-        let lookup = left.into_iter().zip(right.into_iter()).collect();
+        let condition = self
+            .conditions
+            .iter()
+            .fold(BinaryQuery::one(), |a, b| a.clone().and(b.clone()));
+        let lookup = left
+            .into_iter()
+            .map(|q| condition.clone().condition(q))
+            .zip(right.into_iter())
+            .collect();
         self.lookups.push((name, lookup))
     }
 
@@ -88,6 +101,13 @@ impl<F: FieldExt> ConstraintBuilder<F> {
         [0; N].map(|_| AdviceColumn(cs.advice_column()))
     }
 
+    pub fn second_phase_advice_columns<const N: usize>(
+        &self,
+        cs: &mut ConstraintSystem<F>,
+    ) -> [SecondPhaseAdviceColumn; N] {
+        [0; N].map(|_| SecondPhaseAdviceColumn(cs.advice_column_in(SecondPhase)))
+    }
+
     pub fn binary_columns<const N: usize>(
         &mut self,
         cs: &mut ConstraintSystem<F>,
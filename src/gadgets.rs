@@ -1,8 +1,8 @@
-mod byte_bit;
-mod byte_representation;
-mod canonical_representation;
+pub(crate) mod byte_bit;
+pub(crate) mod byte_representation;
+pub(crate) mod canonical_representation;
 mod is_zero;
-mod key_bit;
-mod mpt_update;
+pub(crate) mod key_bit;
+pub(crate) mod mpt_update;
 mod one_hot;
-mod poseidon;
+pub(crate) mod poseidon;
@@ -1,9 +1,13 @@
 use super::{byte_bit::RangeCheck256Lookup, is_zero::IsZeroGadget};
 use crate::constraint_builder::{
-    AdviceColumn, ConstraintBuilder, FixedColumn, Query, SelectorColumn,
+    AdviceColumn, ConstraintBuilder, Query, SecondPhaseAdviceColumn, SelectorColumn,
 };
 use ethers_core::types::{Address, H256, U256};
-use halo2_proofs::{arithmetic::FieldExt, circuit::Region, plonk::ConstraintSystem};
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    circuit::{Region, Value},
+    plonk::{Challenge, ConstraintSystem},
+};
 
 pub trait RlcLookup {
     fn lookup<F: FieldExt>(&self) -> [Query<F>; 2];
@@ -17,11 +21,11 @@ pub trait BytesLookup {
 pub struct ByteRepresentationConfig {
     selector: SelectorColumn, // always enabled selector for constraints we want always enabled.
 
-    randomness: FixedColumn, // TODO: this should be an instance column.
+    randomness: Challenge, // second phase challenge the rlc is taken over.
 
     // lookup columns
     value: AdviceColumn,
-    rlc: AdviceColumn,
+    rlc: SecondPhaseAdviceColumn,
     index: AdviceColumn,
 
     // internal columns
@@ -46,8 +50,10 @@ impl ByteRepresentationConfig {
         cs: &mut ConstraintSystem<F>,
         cb: &mut ConstraintBuilder<F>,
         range_check: &impl RangeCheck256Lookup,
+        randomness: Challenge,
     ) -> Self {
-        let ([selector], [randomness], [value, rlc, index, byte]) = cb.build_columns(cs);
+        let ([selector], [], [value, index, byte]) = cb.build_columns(cs);
+        let [rlc] = cb.second_phase_advice_columns(cs);
         let index_is_zero = IsZeroGadget::configure(cs, cb, selector.current(), index);
 
         cb.add_constraint(
@@ -56,15 +62,15 @@ impl ByteRepresentationConfig {
             index.current() * (index.current() - index.previous() - 1),
         );
         cb.add_constraint(
-            "current value = previous value * 8 * (index == 0) + byte",
+            "current value = previous value * 256 * (index != 0) + byte",
             selector.current(),
-            value.current() - (value.previous() * 8 * !index_is_zero.current() + byte.current()),
+            value.current() - (value.previous() * 256 * !index_is_zero.current() + byte.current()),
         );
         cb.add_constraint(
-            "current rlc = previous rlc * randomness * (index == 0) + byte",
+            "current rlc = previous rlc * randomness * (index != 0) + byte",
             selector.current(),
             rlc.current()
-                - (rlc.previous() * randomness.current() * !index_is_zero.current()
+                - (rlc.previous() * Query::Challenge(randomness) * !index_is_zero.current()
                     + byte.current()),
         );
         cb.add_lookup("0 <= byte < 256", [byte.current()], range_check.lookup());
@@ -80,32 +86,32 @@ impl ByteRepresentationConfig {
         }
     }
 
-    fn assign<F: FieldExt>(
+    pub fn assign<F: FieldExt>(
         &self,
         region: &mut Region<'_, F>,
+        u64s: &[u64],
         addresses: &[Address],
         hashes: &[H256],
         words: &[U256],
+        randomness: Value<F>,
     ) {
-        let randomness = F::from(123123u64); // TODOOOOOOO
-
-        let byte_representations = addresses
+        let byte_representations = u64s
             .iter()
-            .map(address_to_big_endian)
+            .map(u64_to_big_endian)
+            .chain(addresses.iter().map(address_to_big_endian))
             .chain(hashes.iter().map(h256_to_big_endian))
             .chain(words.iter().map(u256_to_big_endian));
 
         let mut offset = 0;
         for byte_representation in byte_representations {
             let mut value = F::zero();
-            let mut rlc = F::zero();
+            let mut rlc = Value::known(F::zero());
             for (index, byte) in byte_representation.iter().enumerate() {
                 let byte = F::from(u64::from(*byte));
-                value = value * F::from(8) + byte;
-                rlc = rlc * randomness + byte;
+                value = value * F::from(256) + byte;
+                rlc = rlc * randomness + Value::known(byte);
 
                 self.selector.enable(region, offset);
-                self.randomness.assign(region, offset, randomness);
                 self.value.assign(region, offset, value);
                 self.rlc.assign(region, offset, rlc);
                 self.byte.assign(region, offset, byte);
@@ -120,6 +126,10 @@ impl ByteRepresentationConfig {
     }
 }
 
+fn u64_to_big_endian(x: &u64) -> Vec<u8> {
+    x.to_be_bytes().to_vec()
+}
+
 fn address_to_big_endian(x: &Address) -> Vec<u8> {
     x.0.to_vec()
 }
@@ -141,11 +151,12 @@ mod test {
         circuit::{Layouter, SimpleFloorPlanner},
         dev::MockProver,
         halo2curves::bn256::Fr,
-        plonk::{Circuit, Error},
+        plonk::{Circuit, Error, FirstPhase},
     };
 
     #[derive(Clone, Default, Debug)]
     struct TestCircuit {
+        u64s: Vec<u64>,
         addresses: Vec<Address>,
         hashes: Vec<H256>,
         words: Vec<U256>,
@@ -162,7 +173,9 @@ mod test {
         fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
             let mut cb = ConstraintBuilder::new();
             let byte_bit = ByteBitGadget::configure(cs, &mut cb);
-            let byte_representation = ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit);
+            let randomness = cs.challenge_usable_after(FirstPhase);
+            let byte_representation =
+                ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit, randomness);
             cb.build(cs);
             (byte_bit, byte_representation)
         }
@@ -172,13 +185,19 @@ mod test {
             config: Self::Config,
             mut layouter: impl Layouter<Fr>,
         ) -> Result<(), Error> {
+            let randomness = layouter.get_challenge(config.1.randomness);
             layouter.assign_region(
                 || "",
                 |mut region| {
                     config.0.assign(&mut region);
-                    config
-                        .1
-                        .assign(&mut region, &self.addresses, &self.hashes, &self.words);
+                    config.1.assign(
+                        &mut region,
+                        &self.u64s,
+                        &self.addresses,
+                        &self.hashes,
+                        &self.words,
+                        randomness,
+                    );
                     Ok(())
                 },
             )
@@ -188,6 +207,7 @@ mod test {
     #[test]
     fn test_byte_representation() {
         let circuit = TestCircuit {
+            u64s: vec![u64::MAX, 1 << 32],
             addresses: vec![Address::repeat_byte(34)],
             hashes: vec![H256::repeat_byte(48)],
             words: vec![U256::zero(), U256::from(123412123)],
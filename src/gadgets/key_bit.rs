@@ -2,8 +2,9 @@ use super::byte_bit::{ByteBitLookup, RangeCheck256Lookup, RangeCheck8Lookup};
 use super::canonical_representation::CanonicalRepresentationLookup;
 use crate::constraint_builder::ConstraintBuilder;
 use crate::constraint_builder::{AdviceColumn, Query, SelectorColumn};
-use halo2_proofs::arithmetic::FieldExt;
-use halo2_proofs::plonk::ConstraintSystem;
+use halo2_proofs::{
+    arithmetic::FieldExt, circuit::Region, halo2curves::bn256::Fr, plonk::ConstraintSystem,
+};
 
 pub trait KeyBitLookup {
     fn lookup<F: FieldExt>(&self) -> [Query<F>; 3];
@@ -52,11 +53,19 @@ impl KeyBitConfig {
             [index_mod_8.current()],
             range_check_8.lookup(),
         );
-        cb.add_lookup(
-            "byte is correct",
-            [value.current(), index_div_8.current(), byte.current()],
-            representation.lookup(),
-        );
+        cb.condition(selector.current(), |cb| {
+            cb.add_lookup(
+                "byte is correct",
+                // index_div_8 counts bytes from the least significant end, while the canonical
+                // representation lists them from the most significant end.
+                [
+                    value.current(),
+                    Query::from(31) - index_div_8.current(),
+                    byte.current(),
+                ],
+                representation.lookup(),
+            );
+        });
         cb.add_lookup(
             "bit is correct",
             [byte.current(), index_mod_8.current(), bit.current()],
@@ -65,7 +74,7 @@ impl KeyBitConfig {
         cb.add_constraint(
             "index = index_div_8 * 8 + index_mod_8",
             selector.current(),
-            index.current() - index_div_8.current() * 8 + index_mod_8.current(),
+            index.current() - (index_div_8.current() * 8 + index_mod_8.current()),
         );
 
         Self {
@@ -79,63 +88,27 @@ impl KeyBitConfig {
         }
     }
 
-    // fn synthesize(
-    //     &self,
-    //     config: Self::Config,
-    //     mut layouter: impl Layouter<Fr>,
-    // ) -> Result<(), Error> {
-    //     layouter.assign_region(
-    //         || "",
-    //         // |mut region| {
-    //         //     for offset in 0..256 {
-    //         //         config
-    //         //             .byte_lookup
-    //         //             .assign(&mut region, offset, u64::try_from(offset).unwrap());
-    //         //     }
-    //         //     let mut offset = 0;
-    //         //     for value in &self.values {
-    //         //         let mut bytes = value.to_bytes();
-    //         //         bytes.reverse();
-    //         //         let mut differences_are_zero_so_far = true;
-    //         //         for (index, (byte, modulus_byte)) in
-    //         //             bytes.iter().zip_eq(&modulus_bytes).enumerate()
-    //         //         {
-    //         //             config.selector.enable(&mut region, offset);
-    //         //             config.byte.assign(&mut region, offset, u64::from(*byte));
-    //         //             config
-    //         //                 .modulus_byte
-    //         //                 .assign(&mut region, offset, u64::from(*modulus_byte));
-
-    //         //             config
-    //         //                 .index
-    //         //                 .assign(&mut region, offset, u64::try_from(index).unwrap());
-    //         //             if index.is_zero() {
-    //         //                 config.index_is_zero.enable(&mut region, offset);
-    //         //             }
-
-    //         //             let difference =
-    //         //                 Fr::from(u64::from(*modulus_byte)) - Fr::from(u64::from(*byte));
-    //         //             config.difference.assign(&mut region, offset, difference);
-    //         //             config
-    //         //                 .difference_is_zero
-    //         //                 .assign(&mut region, offset, difference);
+    pub fn assign(&self, region: &mut Region<'_, Fr>, lookups: &[(Fr, usize, bool)]) {
+        for (offset, (value, index, bit)) in lookups.iter().enumerate() {
+            let mut bytes = value.to_bytes();
+            bytes.reverse();
+            let index_div_8 = index / 8;
+            let index_mod_8 = index % 8;
+            let byte = bytes[31 - index_div_8];
+            debug_assert_eq!(*bit, byte & (1 << index_mod_8) != 0);
 
-    //         //             config.differences_are_zero_so_far.assign(
-    //         //                 &mut region,
-    //         //                 offset,
-    //         //                 differences_are_zero_so_far,
-    //         //             );
-    //         //             differences_are_zero_so_far &= difference.is_zero_vartime();
-
-    //         //             config.value.assign(&mut region, offset, *value);
-
-    //         //             offset += 1
-    //         //         }
-    //         //     }
-    //         //     Ok(())
-    //         // },
-    //     )
-    // }
+            self.selector.enable(region, offset);
+            self.value.assign(region, offset, *value);
+            self.index
+                .assign(region, offset, u64::try_from(*index).unwrap());
+            self.bit.assign(region, offset, *bit);
+            self.index_div_8
+                .assign(region, offset, u64::try_from(index_div_8).unwrap());
+            self.index_mod_8
+                .assign(region, offset, u64::try_from(index_mod_8).unwrap());
+            self.byte.assign(region, offset, u64::from(byte));
+        }
+    }
 }
 
 impl KeyBitLookup for KeyBitConfig {
@@ -5,15 +5,17 @@ use super::{
     poseidon::PoseidonLookup,
 };
 use crate::{
-    constraint_builder::{AdviceColumn, ConstraintBuilder, Query, SelectorColumn},
-    serde::SMTTrace,
-    types::Proof,
-    MPTProofType,
+    constraint_builder::{AdviceColumn, BinaryQuery, ConstraintBuilder, Query, SelectorColumn},
+    types::{account_key, address_halves, LeafNode, Proof},
+    Hashable, MPTProofType,
 };
 use ethers_core::k256::elliptic_curve::PrimeField;
 use ethers_core::types::Address;
 use halo2_proofs::{
-    arithmetic::FieldExt, circuit::Region, halo2curves::bn256::Fr, plonk::ConstraintSystem,
+    arithmetic::{Field, FieldExt},
+    circuit::Region,
+    halo2curves::bn256::Fr,
+    plonk::ConstraintSystem,
 };
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -45,7 +47,7 @@ pub trait MptUpdateLookup {
 }
 
 #[derive(Clone)]
-struct MptUpdateConfig {
+pub struct MptUpdateConfig {
     selector: SelectorColumn,
 
     old_hash: AdviceColumn, // when depth = 0 and is_account_path, old_hash = old_root
@@ -59,6 +61,7 @@ struct MptUpdateConfig {
     proof_type: OneHot<MPTProofType>,
 
     address: AdviceColumn,
+    address_high: AdviceColumn, // the first 16 bytes of the address, hashed into its path key
     storage_key_rlc: AdviceColumn,
 
     segment_type: OneHot<SegmentType>,
@@ -69,6 +72,10 @@ struct MptUpdateConfig {
     direction: AdviceColumn, // this actually must be binary because of a KeyBitLookup
 
     sibling: AdviceColumn,
+
+    // The key of the leaf a path ends in when its own key is not in the trie.
+    other_key: AdviceColumn,
+    other_key_difference_inverse: AdviceColumn,
 }
 
 impl MptUpdateLookup for MptUpdateConfig {
@@ -101,7 +108,7 @@ impl MptUpdateLookup for MptUpdateConfig {
 }
 
 impl MptUpdateConfig {
-    fn configure<F: FieldExt>(
+    pub fn configure<F: FieldExt>(
         cs: &mut ConstraintSystem<F>,
         cb: &mut ConstraintBuilder<F>,
         poseidon: &impl PoseidonLookup,
@@ -112,26 +119,26 @@ impl MptUpdateConfig {
         let ([selector], [], [old_hash, new_hash]) = cb.build_columns(cs);
 
         let proof_type = OneHot::configure(cs, cb);
-        let [address, storage_key_rlc] = cb.advice_columns(cs);
+        let [address, address_high, storage_key_rlc] = cb.advice_columns(cs);
 
         let [old_value_rlc, new_value_rlc] = cb.advice_columns(cs);
 
         let [depth, proof_key, path_key, direction, sibling] = cb.advice_columns(cs);
+        let [other_key, other_key_difference_inverse] = cb.advice_columns(cs);
 
         let segment_type = OneHot::configure(cs, cb);
         let path_type = OneHot::configure(cs, cb);
 
-        cb.add_lookup(
-            "direction is correct for key and depth",
-            [path_key.current(), depth.current(), direction.current()],
-            key_bit.lookup(),
-        );
-
-        cb.add_lookup(
-            "direction = key.bit(depth)",
-            [path_key.current(), depth.current(), direction.current()],
-            key_bit.lookup(),
-        );
+        let is_trie = segment_type
+            .matches(SegmentType::AccountTrie)
+            .or(segment_type.matches(SegmentType::StorageTrie));
+        cb.condition(is_trie, |cb| {
+            cb.add_lookup(
+                "direction = key.bit(depth - 1)",
+                [path_key.current(), depth.current() - 1, direction.current()],
+                key_bit.lookup(),
+            );
+        });
 
         let config = Self {
             selector,
@@ -142,6 +149,7 @@ impl MptUpdateConfig {
             old_value_rlc,
             new_value_rlc,
             address,
+            address_high,
             storage_key_rlc,
             segment_type,
             path_type,
@@ -149,28 +157,125 @@ impl MptUpdateConfig {
             depth,
             direction,
             sibling,
+            other_key,
+            other_key_difference_inverse,
         };
 
-        for variant in PathType::iter() {
+        // The account path key is H(address_high, address_low << 96), where address_low is the
+        // last 4 bytes of the address.
+        cb.condition(config.segment_type.matches(SegmentType::Start), |cb| {
+            let address_low =
+                config.address.current() - config.address_high.current() * Query::from(1 << 32);
+            cb.add_lookup(
+                "address is 20 bytes",
+                [config.address.current(), Query::from(19)],
+                bytes.lookup(),
+            );
+            cb.add_lookup(
+                "address_high is 16 bytes",
+                [config.address_high.current(), Query::from(15)],
+                bytes.lookup(),
+            );
+            cb.add_lookup(
+                "address_low is 4 bytes",
+                [address_low.clone(), Query::from(3)],
+                bytes.lookup(),
+            );
+            cb.add_lookup(
+                "path key = poseidon(address_high, address_low << 96)",
+                [
+                    config.address_high.current(),
+                    address_low * Query::Constant(two_to_the_96::<F>()),
+                    config.path_key.current(),
+                ],
+                poseidon.lookup(),
+            );
+        });
+
+        // The hash on the Start row is the root, which has no parent to be checked against.
+        cb.condition(!config.segment_type.matches(SegmentType::Start), |cb| {
+            cb.add_constraint(
+                "proof type is unchanged within an update",
+                config.selector.current(),
+                config.proof_type.current() - config.proof_type.previous(),
+            );
+            for variant in PathType::iter() {
+                let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+                    PathType::Common => configure_common_path(cb, &config, poseidon),
+                    PathType::ExtensionOld => configure_extension_old(cb, &config, poseidon),
+                    PathType::ExtensionNew => configure_extension_new(cb, &config, poseidon),
+                };
+                cb.condition(config.path_type.matches(variant), conditional_constraints);
+            }
+        });
+
+        // The key bit lookup for the direction reads the depth and the path key, so they are
+        // constrained for every proof type.
+        for variant in SegmentType::iter() {
             let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
-                PathType::Common => configure_common_path(cb, &config, poseidon),
-                PathType::ExtensionOld => configure_extension_old(cb, &config, poseidon),
-                PathType::ExtensionNew => configure_extension_new(cb, &config, poseidon),
+                SegmentType::AccountTrie | SegmentType::StorageTrie => {
+                    cb.add_constraint(
+                        "depth increased by 1",
+                        config.selector.current(),
+                        config.depth.delta() - Query::one(),
+                    );
+                }
+                _ => cb.add_constraint(
+                    "depth is 0",
+                    config.selector.current(),
+                    config.depth.current(),
+                ),
             };
-            cb.condition(config.path_type.matches(variant), conditional_constraints);
+            cb.condition(
+                config.segment_type.matches(variant),
+                conditional_constraints,
+            );
+        }
+        for (trie, leaf) in [
+            (SegmentType::AccountTrie, SegmentType::AccountLeaf0),
+            (SegmentType::StorageTrie, SegmentType::StorageLeaf0),
+        ] {
+            let continues_path = config
+                .segment_type
+                .matches(trie)
+                .or(config.segment_type.matches(leaf))
+                .and(config.segment_type.previous_matches(trie));
+            cb.condition(continues_path, |cb| {
+                cb.add_constraint(
+                    "path key is unchanged along the path",
+                    config.selector.current(),
+                    config.path_key.delta(),
+                );
+            });
         }
 
+        // The account path key is carried over from the Start row, which binds it to the address.
+        let starts_account_path = config
+            .segment_type
+            .matches(SegmentType::AccountTrie)
+            .or(config.segment_type.matches(SegmentType::AccountLeaf0))
+            .and(config.segment_type.previous_matches(SegmentType::Start));
+        cb.condition(starts_account_path, |cb| {
+            cb.add_constraint(
+                "path key is the account key of the Start row",
+                config.selector.current(),
+                config.path_key.delta(),
+            );
+        });
+
         for variant in MPTProofType::iter() {
             let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
-                MPTProofType::NonceChanged => configure_nonce(cb, &config, bytes),
-                MPTProofType::BalanceChanged => configure_balance(cb, &config),
-                MPTProofType::CodeHashExists => configure_code_hash(cb, &config),
-                MPTProofType::AccountDoesNotExist => configure_empty_account(cb, &config),
-                MPTProofType::AccountDestructed => configure_self_destruct(cb, &config),
-                MPTProofType::StorageChanged => configure_storage(cb, &config),
-                MPTProofType::StorageDoesNotExist => configure_empty_storage(cb, &config),
-                MPTProofType::PoseidonCodeHashExists => todo!(),
-                MPTProofType::CodeSizeExists => todo!(),
+                MPTProofType::NonceChanged => configure_nonce(cb, &config, poseidon, bytes),
+                MPTProofType::BalanceChanged => configure_balance(cb, &config, poseidon),
+                MPTProofType::CodeHashExists => configure_code_hash(cb, &config, poseidon),
+                MPTProofType::AccountDoesNotExist => configure_empty_account(cb, &config, poseidon),
+                MPTProofType::AccountDestructed => configure_self_destruct(cb, &config, poseidon),
+                MPTProofType::StorageChanged => configure_storage(cb, &config, poseidon),
+                MPTProofType::StorageDoesNotExist => configure_empty_storage(cb, &config, poseidon),
+                MPTProofType::PoseidonCodeHashExists => {
+                    configure_poseidon_code_hash(cb, &config, poseidon)
+                }
+                MPTProofType::CodeSizeExists => configure_code_size(cb, &config, poseidon, bytes),
             };
             cb.condition(config.proof_type.matches(variant), conditional_constraints);
         }
@@ -178,68 +283,363 @@ impl MptUpdateConfig {
         config
     }
 
-    fn assign(&self, region: &mut Region<'_, Fr>, updates: &[SMTTrace]) {
-        let randomness = Fr::from(123123u64); // TODOOOOOOO
-
+    pub fn assign(&self, region: &mut Region<'_, Fr>, proofs: &[Proof]) -> usize {
         let mut offset = 0;
-        for update in updates {
-            let proof = Proof::from(update.clone());
+        for proof in proofs {
+            let path_key = account_key(proof.claim.address);
+
+            self.assign_row(
+                region,
+                offset,
+                proof,
+                path_key,
+                SegmentType::Start,
+                PathType::Common,
+            );
+            self.old_hash.assign(region, offset, proof.claim.old_root);
+            self.new_hash.assign(region, offset, proof.claim.new_root);
+            self.address_high
+                .assign(region, offset, address_halves(proof.claim.address).0);
+            offset += 1;
 
-            for (direction, old_hash, new_hash, sibling, is_padding_open, is_padding_close) in
-                &proof.address_hash_traces
+            offset = self.assign_trie_rows(
+                region,
+                offset,
+                proof,
+                path_key,
+                SegmentType::AccountTrie,
+                &proof.address_hash_traces,
+            );
+            offset =
+                self.assign_leaf_rows(region, offset, proof, path_key, &account_leaf_rows(proof));
+
+            if let (Some(storage_key), Some(storage_hash_traces)) =
+                (proof.storage_key, &proof.storage_hash_traces)
             {
-                self.selector.enable(region, offset);
-                self.address
-                    .assign(region, offset, address_to_fr(proof.claim.address));
-                // self.storage_key_rlc.assign(region, offset, rlc(proof.claim.storage_key, randomness));
-                // self.new_value_rlc.assign(region, offset, ...)
-                // self.old_value_rlc.assign(region, offset, ...)
-
-                // self.is_common_path.assign(
-                //     region,
-                //     offset,
-                //     !(*is_padding_open || *is_padding_close),
-                // );
-                self.segment_type
-                    .assign(region, offset, SegmentType::AccountTrie);
-
-                // let path_type = match (*is_padding_open, *is_padding_close) {
-                //     (false, false) => PathType::Common,
-                //     (false, true) => PathType::Old,
-                //     (true, false) => PathType::New,
-                //     (true, true) => unreachable!(),
-                // };
-                // self.path_type.assign(region, offset, path_type);
-
-                self.sibling.assign(region, offset, *sibling);
-                self.new_hash.assign(region, offset, *new_hash);
-                self.old_hash.assign(region, offset, *old_hash);
-                self.direction.assign(region, offset, *direction);
-
-                offset += 1;
+                offset = self.assign_trie_rows(
+                    region,
+                    offset,
+                    proof,
+                    storage_key,
+                    SegmentType::StorageTrie,
+                    storage_hash_traces,
+                );
+                offset = self.assign_leaf_rows(
+                    region,
+                    offset,
+                    proof,
+                    storage_key,
+                    &storage_leaf_rows(proof, storage_key),
+                );
             }
         }
+        offset
+    }
+
+    fn assign_row(
+        &self,
+        region: &mut Region<'_, Fr>,
+        offset: usize,
+        proof: &Proof,
+        path_key: Fr,
+        segment_type: SegmentType,
+        path_type: PathType,
+    ) {
+        self.selector.enable(region, offset);
+        self.proof_type
+            .assign(region, offset, proof.claim.proof_type());
+        self.address
+            .assign(region, offset, address_to_fr(proof.claim.address));
+        self.path_key.assign(region, offset, path_key);
+        self.segment_type.assign(region, offset, segment_type);
+        self.path_type.assign(region, offset, path_type);
+    }
+
+    // The hash traces run from the leaf up to the root, but rows run from the root down.
+    fn assign_trie_rows(
+        &self,
+        region: &mut Region<'_, Fr>,
+        mut offset: usize,
+        proof: &Proof,
+        path_key: Fr,
+        segment_type: SegmentType,
+        hash_traces: &[(bool, Fr, Fr, Fr, bool, bool)],
+    ) -> usize {
+        for (depth, (direction, old_hash, new_hash, sibling, is_padding_open, is_padding_close)) in
+            hash_traces.iter().rev().enumerate()
+        {
+            let path_type = match (*is_padding_open, *is_padding_close) {
+                (false, false) => PathType::Common,
+                (false, true) => PathType::ExtensionOld,
+                (true, false) => PathType::ExtensionNew,
+                (true, true) => unreachable!("rejected when converting the trace"),
+            };
+            self.assign_row(region, offset, proof, path_key, segment_type, path_type);
+            self.depth
+                .assign(region, offset, u64::try_from(depth + 1).unwrap());
+            self.direction.assign(region, offset, *direction);
+            self.sibling.assign(region, offset, *sibling);
+            self.old_hash.assign(region, offset, *old_hash);
+            self.new_hash.assign(region, offset, *new_hash);
+            offset += 1;
+        }
+        offset
+    }
+
+    fn assign_leaf_rows(
+        &self,
+        region: &mut Region<'_, Fr>,
+        mut offset: usize,
+        proof: &Proof,
+        path_key: Fr,
+        rows: &[LeafRow],
+    ) -> usize {
+        let (old_value, new_value) = proof.claim.values();
+        for (i, row) in rows.iter().enumerate() {
+            self.assign_row(
+                region,
+                offset,
+                proof,
+                row.moved_key.unwrap_or(path_key),
+                row.segment_type,
+                row.path_type,
+            );
+            self.direction.assign(region, offset, row.direction);
+            self.sibling.assign(region, offset, row.sibling);
+            self.old_hash.assign(region, offset, row.old_hash);
+            self.new_hash.assign(region, offset, row.new_hash);
+            if let Some(other_key) = row.other_key {
+                self.other_key.assign(region, offset, other_key);
+                self.other_key_difference_inverse.assign(
+                    region,
+                    offset,
+                    Field::invert(&(path_key - other_key)).unwrap_or(Fr::zero()),
+                );
+            }
+
+            if i == rows.len() - 1 && row.moved_key.is_none() && row.other_key.is_none() {
+                self.old_value_rlc.assign(region, offset, old_value);
+                self.new_value_rlc.assign(region, offset, new_value);
+            }
+            offset += 1;
+        }
+        offset
+    }
+}
+
+/// A row below the trie rows, going from a leaf down to the field the proof is about.
+struct LeafRow {
+    segment_type: SegmentType,
+    path_type: PathType,
+    direction: bool,
+    old_hash: Fr,
+    new_hash: Fr,
+    sibling: Fr,
+    // The key of the leaf which moves up in place of a removed one. The row opens that leaf,
+    // so its path key is this key.
+    moved_key: Option<Fr>,
+    // The key of the leaf a path ends in when the path key is not in the trie.
+    other_key: Option<Fr>,
+}
+
+impl LeafRow {
+    fn new(
+        segment_type: SegmentType,
+        path_type: PathType,
+        (direction, old_hash, new_hash, sibling): (bool, Fr, Fr, Fr),
+    ) -> Self {
+        Self {
+            segment_type,
+            path_type,
+            direction,
+            old_hash,
+            new_hash,
+            sibling,
+            moved_key: None,
+            other_key: None,
+        }
+    }
+
+    // The row opening the leaf which is moved up to the end of the new path after a removal.
+    fn moved(segment_type: SegmentType, old_hash: Fr, leaf: LeafNode) -> Self {
+        Self {
+            moved_key: Some(leaf.key),
+            ..Self::new(
+                segment_type,
+                PathType::ExtensionNew,
+                (true, old_hash, leaf.value_hash, hash(Fr::one(), leaf.key)),
+            )
+        }
+    }
+
+    // The row opening the leaf of another key, which shows that the path key is not in the trie.
+    fn other(segment_type: SegmentType, leaf: LeafNode) -> Self {
+        Self {
+            other_key: Some(leaf.key),
+            ..Self::new(
+                segment_type,
+                PathType::Common,
+                (
+                    true,
+                    leaf.value_hash,
+                    leaf.value_hash,
+                    hash(Fr::one(), leaf.key),
+                ),
+            )
+        }
+    }
+}
+
+/// The rows below the account trie, going from the account leaf down to the field the proof
+/// is about.
+fn account_leaf_rows(proof: &Proof) -> Vec<LeafRow> {
+    let old = proof.old_account_hash_traces;
+    let new = proof.new_account_hash_traces;
+    // The hashes at the bottom of the account trie, which a path without the account leaf
+    // carries over: either an empty node or the leaf of another account.
+    let (old_bottom, new_bottom) = proof.address_hash_traces.first().map_or(
+        (proof.claim.old_root, proof.claim.new_root),
+        |(_, old_hash, new_hash, ..)| (*old_hash, *new_hash),
+    );
+    if proof.claim.proof_type() == MPTProofType::AccountDestructed {
+        let mut rows = vec![LeafRow::new(
+            SegmentType::AccountLeaf0,
+            PathType::ExtensionOld,
+            (true, old[6][1], new_bottom, old[6][0]),
+        )];
+        // The new path ends in the leaf of another account if it collapsed into the place of
+        // the deleted one.
+        if let Some(leaf) = proof.leafs[1] {
+            rows.push(LeafRow::moved(SegmentType::AccountLeaf1, old[6][1], leaf));
+        }
+        return rows;
+    }
+    // [left, right, hash] of the 5 hashes making up an account; see Account::trace.
+    let path_type = match (old[4] == [Fr::zero(); 3], new[4] == [Fr::zero(); 3]) {
+        (false, false) => PathType::Common,
+        // The account is created, so only the new path has the account leaf.
+        (true, false) => PathType::ExtensionNew,
+        // Neither path has the account leaf, so the path ends in an empty node or the leaf of
+        // another account.
+        (true, true) => {
+            return proof.leafs[0]
+                .map(|leaf| LeafRow::other(SegmentType::AccountLeaf0, leaf))
+                .into_iter()
+                .collect()
+        }
+        (false, true) => unreachable!("rejected when converting the trace"),
+    };
+    let siblings = if path_type == PathType::Common {
+        old
+    } else {
+        new
+    };
+    let old_hash = |i: usize, j: usize| {
+        if path_type == PathType::Common {
+            old[i][j]
+        } else {
+            old_bottom
+        }
+    };
+    let left = |i: usize| (false, old_hash(i, 0), new[i][0], siblings[i][1]);
+    let right = |i: usize| (true, old_hash(i, 1), new[i][1], siblings[i][0]);
+
+    let path = match proof.claim.proof_type() {
+        MPTProofType::NonceChanged | MPTProofType::CodeSizeExists => {
+            vec![left(4), left(3), left(2)]
+        }
+        MPTProofType::BalanceChanged => vec![left(4), left(3), right(2)],
+        MPTProofType::CodeHashExists => vec![left(4), right(3), right(1)],
+        MPTProofType::StorageChanged | MPTProofType::StorageDoesNotExist => {
+            vec![left(4), right(3), left(1)]
+        }
+        MPTProofType::PoseidonCodeHashExists => vec![right(4)],
+        MPTProofType::AccountDoesNotExist | MPTProofType::AccountDestructed => return vec![],
+    };
+
+    let segment_types = [
+        SegmentType::AccountLeaf0,
+        SegmentType::AccountLeaf1,
+        SegmentType::AccountLeaf2,
+        SegmentType::AccountLeaf3,
+    ];
+    // The account hash is the right child of the leaf node, with H(1, key) on the left.
+    std::iter::once(right(6))
+        .chain(path)
+        .zip(segment_types)
+        .map(|(row, segment_type)| LeafRow::new(segment_type, path_type, row))
+        .collect()
+}
+
+/// The rows below the storage trie. The storage leaf is hashed as H(H(1, key), value hash).
+fn storage_leaf_rows(proof: &Proof, key: Fr) -> Vec<LeafRow> {
+    let [old_leaf, new_leaf] = proof.storage_leafs;
+    let (old_bottom, new_bottom) = proof
+        .storage_hash_traces
+        .as_ref()
+        .and_then(|hash_traces| hash_traces.first())
+        .map_or(
+            (
+                proof.old_account_hash_traces[1][0],
+                proof.new_account_hash_traces[1][0],
+            ),
+            |(_, old_hash, new_hash, ..)| (*old_hash, *new_hash),
+        );
+    let sibling = hash(Fr::one(), key);
+    let is_key = |leaf: Option<LeafNode>| leaf.filter(|leaf| leaf.key == key);
+    match (is_key(old_leaf), is_key(new_leaf)) {
+        (Some(old), Some(new)) => vec![LeafRow::new(
+            SegmentType::StorageLeaf0,
+            PathType::Common,
+            (true, old.value_hash, new.value_hash, sibling),
+        )],
+        (Some(old), None) => {
+            let mut rows = vec![LeafRow::new(
+                SegmentType::StorageLeaf0,
+                PathType::ExtensionOld,
+                (true, old.value_hash, new_bottom, sibling),
+            )];
+            if let Some(leaf) = new_leaf {
+                rows.push(LeafRow::moved(
+                    SegmentType::StorageLeaf1,
+                    old.value_hash,
+                    leaf,
+                ));
+            }
+            rows
+        }
+        (None, Some(new)) => vec![LeafRow::new(
+            SegmentType::StorageLeaf0,
+            PathType::ExtensionNew,
+            (true, old_bottom, new.value_hash, sibling),
+        )],
+        // The key is not in the storage trie, which is then left unchanged.
+        (None, None) => vec![],
     }
 }
 
 fn old_left<F: FieldExt>(config: &MptUpdateConfig) -> Query<F> {
-    config.direction.current() * config.old_hash.previous()
-        + (Query::one() - config.direction.current()) * config.sibling.previous()
+    config.direction.current() * config.sibling.current()
+        + (Query::one() - config.direction.current()) * config.old_hash.current()
 }
 
 fn old_right<F: FieldExt>(config: &MptUpdateConfig) -> Query<F> {
-    config.direction.current() * config.sibling.previous()
-        + (Query::one() - config.direction.current()) * config.old_hash.previous()
+    config.direction.current() * config.old_hash.current()
+        + (Query::one() - config.direction.current()) * config.sibling.current()
 }
 
 fn new_left<F: FieldExt>(config: &MptUpdateConfig) -> Query<F> {
-    config.direction.current() * config.new_hash.previous()
-        + (Query::one() - config.direction.current()) * config.sibling.previous()
+    config.direction.current() * config.sibling.current()
+        + (Query::one() - config.direction.current()) * config.new_hash.current()
 }
 
 fn new_right<F: FieldExt>(config: &MptUpdateConfig) -> Query<F> {
-    config.direction.current() * config.sibling.previous()
-        + (Query::one() - config.direction.current()) * config.new_hash.previous()
+    config.direction.current() * config.new_hash.current()
+        + (Query::one() - config.direction.current()) * config.sibling.current()
+}
+
+fn hash(x: Fr, y: Fr) -> Fr {
+    Hashable::hash([x, y])
 }
 
 fn address_to_fr(a: Address) -> Fr {
@@ -259,7 +659,7 @@ fn configure_common_path<F: FieldExt>(
         [
             old_left(config),
             old_right(config),
-            config.old_hash.current(),
+            config.old_hash.previous(),
         ],
         poseidon.lookup(),
     );
@@ -268,7 +668,7 @@ fn configure_common_path<F: FieldExt>(
         [
             new_left(config),
             new_right(config),
-            config.new_hash.current(),
+            config.new_hash.previous(),
         ],
         poseidon.lookup(),
     );
@@ -284,20 +684,79 @@ fn configure_extension_old<F: FieldExt>(
         [
             old_left(config),
             old_right(config),
-            config.old_hash.current(),
+            config.old_hash.previous(),
         ],
         poseidon.lookup(),
     );
-    cb.add_constraint(
-        "sibling is zero for extension path",
-        config.selector.current(),
-        config.sibling.current(),
-    );
     cb.add_constraint(
         "new_hash unchanged for path_type=Old",
         config.selector.current(),
         config.new_hash.current() - config.new_hash.previous(),
     );
+    cb.condition(
+        config.segment_type.matches(SegmentType::AccountTrie),
+        |cb| {
+            cb.assert(
+                "only account deletion removes an account leaf",
+                config.selector.current(),
+                config.proof_type.matches(MPTProofType::AccountDestructed),
+            );
+        },
+    );
+    for (trie, leaf, moved_leaf) in [
+        (
+            SegmentType::AccountTrie,
+            SegmentType::AccountLeaf0,
+            SegmentType::AccountLeaf1,
+        ),
+        (
+            SegmentType::StorageTrie,
+            SegmentType::StorageLeaf0,
+            SegmentType::StorageLeaf1,
+        ),
+    ] {
+        cb.condition(config.segment_type.matches(trie), |cb| {
+            cb.condition(config.segment_type.next_matches(trie), |cb| {
+                cb.add_constraint(
+                    "sibling is zero for extension path",
+                    config.selector.current(),
+                    config.sibling.current(),
+                );
+            });
+            // The last sibling is the leaf which is moved up to where the extension begins.
+            cb.condition(!config.segment_type.next_matches(trie), |cb| {
+                cb.add_constraint(
+                    "sibling is new_hash at the end of extension path",
+                    config.selector.current(),
+                    config.sibling.current() - config.new_hash.current(),
+                );
+            });
+        });
+        // Without an extension, the removed leaf is replaced by an empty node. Otherwise it is
+        // replaced by the moved leaf, which the next row shows to be a leaf.
+        cb.condition(config.segment_type.matches(leaf), |cb| {
+            cb.condition(
+                !config.path_type.previous_matches(PathType::ExtensionOld),
+                |cb| {
+                    cb.add_constraint(
+                        "new_hash is zero for removed leaf",
+                        config.selector.current(),
+                        config.new_hash.current(),
+                    );
+                },
+            );
+            cb.condition(
+                config.path_type.previous_matches(PathType::ExtensionOld),
+                |cb| {
+                    cb.assert(
+                        "moved leaf is opened after removed leaf",
+                        config.selector.current(),
+                        config.segment_type.next_matches(moved_leaf),
+                    );
+                },
+            );
+        });
+    }
 }
 
 fn configure_extension_new<F: FieldExt>(
@@ -310,17 +769,47 @@ fn configure_extension_new<F: FieldExt>(
         config.selector.current(),
         config.old_hash.current() - config.old_hash.previous(),
     );
-    cb.add_constraint(
-        "sibling is zero for extension path",
-        config.selector.current(),
-        config.sibling.current(),
-    );
+    for (trie, leaf) in [
+        (SegmentType::AccountTrie, SegmentType::AccountLeaf0),
+        (SegmentType::StorageTrie, SegmentType::StorageLeaf0),
+    ] {
+        cb.condition(config.segment_type.matches(trie), |cb| {
+            cb.condition(config.segment_type.next_matches(trie), |cb| {
+                cb.add_constraint(
+                    "sibling is zero for extension path",
+                    config.selector.current(),
+                    config.sibling.current(),
+                );
+            });
+            // The last sibling is the leaf which is moved down from where the extension begins.
+            cb.condition(!config.segment_type.next_matches(trie), |cb| {
+                cb.add_constraint(
+                    "sibling is old_hash at the end of extension path",
+                    config.selector.current(),
+                    config.sibling.current() - config.old_hash.current(),
+                );
+            });
+        });
+        cb.condition(
+            config
+                .segment_type
+                .matches(leaf)
+                .and(!config.path_type.previous_matches(PathType::ExtensionNew)),
+            |cb| {
+                cb.add_constraint(
+                    "old_hash is zero for inserted leaf",
+                    config.selector.current(),
+                    config.old_hash.current(),
+                );
+            },
+        );
+    }
     cb.add_lookup(
         "poseidon hash correct for new path",
         [
             new_left(config),
             new_right(config),
-            config.new_hash.current(),
+            config.new_hash.previous(),
         ],
         poseidon.lookup(),
     );
@@ -329,219 +818,672 @@ fn configure_extension_new<F: FieldExt>(
 fn configure_nonce<F: FieldExt>(
     cb: &mut ConstraintBuilder<F>,
     config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
     bytes: &impl BytesLookup,
 ) {
     for variant in SegmentType::iter() {
         let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
-            SegmentType::Start => {
-                cb.add_constraint(
-                    "depth is 0",
-                    config.selector.current(),
-                    config.depth.current(),
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
             }
-            SegmentType::AccountTrie => {
-                cb.assert(
-                    "previous is Start or AccountTrie",
-                    config.selector.current(),
-                    config
-                        .segment_type
-                        .previous_matches(SegmentType::Start)
-                        .or(config
-                            .segment_type
-                            .previous_matches(SegmentType::AccountTrie)),
+            SegmentType::AccountLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
+            }
+            SegmentType::AccountLeaf1 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf2]);
+            }
+            SegmentType::AccountLeaf2 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf3]);
+            }
+            SegmentType::AccountLeaf3 => {
+                assert_direction(cb, config, false);
+                cb.add_lookup(
+                    "new nonce is 8 bytes",
+                    [config.new_value_rlc.current(), Query::from(7)],
+                    bytes.lookup(),
                 );
+                cb.condition(config.path_type.matches(PathType::Common), |cb| {
+                    let old_code_size = (config.old_hash.current()
+                        - config.old_value_rlc.current())
+                        * Query::Constant(two_to_the_64::<F>().invert().unwrap());
+                    let new_code_size = (config.new_hash.current()
+                        - config.new_value_rlc.current())
+                        * Query::Constant(two_to_the_64::<F>().invert().unwrap());
+                    cb.add_constraint(
+                        "code size is unchanged",
+                        config.selector.current(),
+                        old_code_size.clone() - new_code_size,
+                    );
+                    cb.add_lookup(
+                        "old nonce is 8 bytes",
+                        [config.old_value_rlc.current(), Query::from(7)],
+                        bytes.lookup(),
+                    );
+                    cb.add_lookup(
+                        "code size is 8 bytes",
+                        [old_code_size, Query::from(7)],
+                        bytes.lookup(),
+                    );
+                });
+                // The account is created by setting its nonce, so it has no code yet.
+                cb.condition(config.path_type.matches(PathType::ExtensionNew), |cb| {
+                    cb.add_constraint(
+                        "old nonce is 0 for new account",
+                        config.selector.current(),
+                        config.old_value_rlc.current(),
+                    );
+                    cb.add_constraint(
+                        "code size is 0 for new account",
+                        config.selector.current(),
+                        config.new_hash.current() - config.new_value_rlc.current(),
+                    );
+                });
                 cb.assert(
-                    "next is AccountTrie or AccountLeaf0",
+                    "account leaf is not removed by nonce update",
                     config.selector.current(),
-                    config
-                        .segment_type
-                        .next_matches(SegmentType::AccountTrie)
-                        .or(config.segment_type.matches(SegmentType::AccountLeaf0)),
+                    !config.path_type.matches(PathType::ExtensionOld),
                 );
-                cb.add_constraint(
-                    "depth increased by 1",
-                    config.selector.current(),
-                    config.depth.delta() - Query::one(),
+            }
+            SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for nonce update",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+fn configure_balance<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
             }
             SegmentType::AccountLeaf0 => {
-                cb.assert(
-                    "from Start or AccountTrie",
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
+            }
+            SegmentType::AccountLeaf1 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf2]);
+            }
+            SegmentType::AccountLeaf2 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf3]);
+            }
+            SegmentType::AccountLeaf3 => {
+                // The balance is the right child of H(nonce and code size, balance).
+                assert_direction(cb, config, true);
+                cb.add_constraint(
+                    "new_value = new balance",
                     config.selector.current(),
-                    config
-                        .segment_type
-                        .previous_matches(SegmentType::Start)
-                        .or(config
-                            .segment_type
-                            .previous_matches(SegmentType::AccountTrie)),
+                    config.new_value_rlc.current() - config.new_hash.current(),
                 );
+                cb.condition(config.path_type.matches(PathType::Common), |cb| {
+                    cb.add_constraint(
+                        "old_value = old balance",
+                        config.selector.current(),
+                        config.old_value_rlc.current() - config.old_hash.current(),
+                    );
+                });
+                // The account is created by setting its balance, so its nonce and code size
+                // are 0.
+                cb.condition(config.path_type.matches(PathType::ExtensionNew), |cb| {
+                    cb.add_constraint(
+                        "old balance is 0 for new account",
+                        config.selector.current(),
+                        config.old_value_rlc.current(),
+                    );
+                    cb.add_constraint(
+                        "nonce and code size are 0 for new account",
+                        config.selector.current(),
+                        config.sibling.current(),
+                    );
+                });
                 cb.assert(
-                    "next is AccountLeaf1",
+                    "account leaf is not removed by balance update",
                     config.selector.current(),
-                    config.segment_type.next_matches(SegmentType::AccountLeaf1),
+                    !config.path_type.matches(PathType::ExtensionOld),
                 );
-                cb.assert(
-                    "path_type is Common",
-                    config.selector.current(),
-                    config.path_type.matches(PathType::Common),
+            }
+            SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for balance update",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+// The code hash does not fit in a field element, so the value is H(code hash high, code hash
+// low), which is what the account commits to.
+fn configure_code_hash<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
+            }
+            SegmentType::AccountLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
+            }
+            SegmentType::AccountLeaf1 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf2]);
+            }
+            SegmentType::AccountLeaf2 => {
+                assert_direction(cb, config, true);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf3]);
+            }
+            SegmentType::AccountLeaf3 => {
+                // The code hash is the right child of H(storage root, code hash).
+                assert_direction(cb, config, true);
                 cb.add_constraint(
-                    "depth is 0",
+                    "new_value = new code hash",
                     config.selector.current(),
-                    config.depth.current(),
+                    config.new_value_rlc.current() - config.new_hash.current(),
                 );
-                cb.add_constraint(
-                    "direction is 0",
+                cb.condition(config.path_type.matches(PathType::Common), |cb| {
+                    cb.add_constraint(
+                        "old_value = old code hash",
+                        config.selector.current(),
+                        config.old_value_rlc.current() - config.old_hash.current(),
+                    );
+                });
+                cb.condition(config.path_type.matches(PathType::ExtensionNew), |cb| {
+                    cb.add_constraint(
+                        "old code hash is 0 for new account",
+                        config.selector.current(),
+                        config.old_value_rlc.current(),
+                    );
+                });
+                cb.assert(
+                    "account leaf is not removed by code hash update",
                     config.selector.current(),
-                    config.direction.current(),
+                    !config.path_type.matches(PathType::ExtensionOld),
+                );
+            }
+            SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for code hash update",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+fn configure_poseidon_code_hash<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    // A read neither creates nor removes the account leaf.
+    cb.assert(
+        "path_type is Common for poseidon code hash read",
+        config.selector.current(),
+        config.path_type.matches(PathType::Common),
+    );
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
-                // add constraints that sibling = old_path_key and new_path_key
+            }
+            SegmentType::AccountLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
             }
             SegmentType::AccountLeaf1 => {
-                cb.assert(
-                    "previous is AccountLeaf0",
+                // The poseidon code hash is the right child of the account hash.
+                assert_direction(cb, config, true);
+                cb.add_constraint(
+                    "old_value = poseidon code hash",
                     config.selector.current(),
-                    config
-                        .segment_type
-                        .previous_matches(SegmentType::AccountLeaf0),
+                    config.old_value_rlc.current() - config.old_hash.current(),
                 );
-                cb.assert(
-                    "next is AccountLeaf2",
+                cb.add_constraint(
+                    "new_value = poseidon code hash",
                     config.selector.current(),
-                    config.segment_type.next_matches(SegmentType::AccountLeaf2),
+                    config.new_value_rlc.current() - config.new_hash.current(),
                 );
-                cb.assert(
-                    "path_type is Common",
+                cb.add_constraint(
+                    "poseidon code hash is unchanged",
                     config.selector.current(),
-                    config.path_type.matches(PathType::Common),
+                    config.old_hash.current() - config.new_hash.current(),
                 );
+            }
+            SegmentType::AccountLeaf2
+            | SegmentType::AccountLeaf3
+            | SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for poseidon code hash proof",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+fn configure_code_size<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+    bytes: &impl BytesLookup,
+) {
+    // A read neither creates nor removes the account leaf.
+    cb.assert(
+        "path_type is Common for code size read",
+        config.selector.current(),
+        config.path_type.matches(PathType::Common),
+    );
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
+                );
+            }
+            SegmentType::AccountLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
+            }
+            SegmentType::AccountLeaf1 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf2]);
+            }
+            SegmentType::AccountLeaf2 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf3]);
+            }
+            SegmentType::AccountLeaf3 => {
+                // hash = code_size * 2^64 + nonce, with both code_size and nonce 8 bytes.
+                assert_direction(cb, config, false);
                 cb.add_constraint(
-                    "depth is 0",
+                    "code size is unchanged",
                     config.selector.current(),
-                    config.depth.current(),
+                    config.old_value_rlc.current() - config.new_value_rlc.current(),
                 );
                 cb.add_constraint(
-                    "direction is 0",
+                    "code size read does not change account",
                     config.selector.current(),
-                    config.direction.current(),
+                    config.old_hash.current() - config.new_hash.current(),
                 );
+                let nonce = config.old_hash.current()
+                    - config.old_value_rlc.current() * Query::Constant(two_to_the_64::<F>());
+                cb.add_lookup(
+                    "code size is 8 bytes",
+                    [config.old_value_rlc.current(), Query::from(7)],
+                    bytes.lookup(),
+                );
+                cb.add_lookup("nonce is 8 bytes", [nonce, Query::from(7)], bytes.lookup());
             }
-            SegmentType::AccountLeaf2 => {
-                cb.assert(
-                    "previous is AccountLeaf1",
-                    config.selector.current(),
-                    config
+            SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for code size proof",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+fn assert_next_segment<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    segment_types: &[SegmentType],
+) {
+    cb.assert(
+        "next segment type is valid",
+        config.selector.current(),
+        segment_types
+            .iter()
+            .fold(BinaryQuery::zero(), |acc, segment_type| {
+                acc.or(config.segment_type.next_matches(*segment_type))
+            }),
+    );
+}
+
+fn assert_direction<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    direction: bool,
+) {
+    cb.add_constraint(
+        "direction is fixed for account leaf segment",
+        config.selector.current(),
+        config.direction.current() - Query::from(u64::from(direction)),
+    );
+}
+
+fn two_to_the_64<F: FieldExt>() -> F {
+    F::from(1 << 32).square()
+}
+
+fn two_to_the_96<F: FieldExt>() -> F {
+    two_to_the_64::<F>() * F::from(1 << 32)
+}
+
+fn configure_empty_account<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    cb.add_constraint(
+        "hashes are unchanged when reading empty account",
+        config.selector.current(),
+        config.old_hash.current() - config.new_hash.current(),
+    );
+    cb.assert(
+        "path_type is Common for empty account",
+        config.selector.current(),
+        config.path_type.matches(PathType::Common),
+    );
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                // A path which ends without a leaf ends in an empty node.
+                cb.condition(
+                    !config
                         .segment_type
-                        .previous_matches(SegmentType::AccountLeaf1),
-                );
-                cb.assert(
-                    "next is AccountLeaf3",
-                    config.selector.current(),
-                    config.segment_type.next_matches(SegmentType::AccountLeaf3),
+                        .next_matches(SegmentType::AccountTrie)
+                        .or(config.segment_type.next_matches(SegmentType::AccountLeaf0)),
+                    |cb| {
+                        cb.add_constraint(
+                            "path without a leaf ends in an empty node",
+                            config.selector.current(),
+                            config.old_hash.current(),
+                        );
+                    },
                 );
-                cb.assert(
-                    "path_type is Common",
-                    config.selector.current(),
-                    config.path_type.matches(PathType::Common),
+            }
+            SegmentType::AccountLeaf0 => {
+                // Otherwise it ends in the leaf of another account.
+                assert_direction(cb, config, true);
+                cb.add_lookup(
+                    "sibling = poseidon(1, other key)",
+                    [
+                        Query::one(),
+                        config.other_key.current(),
+                        config.sibling.current(),
+                    ],
+                    poseidon.lookup(),
                 );
                 cb.add_constraint(
-                    "depth is 0",
+                    "other key != path key",
                     config.selector.current(),
-                    config.depth.current(),
+                    (config.path_key.current() - config.other_key.current())
+                        * config.other_key_difference_inverse.current()
+                        - Query::one(),
                 );
-                cb.add_constraint(
-                    "direction is 0",
-                    config.selector.current(),
-                    config.direction.current(),
+            }
+            SegmentType::AccountLeaf1
+            | SegmentType::AccountLeaf2
+            | SegmentType::AccountLeaf3
+            | SegmentType::AccountLeaf4
+            | SegmentType::StorageTrie
+            | SegmentType::StorageLeaf0
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for empty account",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+}
+
+fn configure_self_destruct<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
             }
-            SegmentType::AccountLeaf3 => {
+            SegmentType::AccountTrie => {
+                // Removing a leaf can only shorten the path to it.
                 cb.assert(
-                    "previous is AccountLeaf2",
+                    "new path is not extended when deleting account",
                     config.selector.current(),
-                    config
-                        .segment_type
-                        .previous_matches(SegmentType::AccountLeaf2),
+                    !config.path_type.matches(PathType::ExtensionNew),
                 );
-                cb.assert(
-                    "next is Start",
-                    config.selector.current(),
-                    config.segment_type.next_matches(SegmentType::Start),
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
                 );
+            }
+            SegmentType::AccountLeaf0 => {
+                // The old account leaf is opened, while new_hash stays at whatever replaced it:
+                // either an empty node or the sibling leaf collapsed into its place.
                 cb.assert(
-                    "path_type is Common",
-                    config.selector.current(),
-                    config.path_type.matches(PathType::Common),
-                );
-                cb.add_constraint(
-                    "depth is 0",
-                    config.selector.current(),
-                    config.depth.current(),
-                );
-                cb.add_constraint(
-                    "direction is 0",
+                    "account leaf only exists in old path",
                     config.selector.current(),
-                    config.direction.current(),
-                );
-
-                let code_size = (config.old_hash.current() - config.old_value_rlc.current())
-                    * Query::Constant(F::from(1 << 32).invert().unwrap());
-                cb.add_lookup(
-                    "old nonce is 8 bytes",
-                    [config.old_value_rlc.current(), Query::from(7)],
-                    bytes.lookup(),
-                );
-                cb.add_lookup(
-                    "old code size is 8 bytes",
-                    [code_size, Query::from(7)],
-                    bytes.lookup(),
-                );
-                cb.add_lookup(
-                    "hash input is 16 bytes",
-                    [config.old_hash.current(), Query::from(15)],
-                    bytes.lookup(),
+                    config.path_type.matches(PathType::ExtensionOld),
                 );
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
             }
-            SegmentType::AccountLeaf4
+            SegmentType::AccountLeaf1 => configure_moved_leaf(cb, config, poseidon),
+            SegmentType::AccountLeaf2
+            | SegmentType::AccountLeaf3
+            | SegmentType::AccountLeaf4
             | SegmentType::StorageTrie
             | SegmentType::StorageLeaf0
-            | SegmentType::StorageLeaf1 => cb.assert_unreachable("", config.selector.current()),
+            | SegmentType::StorageLeaf1 => cb.assert_unreachable(
+                "unreachable segment type for account deletion",
+                config.selector.current(),
+            ),
         };
         cb.condition(
             config.segment_type.matches(variant),
             conditional_constraints,
         );
     }
+}
 
+fn configure_storage<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    // The storage trie hangs off the storage root, which is the left child of the left child
+    // of the right child of the account hash.
+    for variant in SegmentType::iter() {
+        let conditional_constraints = |cb: &mut ConstraintBuilder<F>| match variant {
+            SegmentType::Start | SegmentType::AccountTrie => {
+                assert_next_segment(
+                    cb,
+                    config,
+                    &[SegmentType::AccountTrie, SegmentType::AccountLeaf0],
+                );
+            }
+            SegmentType::AccountLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf1]);
+            }
+            SegmentType::AccountLeaf1 => {
+                assert_direction(cb, config, false);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf2]);
+            }
+            SegmentType::AccountLeaf2 => {
+                assert_direction(cb, config, true);
+                assert_next_segment(cb, config, &[SegmentType::AccountLeaf3]);
+            }
+            SegmentType::AccountLeaf3 | SegmentType::StorageTrie => {
+                if variant == SegmentType::AccountLeaf3 {
+                    assert_direction(cb, config, false);
+                }
+                // A path which ends without a leaf for the key ends in an empty node or the
+                // leaf of another key, and the storage is only read.
+                cb.condition(
+                    !config
+                        .segment_type
+                        .next_matches(SegmentType::StorageTrie)
+                        .or(config.segment_type.next_matches(SegmentType::StorageLeaf0)),
+                    |cb| {
+                        cb.add_constraint(
+                            "storage is unchanged without a leaf for the key",
+                            config.selector.current(),
+                            config.old_hash.current() - config.new_hash.current(),
+                        );
+                    },
+                );
+            }
+            SegmentType::StorageLeaf0 => {
+                assert_direction(cb, config, true);
+                configure_leaf_key(cb, config, poseidon);
+            }
+            SegmentType::StorageLeaf1 => configure_moved_leaf(cb, config, poseidon),
+            SegmentType::AccountLeaf4 => cb.assert_unreachable(
+                "unreachable segment type for storage update",
+                config.selector.current(),
+            ),
+        };
+        cb.condition(
+            config.segment_type.matches(variant),
+            conditional_constraints,
+        );
+    }
+    // The account itself is neither created nor deleted by a storage update.
     cb.condition(
-        config.segment_type.matches(SegmentType::AccountTrie),
+        config
+            .segment_type
+            .matches(SegmentType::AccountTrie)
+            .or(config.segment_type.matches(SegmentType::AccountLeaf0))
+            .or(config.segment_type.matches(SegmentType::AccountLeaf1))
+            .or(config.segment_type.matches(SegmentType::AccountLeaf2))
+            .or(config.segment_type.matches(SegmentType::AccountLeaf3)),
         |cb| {
-            cb.add_constraint(
-                "0",
-                config
-                    .segment_type
-                    .previous_matches(SegmentType::Start)
-                    .or(config
-                        .segment_type
-                        .previous_matches(SegmentType::AccountTrie)),
-                Query::one(),
+            cb.assert(
+                "path_type is Common for account",
+                config.selector.current(),
+                config.path_type.matches(PathType::Common),
             );
         },
     );
 }
 
-fn configure_balance<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
-
-fn configure_code_hash<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
-
-fn configure_empty_account<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
-
-fn configure_self_destruct<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
+fn configure_empty_storage<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    configure_storage(cb, config, poseidon);
+    cb.add_constraint(
+        "hashes are unchanged when reading empty storage",
+        config.selector.current(),
+        config.old_hash.current() - config.new_hash.current(),
+    );
+}
 
-fn configure_storage<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
+// The leaf node is H(H(1, key), value hash), with the path key as its key.
+fn configure_leaf_key<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    cb.add_lookup(
+        "sibling = poseidon(1, path key)",
+        [
+            Query::one(),
+            config.path_key.current(),
+            config.sibling.current(),
+        ],
+        poseidon.lookup(),
+    );
+}
 
-fn configure_empty_storage<F: FieldExt>(cb: &mut ConstraintBuilder<F>, config: &MptUpdateConfig) {}
+// After a removal, the leaf which moved up is opened on the new path to show that it is a leaf.
+// Collapsing a branch node into the place of the removed leaf is not allowed.
+fn configure_moved_leaf<F: FieldExt>(
+    cb: &mut ConstraintBuilder<F>,
+    config: &MptUpdateConfig,
+    poseidon: &impl PoseidonLookup,
+) {
+    cb.assert(
+        "moved leaf only exists in new path",
+        config.selector.current(),
+        config.path_type.matches(PathType::ExtensionNew),
+    );
+    cb.assert(
+        "moved leaf follows removed leaf",
+        config.selector.current(),
+        config.path_type.previous_matches(PathType::ExtensionOld),
+    );
+    assert_direction(cb, config, true);
+    configure_leaf_key(cb, config, poseidon);
+}
 
 #[cfg(test)]
 mod test {
@@ -555,12 +1497,12 @@ mod test {
         circuit::{Layouter, SimpleFloorPlanner},
         dev::MockProver,
         halo2curves::bn256::Fr,
-        plonk::{Circuit, Error},
+        plonk::{Circuit, Error, FirstPhase},
     };
 
     #[derive(Clone, Debug)]
     struct TestCircuit {
-        updates: Vec<SMTTrace>,
+        proofs: Vec<Proof>,
     }
 
     impl TestCircuit {
@@ -585,14 +1527,14 @@ mod test {
         type FloorPlanner = SimpleFloorPlanner;
 
         fn without_witnesses(&self) -> Self {
-            Self { updates: vec![] }
+            Self { proofs: vec![] }
         }
 
         fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
             let mut cb = ConstraintBuilder::new();
             let poseidon = PoseidonConfig::configure(cs, &mut cb);
             let byte_bit = ByteBitGadget::configure(cs, &mut cb);
-            let byte_representation = ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit);
+            let randomness = cs.challenge_usable_after(FirstPhase);
             let canonical_representation =
                 CanonicalRepresentationConfig::configure(cs, &mut cb, &byte_bit);
             let key_bit = KeyBitConfig::configure(
@@ -604,7 +1546,8 @@ mod test {
                 &byte_bit,
             );
 
-            let byte_representation = ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit);
+            let byte_representation =
+                ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit, randomness);
 
             let mpt_update = MptUpdateConfig::configure(
                 cs,
@@ -643,7 +1586,7 @@ mod test {
             layouter.assign_region(
                 || "",
                 |mut region| {
-                    mpt_update.assign(&mut region, &self.updates);
+                    mpt_update.assign(&mut region, &self.proofs);
                     poseidon.assign(&mut region, &self.hash_traces());
                     canonical_representation.assign(&mut region, &self.keys());
                     // key_bit.assign(region, &[]); // self.
@@ -657,7 +1600,7 @@ mod test {
 
     #[test]
     fn test_mpt_updates() {
-        let circuit = TestCircuit { updates: vec![] };
+        let circuit = TestCircuit { proofs: vec![] };
         let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
         assert_eq!(prover.verify(), Ok(()));
     }
@@ -0,0 +1,359 @@
+//! cache of proving and verifying keys on disk, keyed by the shape of circuits
+
+use crate::{
+    artifact::{downsize, vk_fingerprint},
+    serde::{Hash, HexBytes},
+};
+use ethers_core::utils::keccak256;
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    plonk::{
+        keygen_pk, keygen_vk, Circuit, ConstraintSystem, Error as PlonkError, ProvingKey,
+        VerifyingKey,
+    },
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+    SerdeFormat,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors raised when loading or storing keys
+#[derive(Error, Debug)]
+pub enum KeyStoreError {
+    /// reading or writing the keys fails
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// the metadata of keys is malformed
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// the constraint system has changed since the keys are generated, the fixed columns
+    /// are not covered and a change of them is caught by the vk fingerprint instead
+    #[error(
+        "keys of {shape} are generated for constraint system {stored:?}, now it is {current:?}"
+    )]
+    DigestMismatch {
+        /// the shape of keys
+        shape: String,
+        /// digest recorded along with the keys
+        stored: Hash,
+        /// digest of current constraint system
+        current: Hash,
+    },
+    /// the stored keys differ from the ones their metadata is recorded for
+    #[error("keys of {shape} have vk fingerprint {loaded:?}, but {recorded:?} is recorded")]
+    FingerprintMismatch {
+        /// the shape of keys
+        shape: String,
+        /// fingerprint of vk recorded in the metadata
+        recorded: Hash,
+        /// fingerprint of the loaded vk
+        loaded: Hash,
+    },
+    /// the stored keys are not generated under the k of shape
+    #[error("keys of {shape} are generated under k = {stored}")]
+    KMismatch {
+        /// the shape of keys
+        shape: String,
+        /// k of the stored keys
+        stored: u32,
+    },
+    /// the params can not support the circuit
+    #[error("params of k = {params} is too small for circuit of k = {circuit}")]
+    ParamsTooSmall {
+        /// k of params
+        params: u32,
+        /// k of circuit
+        circuit: u32,
+    },
+    /// generating the keys fails
+    #[error(transparent)]
+    Keygen(#[from] PlonkError),
+}
+
+/// The circuits keys are generated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CircuitKind {
+    /// `EthTrieCircuit`
+    Mpt,
+    /// `HashCircuit`
+    Hash,
+    /// `CombinedCircuit`
+    Combined,
+}
+
+/// The shape of circuit which its keys depend on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitShape {
+    /// the circuit
+    pub kind: CircuitKind,
+    /// k of circuit
+    pub k: u32,
+    /// rows of circuit
+    pub calcs: usize,
+    /// if mpt circuit is under lite mode, i.e. without mpt table
+    pub lite: bool,
+}
+
+impl CircuitShape {
+    /// the shape of `EthTrieCircuit` with [calcs] rows under k
+    pub fn mpt<const LITE: bool>(calcs: usize, k: u32) -> Self {
+        Self {
+            kind: CircuitKind::Mpt,
+            k,
+            calcs,
+            lite: LITE,
+        }
+    }
+
+    /// the shape of `HashCircuit` with [calcs] rows under k
+    pub fn hash(calcs: usize, k: u32) -> Self {
+        Self {
+            kind: CircuitKind::Hash,
+            k,
+            calcs,
+            lite: false,
+        }
+    }
+
+    /// the shape of `CombinedCircuit` with [calcs] rows under k
+    pub fn combined(calcs: usize, k: u32) -> Self {
+        Self {
+            kind: CircuitKind::Combined,
+            k,
+            calcs,
+            lite: false,
+        }
+    }
+
+    /// the key of shape, which names the files of keys
+    pub fn key(&self) -> String {
+        match self.kind {
+            CircuitKind::Hash => format!("hash-k{}-calcs{}", self.k, self.calcs),
+            CircuitKind::Combined => format!("combined-k{}-calcs{}", self.k, self.calcs),
+            CircuitKind::Mpt => format!(
+                "mpt-k{}-calcs{}-{}",
+                self.k,
+                self.calcs,
+                if self.lite { "lite" } else { "full" },
+            ),
+        }
+    }
+}
+
+/// The digest of constraint system configured by circuit [C], i.e. keccak256 over
+/// its pinned form. it covers the gates, lookups and columns but not the values of fixed
+/// columns, which are assigned in synthesis
+pub fn cs_digest<C: Circuit<Fr>>() -> Hash {
+    let mut cs = ConstraintSystem::<Fr>::default();
+    C::configure(&mut cs);
+    HexBytes(keccak256(format!("{:?}", cs.pinned())))
+}
+
+// stored along with the keys
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    shape: CircuitShape,
+    digest: Hash,
+    vk: Hash,
+}
+
+/// Proving and verifying keys cached in a directory, each shape has a pair of `<key>.pk` and
+/// `<key>.vk` and the metadata in `<key>.json`. The keys are refused to be reused once the
+/// constraint system has changed, or if they differ from the vk fingerprint recorded in
+/// the metadata
+#[derive(Clone, Debug)]
+pub struct KeyStore {
+    dir: PathBuf,
+    format: SerdeFormat,
+}
+
+impl KeyStore {
+    /// the store in [dir], which is created on storing keys
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            format: SerdeFormat::RawBytes,
+        }
+    }
+
+    /// use [format] for serializing keys
+    pub fn with_format(mut self, format: SerdeFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn path(&self, shape: &CircuitShape, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{ext}", shape.key()))
+    }
+
+    // the digest only covers the configuration, so the loaded vk is checked against the k
+    // of shape and the fingerprint recorded in metadata
+    fn check_vk(
+        shape: &CircuitShape,
+        meta: &Metadata,
+        vk: &VerifyingKey<G1Affine>,
+    ) -> Result<(), KeyStoreError> {
+        let stored = vk.get_domain().k();
+        if stored != shape.k {
+            return Err(KeyStoreError::KMismatch {
+                shape: shape.key(),
+                stored,
+            });
+        }
+        let loaded = vk_fingerprint(vk);
+        if loaded != meta.vk {
+            return Err(KeyStoreError::FingerprintMismatch {
+                shape: shape.key(),
+                recorded: meta.vk,
+                loaded,
+            });
+        }
+        Ok(())
+    }
+
+    // the metadata checked against current constraint system, none if there are no keys
+    fn check<C: Circuit<Fr>>(
+        &self,
+        shape: &CircuitShape,
+    ) -> Result<Option<Metadata>, KeyStoreError> {
+        let path = self.path(shape, "json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let meta: Metadata = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let current = cs_digest::<C>();
+        if meta.digest != current {
+            return Err(KeyStoreError::DigestMismatch {
+                shape: shape.key(),
+                stored: meta.digest,
+                current,
+            });
+        }
+        Ok(Some(meta))
+    }
+
+    /// load the proving key of [shape], none if it has not been stored
+    pub fn load_pk<C: Circuit<Fr>>(
+        &self,
+        shape: &CircuitShape,
+    ) -> Result<Option<ProvingKey<G1Affine>>, KeyStoreError> {
+        let meta = match self.check::<C>(shape)? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let mut reader = BufReader::new(File::open(self.path(shape, "pk"))?);
+        let pk = ProvingKey::read::<_, C>(&mut reader, self.format)?;
+        Self::check_vk(shape, &meta, pk.get_vk())?;
+        Ok(Some(pk))
+    }
+
+    /// load the verifying key of [shape], none if it has not been stored
+    pub fn load_vk<C: Circuit<Fr>>(
+        &self,
+        shape: &CircuitShape,
+    ) -> Result<Option<VerifyingKey<G1Affine>>, KeyStoreError> {
+        let meta = match self.check::<C>(shape)? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let mut reader = BufReader::new(File::open(self.path(shape, "vk"))?);
+        let vk = VerifyingKey::read::<_, C>(&mut reader, self.format)?;
+        Self::check_vk(shape, &meta, &vk)?;
+        Ok(Some(vk))
+    }
+
+    /// store the keys of [shape] generated for circuit [C]
+    pub fn store<C: Circuit<Fr>>(
+        &self,
+        shape: &CircuitShape,
+        pk: &ProvingKey<G1Affine>,
+    ) -> Result<(), KeyStoreError> {
+        std::fs::create_dir_all(&self.dir)?;
+        // the metadata is removed first and written at last, so the keys are never
+        // taken as complete before they are
+        let meta_path = self.path(shape, "json");
+        if meta_path.exists() {
+            std::fs::remove_file(&meta_path)?;
+        }
+
+        let mut writer = BufWriter::new(File::create(self.path(shape, "vk"))?);
+        pk.get_vk().write(&mut writer, self.format)?;
+        writer.flush()?;
+        let mut writer = BufWriter::new(File::create(self.path(shape, "pk"))?);
+        pk.write(&mut writer, self.format)?;
+        writer.flush()?;
+
+        let meta = Metadata {
+            shape: *shape,
+            digest: cs_digest::<C>(),
+            vk: vk_fingerprint(pk.get_vk()),
+        };
+        serde_json::to_writer_pretty(File::create(meta_path)?, &meta)?;
+        Ok(())
+    }
+
+    /// load the proving key of [shape], or generate it from [circuit] under [params]
+    /// (downsized to the k of shape) and store it
+    pub fn get_or_generate<C: Circuit<Fr>>(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        shape: &CircuitShape,
+        circuit: &C,
+    ) -> Result<ProvingKey<G1Affine>, KeyStoreError> {
+        if let Some(pk) = self.load_pk::<C>(shape)? {
+            return Ok(pk);
+        }
+        let params = downsize(params, shape.k).ok_or(KeyStoreError::ParamsTooSmall {
+            params: params.k(),
+            circuit: shape.k,
+        })?;
+        let vk = keygen_vk(&params, circuit)?;
+        let pk = keygen_pk(&params, vk, circuit)?;
+        self.store::<C>(shape, &pk)?;
+        Ok(pk)
+    }
+
+    /// the directory of store
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{CombinedCircuit, EthTrieCircuit};
+
+    #[test]
+    fn shape_keys() {
+        assert_eq!(
+            CircuitShape::mpt::<false>(40, 7).key(),
+            "mpt-k7-calcs40-full"
+        );
+        assert_eq!(
+            CircuitShape::mpt::<true>(40, 7).key(),
+            "mpt-k7-calcs40-lite"
+        );
+        assert_eq!(CircuitShape::hash(40, 9).key(), "hash-k9-calcs40");
+        assert_eq!(CircuitShape::combined(40, 10).key(), "combined-k10-calcs40");
+
+        // digests tell the constraint systems apart
+        assert_eq!(
+            cs_digest::<EthTrieCircuit<Fr, false>>(),
+            cs_digest::<EthTrieCircuit<Fr, false>>()
+        );
+        assert_ne!(
+            cs_digest::<EthTrieCircuit<Fr, false>>(),
+            cs_digest::<EthTrieCircuit<Fr, true>>()
+        );
+        assert_ne!(
+            cs_digest::<EthTrieCircuit<Fr, false>>(),
+            cs_digest::<CombinedCircuit<Fr>>()
+        );
+    }
+}
@@ -20,8 +20,10 @@
 
 use halo2_proofs::{
     arithmetic::FieldExt,
-    circuit::{Layouter, Region, Value},
-    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Selector, TableColumn},
+    circuit::{AssignedCell, Layouter, Region, Value},
+    plonk::{
+        Advice, Column, ConstraintSystem, Error, Expression, Fixed, Instance, Selector, TableColumn,
+    },
     poly::Rotation,
 };
 
@@ -55,13 +57,42 @@ pub(crate) struct LayerGadget {
     new_root_index: Column<Advice>,
     address_index: Column<Advice>,
 
+    // flag the heading row of a block which starts a new root chain (segment), where
+    // the old root needs not to be the new root of the previous block. it can only be
+    // enabled when checkpoints are configured, and both roots across the break are
+    // exposed as checkpoints then
+    segment_break: Column<Advice>,
+    segment_enable: Column<Fixed>,
+
     free_cols: Vec<Column<Advice>>,
 
     op_delta_aux: Column<Advice>,
 
     control_table: [TableColumn; 5],
+
+    checkpoint: Option<CheckpointConfig>,
+}
+
+// the roots on flagged rows are exposed as checkpoints: flagged rows are counted by
+// a running index, and the (index, kind, root) of each one is looked up from the instance
+// column, which has the count of checkpoints at row 2 and the kind and root of the i-th
+// checkpoint at row 2i + 1 and 2i + 2. the kind is 0 for the new root of a block and 1
+// for the old root of a block starting a new segment.
+// the seq column is assigned at the offsets of main region, while the lookup reads the
+// instance column at the same absolute rows, so the rows above hold only when the main
+// region starts at row 0 of circuit. it is the first region assigned in the columns of
+// LayerGadget in the circuits of this crate, so SimpleFloorPlanner puts it there, and any
+// circuit embedding the gadget with checkpoints must keep it so
+#[derive(Clone, Debug)]
+struct CheckpointConfig {
+    flag: Column<Advice>,
+    index: Column<Advice>,
+    seq: Column<Fixed>,
 }
 
+// row of the instance column for the count of checkpoints, following the start and final root
+pub(crate) const CHECKPOINT_COUNT_ROW: usize = 2;
+
 pub(crate) type OpBorder = ((u32, u32), (u32, u32));
 
 impl LayerGadget {
@@ -91,6 +122,12 @@ impl LayerGadget {
         (self.old_root_index, self.new_root_index)
     }
 
+    // obtain the index cols for start and end root value, with the flag col of segment break
+    // which relax the chaining of roots
+    pub fn get_root_chain(&self) -> (Column<Advice>, Column<Advice>, Column<Advice>) {
+        (self.old_root_index, self.new_root_index, self.segment_break)
+    }
+
     // obtain the index col for address value
     pub fn get_address_index(&self) -> Column<Advice> {
         self.address_index
@@ -131,12 +168,15 @@ impl LayerGadget {
         let new_root_index = meta.advice_column();
         let address_index = meta.advice_column();
         let op_delta_aux = meta.advice_column();
+        let segment_break = meta.advice_column();
+        let segment_enable = meta.fixed_column();
         let control_table = [(); 5].map(|_| meta.lookup_table_column());
 
         // require permutation with constants
         meta.enable_equality(series);
         meta.enable_equality(op_type);
         meta.enable_equality(ctrl_type);
+        meta.enable_equality(new_root_index);
 
         meta.create_gate("series", |meta| {
             let sel = meta.query_selector(sel);
@@ -146,6 +186,25 @@ impl LayerGadget {
             vec![sel * (Expression::Constant(Fp::one()) - series_delta.clone()) * series_delta]
         });
 
+        meta.create_gate("segment break", |meta| {
+            let sel = meta.query_selector(sel);
+            let series_delta = meta.query_advice(series, Rotation::cur())
+                - meta.query_advice(series, Rotation::prev());
+            let segment_break = meta.query_advice(segment_break, Rotation::cur());
+            // break ∈ {0, 1}, and only enabled at the heading row of a block
+            vec![
+                sel.clone()
+                    * segment_break.clone()
+                    * (Expression::Constant(Fp::one()) - segment_break.clone()),
+                sel.clone()
+                    * segment_break.clone()
+                    * (Expression::Constant(Fp::one()) - series_delta),
+                sel * segment_break
+                    * (Expression::Constant(Fp::one())
+                        - meta.query_fixed(segment_enable, Rotation::cur())),
+            ]
+        });
+
         meta.create_gate("op transition", |meta| {
             let sel = meta.query_selector(sel);
             let op_delta = meta.query_advice(op_type, Rotation::cur())
@@ -311,23 +370,88 @@ impl LayerGadget {
             new_root_index,
             address_index,
             op_delta_aux,
+            segment_break,
+            segment_enable,
             control_table,
+            checkpoint: None,
         }
     }
 
+    // expose the new root on flagged rows through the instance column, which requires the
+    // main region to start at row 0, see `CheckpointConfig`
+    pub fn configure_checkpoints<Fp: FieldExt>(
+        &mut self,
+        meta: &mut ConstraintSystem<Fp>,
+        instance: Column<Instance>,
+    ) {
+        let flag = meta.advice_column();
+        let index = meta.advice_column();
+        let seq = meta.fixed_column();
+        meta.enable_equality(index);
+        let (sel, old_root_index, new_root_index, segment_break) = (
+            self.sel,
+            self.old_root_index,
+            self.new_root_index,
+            self.segment_break,
+        );
+
+        meta.create_gate("checkpoint index", |meta| {
+            let sel = meta.query_selector(sel);
+            let flag_prev = meta.query_advice(flag, Rotation::prev());
+            let flag = meta.query_advice(flag, Rotation::cur());
+            let index_delta = meta.query_advice(index, Rotation::cur())
+                - meta.query_advice(index, Rotation::prev());
+            let segment_break = meta.query_advice(segment_break, Rotation::cur());
+            // a segment break must be flagged, as well as the tailing row of the previous
+            // block, so the roots at both sides of the break are exposed
+            vec![
+                sel.clone() * flag.clone() * (Expression::Constant(Fp::one()) - flag.clone()),
+                sel.clone() * (index_delta - flag.clone()),
+                sel.clone() * segment_break.clone() * (Expression::Constant(Fp::one()) - flag),
+                sel * segment_break * (Expression::Constant(Fp::one()) - flag_prev),
+            ]
+        });
+
+        meta.lookup_any("checkpoint root", |meta| {
+            let enable = meta.query_selector(sel) * meta.query_advice(flag, Rotation::cur());
+            let segment_break = meta.query_advice(segment_break, Rotation::cur());
+            let root = meta.query_advice(new_root_index, Rotation::cur())
+                + segment_break.clone()
+                    * (meta.query_advice(old_root_index, Rotation::cur())
+                        - meta.query_advice(new_root_index, Rotation::cur()));
+            vec![
+                (
+                    enable.clone() * meta.query_advice(index, Rotation::cur()),
+                    meta.query_fixed(seq, Rotation::cur()),
+                ),
+                (
+                    enable.clone() * segment_break,
+                    meta.query_instance(instance, Rotation::prev()),
+                ),
+                (
+                    enable * root,
+                    meta.query_instance(instance, Rotation::cur()),
+                ),
+            ]
+        });
+
+        self.checkpoint = Some(CheckpointConfig { flag, index, seq });
+    }
+
     // an unique transition (start_op_code, 0) -> (<op type>, <ctrl type>) would be put in inter-op-block table
     // automatically to specify how the circuit starts
     pub fn start_op_code(&self) -> u32 {
         self.s_stepflags.len() as u32
     }
 
-    // LayerGadget must be first assigned, with other gadgets start from the offset it has returned
+    // LayerGadget must be first assigned, with other gadgets start from the offset it has returned.
+    // the cell of start root is also returned, so it can be bound to public inputs
     pub fn assign<Fp: FieldExt>(
         &self,
         region: &mut Region<'_, Fp>,
         max_rows: usize,
         init_root: Fp,
-    ) -> Result<usize, Error> {
+    ) -> Result<(usize, AssignedCell<Fp, Fp>), Error> {
         // current we flush the first row, and start other circuits's assignation from row 1
         self.free_cols.iter().try_for_each(|col| {
             region
@@ -348,7 +472,7 @@ impl LayerGadget {
             Fp::from(self.start_op_code() as u64),
         )?;
         region.assign_advice_from_constant(|| "init ctrl", self.ctrl_type, 0, Fp::zero())?;
-        region.assign_advice(
+        let start_root = region.assign_advice(
             || "start root",
             self.new_root_index,
             0,
@@ -362,6 +486,39 @@ impl LayerGadget {
             self.sel.enable(region, offset)?;
         }
 
+        if let Some(checkpoint) = &self.checkpoint {
+            // the start root borders the previous circuit, so the first block can start a
+            // segment, which exposes the start root as a segment break
+            region.assign_advice(
+                || "init checkpoint flag",
+                checkpoint.flag,
+                0,
+                || Value::known(Fp::one()),
+            )?;
+            region.assign_advice_from_constant(
+                || "init checkpoint index",
+                checkpoint.index,
+                0,
+                Fp::zero(),
+            )?;
+            for (index, offset) in (CHECKPOINT_COUNT_ROW + 2..=max_rows).step_by(2).enumerate() {
+                region.assign_fixed(
+                    || "checkpoint seq",
+                    checkpoint.seq,
+                    offset,
+                    || Value::known(Fp::from(index as u64 + 1)),
+                )?;
+            }
+            for offset in 1..max_rows {
+                region.assign_fixed(
+                    || "segment enable",
+                    self.segment_enable,
+                    offset,
+                    || Value::known(Fp::one()),
+                )?;
+            }
+        }
+
         // flush one more row
         self.free_cols.iter().try_for_each(|col| {
             region
@@ -391,7 +548,71 @@ impl LayerGadget {
             || Value::known(Fp::zero()),
         )?;
 
-        Ok(1)
+        Ok((1, start_root))
+    }
+
+    // flag the first row of a block as segment break and the last row as checkpoint, and
+    // advance the index of checkpoints. it should be called for every block (include the
+    // padding) after `assign`, and a segment break require the previous block being a
+    // checkpoint. return the index after the block
+    pub fn assign_checkpoint<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        rows: usize,
+        (is_segment_break, is_checkpoint): (bool, bool),
+        mut index: usize,
+    ) -> Result<usize, Error> {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return Ok(index),
+        };
+        let last_row = offset + rows - 1;
+        for row in offset..=last_row {
+            let segment_break = is_segment_break && row == offset;
+            let flagged = segment_break || (is_checkpoint && row == last_row);
+            index += flagged as usize;
+            region.assign_advice(
+                || "segment break",
+                self.segment_break,
+                row,
+                || Value::known(if segment_break { Fp::one() } else { Fp::zero() }),
+            )?;
+            region.assign_advice(
+                || "checkpoint flag",
+                checkpoint.flag,
+                row,
+                || Value::known(if flagged { Fp::one() } else { Fp::zero() }),
+            )?;
+            region.assign_advice(
+                || "checkpoint index",
+                checkpoint.index,
+                row,
+                || Value::known(Fp::from(index as u64)),
+            )?;
+        }
+        Ok(index)
+    }
+
+    // obtain the cell of checkpoint index at the last row, i.e. the count of checkpoints,
+    // so it can be bound to public inputs. None if checkpoints are not configured
+    pub fn assign_checkpoint_count<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        max_rows: usize,
+        count: usize,
+    ) -> Result<Option<AssignedCell<Fp, Fp>>, Error> {
+        self.checkpoint
+            .as_ref()
+            .map(|checkpoint| {
+                region.assign_advice(
+                    || "checkpoint count",
+                    checkpoint.index,
+                    max_rows - 1,
+                    || Value::known(Fp::from(count as u64)),
+                )
+            })
+            .transpose()
     }
 
     // pace has to be called before a working gadget is assigned on the specified offset, the rows
@@ -471,6 +692,12 @@ impl LayerGadget {
                     .assign_advice(|| "flushing", *col, offset, || Value::known(Fp::zero()))
                     .map(|_| ())
             })?;
+            region.assign_advice(
+                || "flushing segment break",
+                self.segment_break,
+                offset,
+                || Value::known(Fp::zero()),
+            )?;
 
             prev_op = op_type.1;
         }
@@ -479,7 +706,8 @@ impl LayerGadget {
     }
 
     // complete block is called AFTER all working gadget has been assigned on the specified offset,
-    // this entry fill whole block with series and index value
+    // this entry fill whole block with series and index value. return the cell of new root at the
+    // last row of block, i.e. the final root if the block is the last one
     pub fn complete_block<Fp: FieldExt>(
         &self,
         region: &mut Region<'_, Fp>,
@@ -488,7 +716,8 @@ impl LayerGadget {
         roots: Option<(Fp, Fp)>,
         address: Option<Fp>,
         rows: usize,
-    ) -> Result<(), Error> {
+    ) -> Result<Option<AssignedCell<Fp, Fp>>, Error> {
+        let mut new_root = None;
         for offset in offset..(offset + rows) {
             region.assign_advice(
                 || "series pacing",
@@ -502,12 +731,12 @@ impl LayerGadget {
                 offset,
                 || Value::known(roots.map(|(v, _)| v).unwrap_or_default()),
             )?;
-            region.assign_advice(
+            new_root.replace(region.assign_advice(
                 || "new root index",
                 self.new_root_index,
                 offset,
                 || Value::known(roots.map(|(_, v)| v).unwrap_or_default()),
-            )?;
+            )?);
             region.assign_advice(
                 || "address root index",
                 self.address_index,
@@ -515,7 +744,7 @@ impl LayerGadget {
                 || Value::known(address.unwrap_or_default()),
             )?;
         }
-        Ok(())
+        Ok(new_root)
     }
 
     // set all transition rules
@@ -720,6 +949,8 @@ impl LayerGadget {
 
 // padding gadget keep start and end root identical, it often act as the "terminal" circuit to fill the rest space
 // in the region, it has only one ctrl type equal to 0,
+// with root index specified, padding rows also carry the new root of the row before them, so
+// the final root of all ops can be found on the last row
 #[derive(Clone, Debug)]
 pub(crate) struct PaddingGadget {
     s_enable: Column<Advice>,
@@ -729,14 +960,31 @@ pub(crate) struct PaddingGadget {
 
 impl PaddingGadget {
     pub fn configure<Fp: FieldExt>(
-        _meta: &mut ConstraintSystem<Fp>,
-        _sel: Selector,
+        meta: &mut ConstraintSystem<Fp>,
+        sel: Selector,
         exported: &[Column<Advice>],
         s_ctrl_type: &[Column<Advice>],
+        root_index: Option<(Column<Advice>, Column<Advice>)>,
     ) -> Self {
+        let s_enable = exported[1];
+
+        if let Some((old_root_index, new_root_index)) = root_index {
+            meta.create_gate("padding root index", |meta| {
+                let enable =
+                    meta.query_selector(sel) * meta.query_advice(s_enable, Rotation::cur());
+                let new_root_prev = meta.query_advice(new_root_index, Rotation::prev());
+                vec![
+                    enable.clone()
+                        * (meta.query_advice(old_root_index, Rotation::cur())
+                            - new_root_prev.clone()),
+                    enable * (meta.query_advice(new_root_index, Rotation::cur()) - new_root_prev),
+                ]
+            });
+        }
+
         Self {
             ctrl_type: exported[0],
-            s_enable: exported[1],
+            s_enable,
             s_ctrl_type: s_ctrl_type[0],
         }
     }
@@ -810,6 +1058,7 @@ mod test {
                 layer.sel,
                 layer.exported_cols(0).as_slice(),
                 layer.get_ctrl_type_flags(),
+                Some(layer.get_root_indexs()),
             );
 
             let cst = meta.fixed_column();
@@ -828,7 +1077,7 @@ mod test {
                 |mut region| {
                     let r = self.root;
                     let rows = self.blocks.iter().sum();
-                    let mut start = config.layer.assign(&mut region, rows, r)?;
+                    let (mut start, _) = config.layer.assign(&mut region, rows, r)?;
                     let mut last_op = config.layer.start_op_code();
 
                     for (index, rows) in self.blocks.iter().enumerate() {
@@ -909,12 +1158,14 @@ mod test {
                 layer.sel,
                 layer.exported_cols(0).as_slice(),
                 layer.get_ctrl_type_flags(),
+                None,
             );
             let padding1 = PaddingGadget::configure(
                 meta,
                 layer.sel,
                 layer.exported_cols(2).as_slice(),
                 layer.get_ctrl_type_flags(),
+                None,
             );
 
             let cst = meta.fixed_column();
@@ -937,7 +1188,7 @@ mod test {
                 |mut region| {
                     let r = self.root;
                     let rows = self.blocks.iter().fold(0, |acc, x| acc + x.0 + x.1);
-                    let mut start = config.layer.assign(&mut region, rows, r)?;
+                    let (mut start, _) = config.layer.assign(&mut region, rows, r)?;
                     let mut last_op = config.layer.start_op_code();
 
                     for (index, rows) in self.blocks.iter().enumerate() {
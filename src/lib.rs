@@ -8,6 +8,7 @@
 #![deny(unsafe_code)]
 
 pub use crate::serde::{Hash, Row, RowDeError};
+pub use artifact::MptProofArtifact;
 
 mod constraint_builder;
 mod eth;
@@ -15,6 +16,7 @@ mod gadgets;
 mod layers;
 mod mason;
 mod mpt;
+mod mpt_circuit;
 mod mpt_table;
 mod proof;
 #[cfg(test)]
@@ -22,33 +24,49 @@ mod test_utils;
 mod types;
 mod util;
 
+pub mod artifact;
+pub mod keystore;
 pub mod operation;
 pub mod serde;
+pub mod trie;
+pub mod verify;
 
 use eth::StorageGadget;
 use hash_circuit::hash::PoseidonHashTable;
 /// re-export required namespace from depened poseidon hash circuit
 pub use hash_circuit::{hash, poseidon};
-pub use mpt_table::MPTProofType;
+pub use mpt_circuit::{MptCircuit, MptCircuitConfig};
 use mpt_table::{Config as MPTConfig, MPTEntry, MPTTable};
+pub use mpt_table::{MPTProofType, MptTable, MptTableRow, PermutedMptTable};
 
-use lazy_static::lazy_static;
-use std::sync::Mutex;
-lazy_static! {
-    static ref RAND_BASE: Mutex<Vec<u64>> = Mutex::new(vec![0x10000u64]);
+/// global entry to set new RAND_BASE, which is no longer supported: the randomness
+/// is specified for each circuit by its config, and the full circuit always takes
+/// a challenge. it panics instead of leaving the circuits under another base silently
+#[deprecated(note = "pass RlcRandomness to EthTrieConfig::configure_sub")]
+pub fn set_rand_base(r: u64) {
+    panic!(
+        "set_rand_base({r:#x}) is not supported, \
+         pass RlcRandomness to EthTrieConfig::configure_sub"
+    );
 }
 
-/// global entry to set new RAND_BASE instead of default: 0x100
-pub fn set_rand_base(r: u64) {
-    RAND_BASE.lock().unwrap().push(r);
+/// The randomness which rlc fields in mpt table are taken over
+#[derive(Clone, Copy, Debug)]
+pub enum RlcRandomness {
+    /// challenge drawn after the first phase
+    Challenge(Challenge),
+    /// fixed base, which make the rlc forgeable and is only for testing
+    Fixed(u64),
 }
 
-fn get_rand_base() -> u64 {
-    *RAND_BASE
-        .lock()
-        .unwrap()
-        .last()
-        .expect("always has init element")
+impl RlcRandomness {
+    /// the expression of randomness in constraints
+    pub fn expr<Fp: FieldExt>(&self) -> Expression<Fp> {
+        match *self {
+            Self::Challenge(challenge) => Expression::Challenge(challenge),
+            Self::Fixed(base) => Expression::Constant(Fp::from(base)),
+        }
+    }
 }
 
 /// Indicate the operation type of a row in MPT circuit
@@ -79,13 +97,17 @@ enum CtrlTransitionKind {
 use eth::AccountGadget;
 use halo2_proofs::{
     arithmetic::FieldExt,
-    circuit::{Layouter, SimpleFloorPlanner},
-    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Expression},
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    plonk::{
+        Advice, Challenge, Circuit, Column, ConstraintSystem, Error, Expression, FirstPhase,
+        Instance,
+    },
 };
 use hash::Hashable;
 use layers::{LayerGadget, PaddingGadget};
 use mpt::MPTOpGadget;
-use operation::{AccountOp, HashTracesSrc, SingleOp};
+use operation::{AccountOp, HashTracesSrc, HashableField, SingleOp};
+use std::collections::HashSet;
 
 // building lagrange polynmials L for T so that L(n) = 1 when n = T else 0, n in [0, TO]
 fn lagrange_polynomial<Fp: FieldExt, const T: usize, const TO: usize>(
@@ -177,6 +199,7 @@ impl<Fp: FieldExt> Circuit<Fp> for SimpleTrie<Fp> {
             layer.public_sel(),
             layer.exported_cols(OP_PADDING).as_slice(),
             layer.get_ctrl_type_flags(),
+            None,
         );
         let mpt = MPTOpGadget::configure_simple(
             meta,
@@ -184,7 +207,7 @@ impl<Fp: FieldExt> Circuit<Fp> for SimpleTrie<Fp> {
             layer.exported_cols(OP_MPT).as_slice(),
             layer.get_ctrl_type_flags(),
             layer.get_free_cols(),
-            Some(layer.get_root_indexs()),
+            Some(layer.get_root_chain()),
         );
 
         let cst = meta.fixed_column();
@@ -300,6 +323,8 @@ pub struct EthTrieConfig {
     tables: mpt::MPTOpTables,
     hash_tbl: mpt::HashTable,
     mpt_tbl: Option<MPTConfig>,
+    instance: Option<Column<Instance>>,
+    randomness: Option<RlcRandomness>,
 }
 
 impl EthTrieConfig {
@@ -347,6 +372,7 @@ impl EthTrieConfig {
             layer.public_sel(),
             layer.exported_cols(OP_PADDING).as_slice(),
             layer.get_ctrl_type_flags(),
+            Some(layer.get_root_indexs()),
         );
         let account_trie = MPTOpGadget::configure(
             meta,
@@ -354,7 +380,7 @@ impl EthTrieConfig {
             layer.exported_cols(OP_TRIE_ACCOUNT).as_slice(),
             layer.get_ctrl_type_flags(),
             layer.get_free_cols(),
-            Some(layer.get_root_indexs()),
+            Some(layer.get_root_chain()),
             tables.clone(),
             hash_tbl.clone(),
         );
@@ -400,6 +426,41 @@ impl EthTrieConfig {
             tables,
             hash_tbl,
             mpt_tbl: None,
+            instance: None,
+            randomness: None,
+        }
+    }
+
+    /// expose the start and final root of the operations as public inputs, at the first
+    /// and second row of the instance column. the count of checkpoints and the (kind, root)
+    /// of each checkpoint follow them, if there is any checkpoint or segment break
+    pub fn with_instance<Fp: FieldExt>(
+        mut self,
+        meta: &mut ConstraintSystem<Fp>,
+        instance: Column<Instance>,
+    ) -> Self {
+        meta.enable_equality(instance);
+        self.layer.configure_checkpoints(meta, instance);
+        self.instance.replace(instance);
+        self
+    }
+
+    /// the value of randomness for mpt table, unknown before the challenge
+    /// is available. the mpt table under word mode has no rlc and it is always zero
+    pub fn randomness<Fp: FieldExt>(&self, layouter: &impl Layouter<Fp>) -> Value<Fp> {
+        if self
+            .mpt_tbl
+            .as_ref()
+            .map_or(false, |tbl| tbl.is_word_mode())
+        {
+            return Value::known(Fp::zero());
+        }
+        match self
+            .randomness
+            .expect("randomness is specified for full circuit")
+        {
+            RlcRandomness::Challenge(challenge) => layouter.get_challenge(challenge),
+            RlcRandomness::Fixed(base) => Value::known(Fp::from(base)),
         }
     }
 
@@ -409,16 +470,41 @@ impl EthTrieConfig {
         Self::configure_base(meta, hash_tbl)
     }
 
-    /// configure for full circuit
+    /// configure for full circuit, [mpt_tbl] is the mpt table which other circuits can
+    /// lookup through. the rlc fields of mpt table are constrained and assigned over
+    /// [randomness]
     pub fn configure_sub<Fp: FieldExt>(
         meta: &mut ConstraintSystem<Fp>,
-        mpt_tbl: [Column<Advice>; 7],
+        mpt_tbl: MptTable,
         hash_tbl: [Column<Advice>; 5],
-        randomness: Expression<Fp>,
+        randomness: RlcRandomness,
     ) -> Self {
         let mut lite_cfg = Self::configure_base(meta, hash_tbl);
-        let mpt_tbl = MPTTable::configure(meta, mpt_tbl, randomness);
-        let layer = &lite_cfg.layer;
+        lite_cfg.randomness.replace(randomness);
+        let mpt_tbl = MPTTable::configure(meta, mpt_tbl, randomness.expr());
+        lite_cfg.bind_mpt_table(meta, mpt_tbl)
+    }
+
+    /// configure for full circuit with mpt table under word mode, where storage keys and
+    /// values are represented by (hi, lo) pairs in `MptTable::columns` and
+    /// `MptTable::word_columns` of [mpt_tbl]. no randomness is required, while the pairs
+    /// are not range checked (see `MptTable`)
+    pub fn configure_word<Fp: FieldExt>(
+        meta: &mut ConstraintSystem<Fp>,
+        mpt_tbl: MptTable,
+        hash_tbl: [Column<Advice>; 5],
+    ) -> Self {
+        let lite_cfg = Self::configure_base(meta, hash_tbl);
+        let mpt_tbl = MPTTable::configure_word(meta, mpt_tbl);
+        lite_cfg.bind_mpt_table(meta, mpt_tbl)
+    }
+
+    fn bind_mpt_table<Fp: FieldExt>(
+        mut self,
+        meta: &mut ConstraintSystem<Fp>,
+        mpt_tbl: MPTConfig,
+    ) -> Self {
+        let layer = &self.layer;
         let layer_exported = layer.exported_cols(0);
         let gadget_ind = layer.get_gadget_index();
         let root_ind = layer.get_root_indexs();
@@ -435,16 +521,37 @@ impl EthTrieConfig {
             [layer_exported[4], layer_exported[7]],
         );
 
-        lite_cfg.mpt_tbl.replace(mpt_tbl);
-        lite_cfg
+        self.mpt_tbl.replace(mpt_tbl);
+        self
+    }
+
+    /// also expose a copy of mpt table bound to it by a permutation argument, whose rows
+    /// are sorted by (address, storage key, index), see `PermutedMptTable`. only call for
+    /// non-lite circuit
+    pub fn with_permuted_mpt_table<Fp: FieldExt>(
+        mut self,
+        meta: &mut ConstraintSystem<Fp>,
+    ) -> Self {
+        let mpt_tbl = self
+            .mpt_tbl
+            .as_mut()
+            .expect("only call for non-lite circuit");
+        MPTTable::configure_permuted(meta, mpt_tbl);
+        self
+    }
+
+    /// the permuted copy of mpt table, if it has been configured by `with_permuted_mpt_table`
+    pub fn permuted_mpt_table(&self) -> Option<PermutedMptTable> {
+        self.mpt_tbl.as_ref().and_then(|tbl| tbl.permuted_table())
     }
 
     /// synthesize the mpt table part, the randomness also specify
-    /// if the base part of mpt table should be assigned
+    /// if the base part of mpt table should be assigned, the rlc fields
+    /// are left unknown until the value of challenge is available
     pub fn load_mpt_table<'d, Fp: Hashable>(
         &self,
         layouter: &mut impl Layouter<Fp>,
-        randomness: Option<Fp>,
+        randomness: Option<Value<Fp>>,
         ops: impl IntoIterator<Item = &'d AccountOp<Fp>>,
         tbl_tips: impl IntoIterator<Item = MPTProofType>,
         rows: usize,
@@ -492,22 +599,48 @@ impl EthTrieConfig {
         layouter: &mut impl Layouter<Fp>,
         ops: impl Iterator<Item = &'d AccountOp<Fp>> + Clone,
         rows: usize,
+    ) -> Result<(), Error> {
+        self.synthesize_core_with_checkpoints(layouter, ops, (&[], &[]), rows)
+    }
+
+    /// synthesize core part, and expose the roots after the ops indexed by [checkpoints]
+    /// as public inputs, which are put after the start and final root in the instance column.
+    /// the ops indexed by [segments] start new root chains, their old roots are exposed
+    /// along with the checkpoints, and the ops before them must be checkpoints.
+    /// the rows of checkpoints in the instance column are counted from the first row of the
+    /// main region, so it must be placed at row 0, i.e. be the first region in its columns
+    pub fn synthesize_core_with_checkpoints<'d, Fp: Hashable>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        ops: impl Iterator<Item = &'d AccountOp<Fp>> + Clone,
+        (checkpoints, segments): (&[usize], &[usize]),
+        rows: usize,
     ) -> Result<(), Error> {
         let start_root = ops
             .clone()
             .next()
             .map(|op| op.account_root_before())
             .unwrap_or_else(Fp::zero);
+        let final_root = ops
+            .clone()
+            .last()
+            .map(|op| op.account_root())
+            .unwrap_or(start_root);
+        let checkpoints: HashSet<_> = checkpoints.iter().copied().collect();
+        let segments: HashSet<_> = segments.iter().copied().collect();
 
-        layouter.assign_region(
+        let (root_cells, count_cell) = layouter.assign_region(
             || "main",
             |mut region| {
+                let mut checkpoint_index = 0;
                 let mut series: usize = 1;
                 let mut last_op_code = self.layer.start_op_code();
-                let mut start = self.layer.assign(&mut region, rows, start_root)?;
+                let (mut start, start_cell) = self.layer.assign(&mut region, rows, start_root)?;
+                // the new root at the last row, which is assigned by the last block
+                let mut final_cell = start_cell.clone();
 
                 let empty_account = Default::default();
-                for op in ops.clone() {
+                for (i, op) in ops.clone().enumerate() {
                     let block_start = start;
                     self.layer.pace_op(
                         &mut region,
@@ -551,13 +684,22 @@ impl EthTrieConfig {
 
                     assert!(start <= rows, "assigned rows for exceed limited {rows}");
 
-                    self.layer.complete_block(
+                    if let Some(cell) = self.layer.complete_block(
                         &mut region,
                         block_start,
                         series,
                         Some((op.account_root_before(), op.account_root())),
                         Some(op.address),
                         start - block_start,
+                    )? {
+                        final_cell = cell;
+                    }
+                    checkpoint_index = self.layer.assign_checkpoint(
+                        &mut region,
+                        block_start,
+                        start - block_start,
+                        (segments.contains(&i), checkpoints.contains(&i)),
+                        checkpoint_index,
                     )?;
 
                     series += 1;
@@ -568,14 +710,42 @@ impl EthTrieConfig {
                     self.layer
                         .pace_op(&mut region, start, (last_op_code, OP_PADDING), row_left)?;
                     self.padding.padding(&mut region, start, row_left)?;
-                    self.layer
-                        .complete_block(&mut region, start, series, None, None, row_left)?;
+                    if let Some(cell) = self.layer.complete_block(
+                        &mut region,
+                        start,
+                        series,
+                        Some((final_root, final_root)),
+                        None,
+                        row_left,
+                    )? {
+                        final_cell = cell;
+                    }
+                    self.layer.assign_checkpoint(
+                        &mut region,
+                        start,
+                        row_left,
+                        (false, false),
+                        checkpoint_index,
+                    )?;
                 }
 
-                Ok(())
+                Ok((
+                    [start_cell, final_cell],
+                    self.layer
+                        .assign_checkpoint_count(&mut region, rows, checkpoint_index)?,
+                ))
             },
         )?;
 
+        if let Some(instance) = self.instance {
+            for (index, cell) in root_cells.iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), instance, index)?;
+            }
+            if let Some(cell) = count_cell {
+                layouter.constrain_instance(cell.cell(), instance, layers::CHECKPOINT_COUNT_ROW)?;
+            }
+        }
+
         self.tables.fill_constant(
             layouter,
             MPTOpGadget::transition_rules().chain(AccountGadget::transition_rules()),
@@ -619,6 +789,8 @@ pub struct EthTrie<F: FieldExt> {
     start_root: F,
     final_root: F,
     ops: Vec<AccountOp<F>>,
+    checkpoints: Vec<usize>,
+    segments: Vec<usize>,
 }
 
 const OP_TRIE_ACCOUNT: u32 = 1;
@@ -634,9 +806,18 @@ impl<Fp: FieldExt> EthTrie<Fp> {
 
     /// Add an op into the circuit data
     pub fn add_op(&mut self, op: AccountOp<Fp>) {
+        self.add_op_with_checkpoint(op, false)
+    }
+
+    /// Add an op, and mark the root after it as a checkpoint if [checkpoint] is true,
+    /// e.g. for the last op of a transaction or block
+    pub fn add_op_with_checkpoint(&mut self, op: AccountOp<Fp>, checkpoint: bool) {
+        if checkpoint {
+            self.checkpoints.push(self.ops.len());
+        }
         if self.ops.is_empty() {
             self.start_root = op.account_root_before();
-        } else {
+        } else if self.segments.last() != Some(&self.ops.len()) {
             assert_eq!(self.final_root, op.account_root_before());
         }
         self.final_root = op.account_root();
@@ -650,6 +831,72 @@ impl<Fp: FieldExt> EthTrie<Fp> {
         }
     }
 
+    /// Obtain the indexs of ops after which the roots are checkpoints
+    pub fn checkpoints(&self) -> &[usize] {
+        &self.checkpoints
+    }
+
+    /// Start a new segment, so the next op can begin with a root unrelated to the current
+    /// final root. the roots at both sides of the break are exposed as checkpoints
+    pub fn start_segment(&mut self) {
+        assert!(!self.ops.is_empty(), "no segment to break");
+        let last = self.ops.len() - 1;
+        if self.checkpoints.last() != Some(&last) {
+            self.checkpoints.push(last);
+        }
+        if self.segments.last() != Some(&self.ops.len()) {
+            self.segments.push(self.ops.len());
+        }
+    }
+
+    /// Obtain the indexs of ops which start a new segment, except the first one
+    pub fn segments(&self) -> &[usize] {
+        &self.segments
+    }
+
+    /// Obtain the start and final root of each segment
+    pub fn segment_roots(&self) -> Vec<(Fp, Fp)> {
+        let mut borders = vec![0];
+        borders.extend(
+            self.segments
+                .iter()
+                .copied()
+                .filter(|i| *i < self.ops.len()),
+        );
+        borders.push(self.ops.len());
+        borders
+            .windows(2)
+            .filter(|w| w[0] < w[1])
+            .map(|w| {
+                (
+                    self.ops[w[0]].account_root_before(),
+                    self.ops[w[1] - 1].account_root(),
+                )
+            })
+            .collect()
+    }
+
+    /// Obtain the rows of mpt table for the operations under [randomness], with the same
+    /// [tips] as creating circuits
+    pub fn mpt_table_rows(
+        &self,
+        randomness: Fp,
+        tips: &[MPTProofType],
+    ) -> Result<Vec<MptTableRow>, CircuitError> {
+        let proof_types = proof_types(&self.ops, Vec::from(tips))?;
+        Ok(self
+            .ops
+            .iter()
+            .zip(proof_types)
+            .map(|(op, proof_type)| MptTableRow::from_op(proof_type, op, randomness))
+            .collect())
+    }
+
+    /// Obtain the start root
+    pub fn start_root(&self) -> Fp {
+        self.start_root
+    }
+
     /// Obtain the final root
     pub fn final_root(&self) -> Fp {
         self.final_root
@@ -667,27 +914,233 @@ pub struct EthTrieCircuit<F: FieldExt, const LITE: bool> {
     /// if NONE, circuit work under lite mode
     /// no run-time checking for the consistents between ops and generated mpt table
     pub mpt_table: Vec<MPTProofType>,
+    /// the indexs of ops after which the roots are exposed as public inputs
+    pub checkpoints: Vec<usize>,
+    /// the indexs of ops which start a new segment, their old roots are exposed as
+    /// public inputs and need not to chain with the previous op
+    pub segments: Vec<usize>,
+}
+
+impl<Fp: FieldExt, const LITE: bool> EthTrieCircuit<Fp, LITE> {
+    /// the public inputs of circuit, i.e. the start root and final root of the operations,
+    /// followed by the count of checkpoints and the (kind, root) of each one if there is any:
+    /// kind 0 is the root after an op, and kind 1 is the root before an op starting a segment
+    pub fn instance(&self) -> Vec<Vec<Fp>> {
+        let start_root = self
+            .ops
+            .first()
+            .map(|op| op.account_root_before())
+            .unwrap_or_else(Fp::zero);
+        let final_root = self
+            .ops
+            .last()
+            .map(|op| op.account_root())
+            .unwrap_or(start_root);
+        let mut instance = vec![start_root, final_root];
+        let segment_ops: HashSet<_> = self.segments.iter().copied().collect();
+        let checkpoint_ops: HashSet<_> = self.checkpoints.iter().copied().collect();
+        let checkpoints: Vec<_> = self
+            .ops
+            .iter()
+            .enumerate()
+            .flat_map(|(i, op)| {
+                let segment = segment_ops
+                    .contains(&i)
+                    .then(|| (Fp::one(), op.account_root_before()));
+                let checkpoint = checkpoint_ops
+                    .contains(&i)
+                    .then(|| (Fp::zero(), op.account_root()));
+                segment.into_iter().chain(checkpoint)
+            })
+            .collect();
+        if !checkpoints.is_empty() {
+            instance.push(Fp::from(checkpoints.len() as u64));
+            instance.extend(
+                checkpoints
+                    .into_iter()
+                    .flat_map(|(kind, root)| [kind, root]),
+            );
+        }
+        vec![instance]
+    }
+}
+
+/// The error raised when the operations do not fit in the rows available in circuits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CapacityError {
+    /// the main region of mpt circuit can not hold all the operations
+    #[error("mpt region requires {required} rows but only {available} are available")]
+    MptRegion {
+        /// rows required by the operations, include the leading row
+        required: usize,
+        /// rows available in circuit
+        available: usize,
+    },
+    /// the hash table can not hold all the hashes of operations
+    #[error("hash table requires {required} rows but only {available} are available")]
+    HashTable {
+        /// hashes involved in the operations
+        required: usize,
+        /// rows available in the table
+        available: usize,
+    },
+    /// the mpt table can not hold all the entries
+    #[error("mpt table requires {required} rows but only {available} are available")]
+    MptTable {
+        /// entries of the table
+        required: usize,
+        /// rows available in the table
+        available: usize,
+    },
+}
+
+/// The error raised when creating circuits from the operations
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CircuitError {
+    /// the operations do not fit in the circuits
+    #[error(transparent)]
+    Capacity(#[from] CapacityError),
+    /// the count of proof types does not match the count of operations
+    #[error("{tips} proof types are given for {ops} operations")]
+    TipCount {
+        /// count of proof types
+        tips: usize,
+        /// count of operations
+        ops: usize,
+    },
+    /// the proof type is inconsistent with the changes made by operation
+    #[error("proof type {tip:?} does not match operation {index}, inferred as {inferred:?}")]
+    ProofType {
+        /// index of the operation
+        index: usize,
+        /// the proof type given for the operation
+        tip: MPTProofType,
+        /// the proof type inferred from the operation
+        inferred: MPTProofType,
+    },
+    /// the proof type has no lookup binding mpt table to mpt circuit, so its entries in
+    /// mpt table could not be trusted
+    #[error("proof type {proof_type:?} of operation {index} is not supported by mpt table")]
+    UnsupportedProofType {
+        /// index of the operation
+        index: usize,
+        /// the proof type given for or inferred from the operation
+        proof_type: MPTProofType,
+    },
+}
+
+// the proof types of operations, inferred if [tips] is empty or checked against them
+fn proof_types<Fp: FieldExt>(
+    ops: &[AccountOp<Fp>],
+    tips: Vec<MPTProofType>,
+) -> Result<Vec<MPTProofType>, CircuitError> {
+    let tips = if tips.is_empty() {
+        ops.iter().map(AccountOp::proof_type).collect()
+    } else if tips.len() != ops.len() {
+        return Err(CircuitError::TipCount {
+            tips: tips.len(),
+            ops: ops.len(),
+        });
+    } else {
+        let mismatched = ops
+            .iter()
+            .zip(&tips)
+            .position(|(op, tip)| !op.accepts_proof_type(*tip));
+        if let Some(index) = mismatched {
+            return Err(CircuitError::ProofType {
+                index,
+                tip: tips[index],
+                inferred: ops[index].proof_type(),
+            });
+        }
+        tips
+    };
+    // mpt table has no lookup into mpt circuit for the poseidon code hash, code size
+    // and destructed accounts
+    let unsupported = tips.iter().position(|tip| {
+        matches!(
+            tip,
+            MPTProofType::PoseidonCodeHashExists
+                | MPTProofType::CodeSizeExists
+                | MPTProofType::AccountDestructed
+        )
+    });
+    match unsupported {
+        Some(index) => Err(CircuitError::UnsupportedProofType {
+            index,
+            proof_type: tips[index],
+        }),
+        None => Ok(tips),
+    }
+}
+
+impl<Fp: Hashable, const LITE: bool> EthTrieCircuit<Fp, LITE> {
+    // the rows required by the main region and the hash table
+    fn required_rows(ops: &[AccountOp<Fp>]) -> (usize, usize) {
+        let region_rows = 1 + ops.iter().map(|op| op.use_rows()).sum::<usize>();
+        let hash_rows = HashTracesSrc::from(ops.iter().flat_map(|op| op.hash_traces())).count();
+        (region_rows, hash_rows)
+    }
+
+    /// check the operations and mpt table fit in the rows of circuit
+    pub fn check_capacity(&self) -> Result<(), CapacityError> {
+        // an entry of mpt table takes fewer rows than its operation in the main region,
+        // so the table is checked first or its overflow would be reported as the region's
+        let table_rows = self.mpt_table.len().min(self.ops.len());
+        if !LITE && table_rows > self.calcs {
+            return Err(CapacityError::MptTable {
+                required: table_rows,
+                available: self.calcs,
+            });
+        }
+        let (region_rows, hash_rows) = Self::required_rows(&self.ops);
+        if region_rows > self.calcs {
+            return Err(CapacityError::MptRegion {
+                required: region_rows,
+                available: self.calcs,
+            });
+        }
+        if hash_rows > self.calcs {
+            return Err(CapacityError::HashTable {
+                required: hash_rows,
+                available: self.calcs,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl<Fp: Hashable> EthTrieCircuit<Fp, true> {
     /// create circuit without mpt table
-    pub fn new_lite(calcs: usize, ops: Vec<AccountOp<Fp>>) -> Self {
-        Self {
+    pub fn new_lite(calcs: usize, ops: Vec<AccountOp<Fp>>) -> Result<Self, CapacityError> {
+        let circuit = Self {
             calcs,
             ops,
             ..Default::default()
-        }
+        };
+        circuit.check_capacity()?;
+        Ok(circuit)
     }
 }
 
 impl<Fp: Hashable> EthTrieCircuit<Fp, false> {
-    /// create circuit
-    pub fn new(calcs: usize, ops: Vec<AccountOp<Fp>>, mpt_table: Vec<MPTProofType>) -> Self {
-        Self {
+    /// create circuit, the mpt table is inferred from operations if [mpt_table] is empty,
+    /// or each proof type in it must be consistent with the corresponding operation
+    pub fn new(
+        calcs: usize,
+        ops: Vec<AccountOp<Fp>>,
+        mpt_table: Vec<MPTProofType>,
+    ) -> Result<Self, CircuitError> {
+        let mpt_table = proof_types(&ops, mpt_table)?;
+        let circuit = Self {
             calcs,
             ops,
             mpt_table,
-        }
+            checkpoints: Vec::new(),
+            segments: Vec::new(),
+        };
+        circuit.check_capacity()?;
+        Ok(circuit)
     }
 
     /// downgrade circuit to lite mode
@@ -696,8 +1149,53 @@ impl<Fp: Hashable> EthTrieCircuit<Fp, false> {
             calcs: self.calcs,
             ops: self.ops,
             mpt_table: Vec::new(),
+            checkpoints: self.checkpoints,
+            segments: self.segments,
         }
     }
+
+    /// take the rlc fields of mpt table over the fixed [BASE] instead of a challenge,
+    /// which make them forgeable and is only for testing
+    #[cfg(test)]
+    pub(crate) fn with_fixed_base<const BASE: u64>(self) -> FixedBaseCircuit<Fp, BASE> {
+        FixedBaseCircuit(self)
+    }
+
+    /// put mpt table under word mode, where storage keys and values are (hi, lo) pairs
+    pub fn with_word_mode(self) -> WordModeCircuit<Fp> {
+        WordModeCircuit(self)
+    }
+}
+
+/// The full mpt circuit whose mpt table is under word mode, created by
+/// `EthTrieCircuit::with_word_mode`. the (hi, lo) pairs in mpt table are not range checked
+#[derive(Clone, Default, Debug)]
+pub struct WordModeCircuit<Fp: FieldExt>(EthTrieCircuit<Fp, false>);
+
+impl<Fp: FieldExt> WordModeCircuit<Fp> {
+    /// the public inputs of circuit, same as the ones of mpt circuit
+    pub fn instance(&self) -> Vec<Vec<Fp>> {
+        self.0.instance()
+    }
+
+    /// the mpt circuit under word mode
+    pub fn mpt_circuit(&self) -> &EthTrieCircuit<Fp, false> {
+        &self.0
+    }
+}
+
+/// The full mpt circuit whose mpt table is taken over a fixed rand base, created by
+/// `EthTrieCircuit::with_fixed_base` for testing
+#[cfg(test)]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct FixedBaseCircuit<Fp: FieldExt, const BASE: u64>(EthTrieCircuit<Fp, false>);
+
+#[cfg(test)]
+impl<Fp: FieldExt, const BASE: u64> FixedBaseCircuit<Fp, BASE> {
+    /// the public inputs of circuit, same as the ones of mpt circuit
+    pub fn instance(&self) -> Vec<Vec<Fp>> {
+        self.0.instance()
+    }
 }
 
 /// a companied hash circuit as the companion of mpt hashes
@@ -705,10 +1203,16 @@ pub struct HashCircuit<F: Hashable>(hash::PoseidonHashTable<F>, usize);
 
 impl<Fp: Hashable> HashCircuit<Fp> {
     /// re-warped, all-in-one creation
-    pub fn new(calcs: usize, input_with_check: &[&(Fp, Fp, Fp)]) -> Self {
+    pub fn new(calcs: usize, input_with_check: &[&(Fp, Fp, Fp)]) -> Result<Self, CapacityError> {
+        if input_with_check.len() > calcs {
+            return Err(CapacityError::HashTable {
+                required: input_with_check.len(),
+                available: calcs,
+            });
+        }
         let mut tbl = PoseidonHashTable::default();
         tbl.constant_inputs_with_check(input_with_check.iter().copied());
-        Self(tbl, calcs)
+        Ok(Self(tbl, calcs))
     }
 }
 
@@ -737,6 +1241,89 @@ impl<Fp: Hashable> Circuit<Fp> for HashCircuit<Fp> {
     }
 }
 
+/// capacity tiers for the `calcs` of circuits, rounding up to one of them keep the vk
+/// stable for operation sequences of different length
+pub const CAPACITY_TIERS: [usize; 6] = [40, 90, 200, 450, 900, 1950];
+
+// the constant tables of transition rules take 2^6 rows
+const MIN_MPT_K: u32 = 6;
+
+// the range check table of bytes in mpt table takes 2^8 rows
+const RANGE_TABLE_ROWS: usize = 1 << 8;
+
+fn log2_ceil(n: usize) -> u32 {
+    usize::BITS - n.saturating_sub(1).leading_zeros()
+}
+
+// rows reserved by halo2 for blinding factors, which can not be assigned
+fn reserved_rows<Fp: FieldExt, C: Circuit<Fp>>() -> usize {
+    let mut cs: ConstraintSystem<Fp> = Default::default();
+    C::configure(&mut cs);
+    cs.minimum_rows()
+}
+
+/// The size of the mpt circuit and accompanied hash circuit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitSize {
+    /// the rows passed to `circuits` or `to_circuits`, for both mpt and hash circuit
+    pub calcs: usize,
+    /// the minimal k of mpt circuit, which also hold the byte range table of mpt table
+    pub mpt_k: u32,
+    /// the minimal k of lite mpt circuit, which has no mpt table
+    pub lite_k: u32,
+    /// the minimal k of hash circuit
+    pub hash_k: u32,
+    /// the minimal k of `CombinedCircuit`
+    pub combined_k: u32,
+}
+
+impl CircuitSize {
+    /// the minimal size of circuits for `calcs` rows
+    pub fn new<Fp: Hashable>(calcs: usize) -> Self {
+        // both the main region and the tables have a leading row besides `calcs`
+        let mpt_rows =
+            (calcs + 1).max(RANGE_TABLE_ROWS) + reserved_rows::<Fp, EthTrieCircuit<Fp, false>>();
+        let lite_rows = calcs + 1 + reserved_rows::<Fp, EthTrieCircuit<Fp, true>>();
+        let hash_rows =
+            (calcs + 1) * Fp::hash_block_size() + reserved_rows::<Fp, HashCircuit<Fp>>();
+        let combined_rows = ((calcs + 1) * Fp::hash_block_size()).max(RANGE_TABLE_ROWS)
+            + reserved_rows::<Fp, CombinedCircuit<Fp>>();
+        Self {
+            calcs,
+            mpt_k: log2_ceil(mpt_rows).max(MIN_MPT_K),
+            lite_k: log2_ceil(lite_rows).max(MIN_MPT_K),
+            hash_k: log2_ceil(hash_rows),
+            combined_k: log2_ceil(combined_rows).max(MIN_MPT_K),
+        }
+    }
+
+    /// round the size up to the smallest tier which can hold it, None if it exceed
+    /// all the tiers
+    pub fn round_up<Fp: Hashable>(&self, tiers: &[usize]) -> Option<Self> {
+        tiers
+            .iter()
+            .copied()
+            .filter(|tier| *tier >= self.calcs)
+            .min()
+            .map(Self::new::<Fp>)
+    }
+}
+
+/// The mpt circuit and its accompanied hash circuit
+pub type CircuitPair<Fp> = (EthTrieCircuit<Fp, false>, HashCircuit<Fp>);
+
+/// The roots of a chunk split from the operations of `EthTrie`, the final root of
+/// each chunk is the start root of the next one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkRoots<Fp> {
+    /// the range of operations in the chunk
+    pub ops: std::ops::Range<usize>,
+    /// the root before the operations of chunk
+    pub start_root: Fp,
+    /// the root after the operations of chunk
+    pub final_root: Fp,
+}
+
 impl<Fp: Hashable> EthTrie<Fp> {
     /// export the hashes involved in current operation sequence
     pub fn hash_traces(&self) -> impl Iterator<Item = &(Fp, Fp, Fp)> + Clone {
@@ -754,8 +1341,17 @@ impl<Fp: Hashable> EthTrie<Fp> {
         (adv_rows.max(hash_rows), hash_rows * Fp::hash_block_size())
     }
 
+    /// Obtain the minimal size of mpt and hash circuits for current operations
+    pub fn circuit_size(&self) -> CircuitSize {
+        let (region_rows, hash_rows) = EthTrieCircuit::<Fp, false>::required_rows(&self.ops);
+        CircuitSize::new::<Fp>(region_rows.max(hash_rows))
+    }
+
     /// Create all associated circuit objects, depecrated
-    pub fn circuits(&self, rows: usize) -> (EthTrieCircuit<Fp, true>, HashCircuit<Fp>) {
+    pub fn circuits(
+        &self,
+        rows: usize,
+    ) -> Result<(EthTrieCircuit<Fp, true>, HashCircuit<Fp>), CircuitError> {
         self.clone().to_circuits_lite((rows, Some(rows)))
     }
 
@@ -763,9 +1359,21 @@ impl<Fp: Hashable> EthTrie<Fp> {
     pub fn to_circuits_lite(
         self,
         rows: (usize, Option<usize>),
-    ) -> (EthTrieCircuit<Fp, true>, HashCircuit<Fp>) {
-        let (mpt_circuit, hash_circuit) = self.to_circuits(rows, &[]);
-        (mpt_circuit.switch_lite(), hash_circuit)
+    ) -> Result<(EthTrieCircuit<Fp, true>, HashCircuit<Fp>), CircuitError> {
+        // no mpt table is laid out in lite mode, so the proof types are left unchecked
+        let (hash_rows, mpt_rows) = rows;
+        let hash_circuit = self.hash_circuit(hash_rows)?;
+        let mut mpt_circuit = EthTrieCircuit::new_lite(mpt_rows.unwrap_or(hash_rows), self.ops)?;
+        mpt_circuit.checkpoints = self.checkpoints;
+        mpt_circuit.segments = self.segments;
+        Ok((mpt_circuit, hash_circuit))
+    }
+
+    // the hash circuit accommodating [rows] hash entries for the operations
+    fn hash_circuit(&self, rows: usize) -> Result<HashCircuit<Fp>, CircuitError> {
+        let hashes: Vec<_> =
+            HashTracesSrc::from(self.ops.iter().flat_map(|op| op.hash_traces())).collect();
+        Ok(HashCircuit::new(rows, &hashes)?)
     }
 
     /// Create all associated circuit objects, better API
@@ -773,21 +1381,35 @@ impl<Fp: Hashable> EthTrie<Fp> {
     /// and the option in rows specify the **circuit** rows mpt circuit would used
     /// without specified it would derived a mpt circuit much larger than the accompanied
     /// hash circuit, i.e: if the mpt circuit has almost fully filled there would be more
-    /// hashes need to be handled than the accompanied hash circuit can accommodate
+    /// hashes need to be handled than the accompanied hash circuit can accommodate.
+    /// [tips] is the proof types of operations in mpt table, they are inferred from
+    /// the operations if it is empty
     pub fn to_circuits(
         self,
         rows: (usize, Option<usize>),
         tips: &[MPTProofType],
-    ) -> (EthTrieCircuit<Fp, false>, HashCircuit<Fp>) {
+    ) -> Result<(EthTrieCircuit<Fp, false>, HashCircuit<Fp>), CircuitError> {
         let (hash_rows, mpt_rows) = rows;
         let mpt_rows = mpt_rows.unwrap_or(hash_rows);
-        let hashes: Vec<_> =
-            HashTracesSrc::from(self.ops.iter().flat_map(|op| op.hash_traces())).collect();
-        let hash_circuit = HashCircuit::new(hash_rows, &hashes);
-        (
-            EthTrieCircuit::new(mpt_rows, self.ops, Vec::from(tips)),
-            hash_circuit,
-        )
+        let hash_circuit = self.hash_circuit(hash_rows)?;
+        let mut mpt_circuit = EthTrieCircuit::new(mpt_rows, self.ops, Vec::from(tips))?;
+        mpt_circuit.checkpoints = self.checkpoints;
+        mpt_circuit.segments = self.segments;
+        Ok((mpt_circuit, hash_circuit))
+    }
+
+    /// Create the circuits for [mode] with [capacity] rows, as `to_circuits` does
+    pub fn to_circuits_in_mode(
+        self,
+        mode: CircuitMode,
+        capacity: usize,
+        tips: &[MPTProofType],
+    ) -> Result<TrieCircuits<Fp>, CircuitError> {
+        let circuits = self.to_circuits((capacity, None), tips)?;
+        Ok(match mode {
+            CircuitMode::Split => TrieCircuits::Split(circuits),
+            CircuitMode::Combined => TrieCircuits::Combined(circuits.into()),
+        })
     }
 
     /// Create all associated circuit objects, with specificing the maxium rows circuit
@@ -796,12 +1418,91 @@ impl<Fp: Hashable> EthTrie<Fp> {
         self,
         maxium_circuit_rows: usize,
         tips: &[MPTProofType],
-    ) -> (EthTrieCircuit<Fp, false>, HashCircuit<Fp>) {
+    ) -> Result<(EthTrieCircuit<Fp, false>, HashCircuit<Fp>), CircuitError> {
         self.to_circuits((maxium_circuit_rows / Fp::hash_block_size(), None), tips)
     }
+
+    /// Split the operations into consecutive chunks, each fits in a pair of circuits
+    /// created with `capacity` rows. [tips] is the mpt table for all operations (or empty
+    /// for inferring it) and is split along with them. the chunks are returned with their roots, which chain
+    /// one chunk into the next unless a segment starts at the border, where the chunk exposes
+    /// the segment break in its instance
+    pub fn split(
+        &self,
+        capacity: usize,
+        tips: &[MPTProofType],
+    ) -> Result<(Vec<CircuitPair<Fp>>, Vec<ChunkRoots<Fp>>), CircuitError> {
+        if !tips.is_empty() && tips.len() != self.ops.len() {
+            return Err(CircuitError::TipCount {
+                tips: tips.len(),
+                ops: self.ops.len(),
+            });
+        }
+        let mut circuits = Vec::new();
+        let mut manifest = Vec::new();
+        let mut start = 0;
+        while start < self.ops.len() {
+            // the rows of chunk are accumulated as ops join it, as `required_rows` counts
+            // them, i.e. the hashes are deduplicated over the chunk
+            let mut region_rows = 1;
+            let mut hashes = HashSet::new();
+            let mut end = start;
+            while end < self.ops.len() {
+                let op = &self.ops[end];
+                let new_hashes: HashSet<_> = op
+                    .hash_traces()
+                    .map(|trace| HashableField::from(trace.2))
+                    .filter(|hash| !hashes.contains(hash))
+                    .collect();
+                let fits = region_rows + op.use_rows() <= capacity
+                    && hashes.len() + new_hashes.len() <= capacity;
+                // the first op always joins, so an oversized one is reported below
+                if !fits && end > start {
+                    break;
+                }
+                region_rows += op.use_rows();
+                hashes.extend(new_hashes);
+                end += 1;
+            }
+
+            let chunk = EthTrie {
+                start_root: self.ops[start].account_root_before(),
+                final_root: self.ops[end - 1].account_root(),
+                ops: self.ops[start..end].to_vec(),
+                checkpoints: self
+                    .checkpoints
+                    .iter()
+                    .filter(|i| (start..end).contains(*i))
+                    .map(|i| i - start)
+                    .collect(),
+                segments: self
+                    .segments
+                    .iter()
+                    .filter(|i| (start..end).contains(*i))
+                    .map(|i| i - start)
+                    .collect(),
+            };
+            let chunk_tips = if tips.is_empty() {
+                &[]
+            } else {
+                &tips[start..end]
+            };
+            manifest.push(ChunkRoots {
+                ops: start..end,
+                start_root: chunk.start_root,
+                final_root: chunk.final_root,
+            });
+            // a single operation exceeding the capacity is reported here
+            circuits.push(chunk.to_circuits((capacity, None), chunk_tips)?);
+            start = end;
+        }
+        Ok((circuits, manifest))
+    }
 }
 
-/// index for hash table's commitments
+/// positions of the hash table's and mpt table's advice commitments in the proof
+/// transcript. advice commitments are written phase by phase, so a position differs
+/// from the index of column once a column of later phase is allocated before it
 pub struct CommitmentIndexs(usize, usize, Option<usize>);
 
 impl CommitmentIndexs {
@@ -823,17 +1524,19 @@ impl CommitmentIndexs {
         (self.0 + 2, self.1 + 2)
     }
 
-    /// the beginning of hash table index
+    /// the position of the first hash table commitment, the 5 columns of hash table
+    /// are all in the first phase so their commitments follow one by one
     pub fn hash_tbl_begin(&self) -> usize {
         self.0
     }
 
-    /// the beginning of hash table index, at the accompanied hash circuit
+    /// the position of the first hash table commitment, at the accompanied hash circuit
     pub fn hash_tbl_begin_at_accompanied_circuit(&self) -> usize {
         self.1
     }
 
-    /// the beginning of mpt table index
+    /// the position of the commitment of mpt table's address column, the columns of
+    /// mpt table in later phases are written after all first phase commitments
     pub fn mpt_tbl_begin(&self) -> usize {
         self.2.expect("only call for non-lite circuit")
     }
@@ -844,13 +1547,15 @@ impl CommitmentIndexs {
         let config = EthTrieCircuit::<_, true>::configure(&mut cs);
 
         let trie_circuit_indexs = config.hash_tbl.commitment_index();
+        let trie_circuit_begin = commitment_position(&cs, trie_circuit_indexs[0]);
 
         let mut cs: ConstraintSystem<Fp> = Default::default();
         let config = HashCircuit::configure(&mut cs);
 
         let hash_circuit_indexs = config.commitment_index();
+        let hash_circuit_begin = commitment_position(&cs, hash_circuit_indexs[0]);
 
-        Self(trie_circuit_indexs[0], hash_circuit_indexs[0], None)
+        Self(trie_circuit_begin, hash_circuit_begin, None)
     }
 
     /// get commitment for full circuit
@@ -859,24 +1564,36 @@ impl CommitmentIndexs {
         let config = EthTrieCircuit::<_, false>::configure(&mut cs);
 
         let trie_circuit_indexs = config.hash_tbl.commitment_index();
+        let trie_circuit_begin = commitment_position(&cs, trie_circuit_indexs[0]);
         let mpt_table_start = config
             .mpt_tbl
             .expect("should has mpt table")
             .mpt_table_begin_index();
+        let mpt_table_begin = commitment_position(&cs, mpt_table_start);
 
         let mut cs: ConstraintSystem<Fp> = Default::default();
         let config = HashCircuit::configure(&mut cs);
 
         let hash_circuit_indexs = config.commitment_index();
+        let hash_circuit_begin = commitment_position(&cs, hash_circuit_indexs[0]);
 
         Self(
-            trie_circuit_indexs[0],
-            hash_circuit_indexs[0],
-            Some(mpt_table_start),
+            trie_circuit_begin,
+            hash_circuit_begin,
+            Some(mpt_table_begin),
         )
     }
 }
 
+// the position of advice commitment for [column] in proof, the commitments are written
+// phase by phase and in the order of columns inside each phase
+fn commitment_position<Fp: FieldExt>(cs: &ConstraintSystem<Fp>, column: usize) -> usize {
+    let phases = cs.advice_column_phase();
+    let phase = phases[column];
+    phases.iter().filter(|p| **p < phase).count()
+        + phases[..column].iter().filter(|p| **p == phase).count()
+}
+
 const TEMP_RANDOMNESS: u64 = 1;
 
 impl<Fp: Hashable, const LITE: bool> Circuit<Fp> for EthTrieCircuit<Fp, LITE> {
@@ -888,17 +1605,18 @@ impl<Fp: Hashable, const LITE: bool> Circuit<Fp> for EthTrieCircuit<Fp, LITE> {
             calcs: self.calcs,
             ops: Vec::new(),
             mpt_table: Vec::new(),
+            checkpoints: Vec::new(),
+            segments: Vec::new(),
         }
     }
 
     fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
         if LITE {
-            EthTrieConfig::configure_lite(meta)
+            let config = EthTrieConfig::configure_lite(meta);
+            let instance = meta.instance_column();
+            config.with_instance(meta, instance)
         } else {
-            let base = [0; 7].map(|_| meta.advice_column());
-            let hash_tbl = [0; 5].map(|_| meta.advice_column());
-            let randomness = Expression::Constant(Fp::from(get_rand_base()));
-            EthTrieConfig::configure_sub(meta, base, hash_tbl, randomness)
+            configure_full(meta).0
         }
     }
 
@@ -912,13 +1630,90 @@ impl<Fp: Hashable, const LITE: bool> Circuit<Fp> for EthTrieCircuit<Fp, LITE> {
             self.ops.iter().flat_map(|op| op.hash_traces()),
             self.calcs,
         )?;
-        config.synthesize_core(&mut layouter, self.ops.iter(), self.calcs)?;
+        self.synthesize_without_hash_table(&config, &mut layouter)
+    }
+}
+
+#[cfg(test)]
+impl<Fp: Hashable, const BASE: u64> Circuit<Fp> for FixedBaseCircuit<Fp, BASE> {
+    type Config = EthTrieConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self(self.0.without_witnesses())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        configure_full_with(meta, RlcRandomness::Fixed(BASE)).0
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.0.synthesize(config, layouter)
+    }
+}
+
+impl<Fp: Hashable> Circuit<Fp> for WordModeCircuit<Fp> {
+    type Config = EthTrieConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self(self.0.without_witnesses())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let mpt_tbl = MptTable::construct_word(meta);
+        let hash_tbl = [0; 5].map(|_| meta.advice_column());
+        let config = EthTrieConfig::configure_word(meta, mpt_tbl, hash_tbl);
+        let instance = meta.instance_column();
+        config.with_instance(meta, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.0.synthesize(config, layouter)
+    }
+}
+
+// configure the full mpt circuit, along with the columns of its hash table
+fn configure_full<Fp: FieldExt>(
+    meta: &mut ConstraintSystem<Fp>,
+) -> (EthTrieConfig, [Column<Advice>; 5]) {
+    // storage key and values in mpt table are rlc over the challenge
+    let randomness = RlcRandomness::Challenge(meta.challenge_usable_after(FirstPhase));
+    configure_full_with(meta, randomness)
+}
+
+// configure the full mpt circuit over the given randomness
+fn configure_full_with<Fp: FieldExt>(
+    meta: &mut ConstraintSystem<Fp>,
+    randomness: RlcRandomness,
+) -> (EthTrieConfig, [Column<Advice>; 5]) {
+    let mpt_tbl = MptTable::construct(meta);
+    let hash_tbl = [0; 5].map(|_| meta.advice_column());
+    let config = EthTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl, randomness);
+    let instance = meta.instance_column();
+    (config.with_instance(meta, instance), hash_tbl)
+}
+
+impl<Fp: Hashable, const LITE: bool> EthTrieCircuit<Fp, LITE> {
+    // synthesize the main region and the mpt table, leaving the hash table to the caller
+    fn synthesize_without_hash_table(
+        &self,
+        config: &EthTrieConfig,
+        layouter: &mut impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        config.synthesize_core_with_checkpoints(
+            layouter,
+            self.ops.iter(),
+            (&self.checkpoints, &self.segments),
+            self.calcs,
+        )?;
         if LITE {
             Ok(())
         } else {
+            let randomness = config.randomness(&*layouter);
             config.load_mpt_table(
-                &mut layouter,
-                Some(Fp::from(get_rand_base())),
+                layouter,
+                Some(randomness),
                 self.ops.as_slice(),
                 self.mpt_table.iter().copied(),
                 self.calcs,
@@ -927,6 +1722,101 @@ impl<Fp: Hashable, const LITE: bool> Circuit<Fp> for EthTrieCircuit<Fp, LITE> {
     }
 }
 
+/// The mpt circuit and its accompanied hash circuit configured in one constraint system,
+/// where the poseidon chip fills the hash table looked up by mpt circuit, so both of them
+/// are proven at once
+pub struct CombinedCircuit<Fp: Hashable> {
+    mpt: EthTrieCircuit<Fp, false>,
+    hash: HashCircuit<Fp>,
+}
+
+impl<Fp: Hashable> From<CircuitPair<Fp>> for CombinedCircuit<Fp> {
+    fn from((mpt, hash): CircuitPair<Fp>) -> Self {
+        Self { mpt, hash }
+    }
+}
+
+impl<Fp: Hashable> CombinedCircuit<Fp> {
+    /// the public inputs of circuit, same as the ones of mpt circuit
+    pub fn instance(&self) -> Vec<Vec<Fp>> {
+        self.mpt.instance()
+    }
+
+    /// the mpt circuit being combined
+    pub fn mpt_circuit(&self) -> &EthTrieCircuit<Fp, false> {
+        &self.mpt
+    }
+
+    /// split into the mpt circuit and the hash circuit
+    pub fn into_pair(self) -> CircuitPair<Fp> {
+        (self.mpt, self.hash)
+    }
+}
+
+impl<Fp: Hashable> Circuit<Fp> for CombinedCircuit<Fp> {
+    type Config = (EthTrieConfig, hash::PoseidonHashConfig<Fp>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            mpt: self.mpt.without_witnesses(),
+            hash: self.hash.without_witnesses(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        // the five columns of hash table are shared by both circuits
+        let (mpt_config, hash_tbl) = configure_full(meta);
+        let hash_config =
+            hash::PoseidonHashConfig::configure_sub(meta, hash_tbl, hash_circuit::DEFAULT_STEP);
+        (mpt_config, hash_config)
+    }
+
+    fn synthesize(
+        &self,
+        (mpt_config, hash_config): Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let chip = hash::PoseidonHashChip::<Fp, { hash_circuit::DEFAULT_STEP }>::construct(
+            hash_config,
+            &self.hash.0,
+            self.hash.1,
+            true,
+            None,
+        );
+        chip.load(&mut layouter)?;
+        self.mpt
+            .synthesize_without_hash_table(&mpt_config, &mut layouter)
+    }
+}
+
+/// How the mpt circuit and its accompanied hash circuit are proven
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitMode {
+    /// two proofs linked by the commitments of hash table, see `CommitmentIndexs`
+    Split,
+    /// one proof of `CombinedCircuit`
+    Combined,
+}
+
+/// The circuits created for operations under a `CircuitMode`
+pub enum TrieCircuits<Fp: Hashable> {
+    /// the mpt circuit and the hash circuit
+    Split(CircuitPair<Fp>),
+    /// the combined circuit
+    Combined(CombinedCircuit<Fp>),
+}
+
+impl<Fp: Hashable> TrieCircuits<Fp> {
+    /// the public inputs, which are the same in both modes
+    pub fn instance(&self) -> Vec<Vec<Fp>> {
+        match self {
+            Self::Split((mpt, _)) => mpt.instance(),
+            Self::Combined(circuit) => circuit.instance(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -947,6 +1837,12 @@ mod test {
 
         println!("hash circuit degree: {}", cs.degree());
         assert!(cs.degree() <= 9);
+
+        let mut cs: ConstraintSystem<Fp> = Default::default();
+        CombinedCircuit::configure(&mut cs);
+
+        println!("combined circuit degree: {}", cs.degree());
+        assert!(cs.degree() <= 9);
     }
 
     #[test]
@@ -959,14 +1855,13 @@ mod test {
     fn empty_eth_trie() {
         let k = 9;
         let data: EthTrie<Fp> = Default::default();
-        let (circuit, _) = data.to_circuits((20, None), &[]);
+        let (circuit, _) = data.to_circuits((20, None), &[]).unwrap();
 
-        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        let prover = MockProver::<Fp>::run(k, &circuit, circuit.instance()).unwrap();
         assert_eq!(prover.verify(), Ok(()));
     }
 
-    #[test]
-    fn rand_eth_trie() {
+    fn rand_storage_op() -> AccountOp<Fp> {
         let store_key = KeyValue::create_rand(mock_hash);
         let store_before = KeyValue::create_rand(mock_hash);
         let store_after = KeyValue::create_rand(mock_hash);
@@ -1009,7 +1904,7 @@ mod test {
             mock_hash,
         );
 
-        let op1 = AccountOp::<Fp> {
+        AccountOp::<Fp> {
             acc_trie,
             state_trie: Some(state_trie),
             account_after: Some(account_after),
@@ -1020,8 +1915,12 @@ mod test {
             store_before: Some(store_before),
             store_after: Some(store_after),
             ..Default::default()
-        };
+        }
+    }
 
+    #[test]
+    fn rand_eth_trie() {
+        let op1 = rand_storage_op();
         let start_root = op1.account_root_before();
         let final_root = op1.account_root();
 
@@ -1030,15 +1929,731 @@ mod test {
             start_root,
             final_root,
             ops: vec![op1],
+            ..Default::default()
         };
 
-        let (circuit, _) = trie.to_circuits((40, None), &[MPTProofType::StorageChanged]);
+        let (circuit, _) = trie
+            .to_circuits((40, None), &[MPTProofType::StorageChanged])
+            .unwrap();
 
         #[cfg(feature = "print_layout")]
         print_layout!("layouts/eth_trie_layout.png", k, &circuit);
 
-        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(circuit.instance(), vec![vec![start_root, final_root]]);
+        let prover = MockProver::<Fp>::run(k, &circuit, circuit.instance()).unwrap();
         let ret = prover.verify();
         assert_eq!(ret, Ok(()), "{:#?}", ret);
+
+        // the proof can not be bound to another final root
+        let prover =
+            MockProver::<Fp>::run(k, &circuit, vec![vec![start_root, start_root]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn circuit_size() {
+        let op = rand_storage_op();
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+
+        let size = trie.circuit_size();
+        assert!(size.round_up::<Fp>(&[size.calcs - 1]).is_none());
+        let tiered = size.round_up::<Fp>(&CAPACITY_TIERS).unwrap();
+        assert!(tiered.calcs >= size.calcs);
+        assert!(tiered.mpt_k >= size.mpt_k && tiered.hash_k >= size.hash_k);
+
+        let (circuit, hash_circuit) = trie
+            .to_circuits((size.calcs, None), &[MPTProofType::StorageChanged])
+            .unwrap();
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+        let prover = MockProver::<Fp>::run(size.hash_k, &hash_circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn circuit_size_small_capacity() {
+        let op = rand_storage_op();
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+        let size = trie.circuit_size();
+        // the byte range table of mpt table is larger than the operations
+        assert!(size.mpt_k > size.lite_k);
+
+        let (circuit, _) = trie
+            .to_circuits((size.calcs, None), &[MPTProofType::StorageChanged])
+            .unwrap();
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+        assert!(MockProver::<Fp>::run(size.mpt_k - 1, &circuit, circuit.instance()).is_err());
+
+        let circuit = circuit.switch_lite();
+        let prover = MockProver::<Fp>::run(size.lite_k, &circuit, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn combined_circuit() {
+        let op = rand_storage_op();
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+        let size = trie.circuit_size();
+        // the byte range table of mpt table fits besides the reserved rows
+        assert!(
+            1 << size.combined_k >= RANGE_TABLE_ROWS + reserved_rows::<Fp, CombinedCircuit<Fp>>()
+        );
+
+        let circuits = trie
+            .clone()
+            .to_circuits_in_mode(CircuitMode::Combined, size.calcs, &[])
+            .unwrap();
+        let instance = circuits.instance();
+        let circuit = match circuits {
+            TrieCircuits::Combined(circuit) => circuit,
+            TrieCircuits::Split(_) => unreachable!(),
+        };
+        let prover = MockProver::<Fp>::run(size.combined_k, &circuit, instance.clone()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+        assert!(MockProver::<Fp>::run(size.combined_k - 1, &circuit, instance).is_err());
+
+        // the hashes of mpt circuit are missed in the hash table of an empty trie
+        let (_, hash_circuit) = EthTrie::<Fp>::default()
+            .to_circuits((size.calcs, None), &[])
+            .unwrap();
+        let circuit = CombinedCircuit::from((circuit.into_pair().0, hash_circuit));
+        let prover = MockProver::<Fp>::run(size.combined_k, &circuit, circuit.instance()).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn capacity_errors() {
+        let op = rand_storage_op();
+        let region_rows = op.use_rows() + 1;
+        let hash_rows = HashTracesSrc::from(op.hash_traces()).count();
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+
+        let calcs = trie.circuit_size().calcs;
+        assert!(trie.circuits(calcs).is_ok());
+        let expected: CircuitError = if region_rows >= hash_rows {
+            CapacityError::MptRegion {
+                required: region_rows,
+                available: calcs - 1,
+            }
+        } else {
+            CapacityError::HashTable {
+                required: hash_rows,
+                available: calcs - 1,
+            }
+        }
+        .into();
+        assert_eq!(trie.circuits(calcs - 1).err(), Some(expected));
+
+        // the accompanied hash circuit is checked separately
+        assert_eq!(
+            trie.clone()
+                .to_circuits((hash_rows - 1, Some(calcs)), &[])
+                .err(),
+            Some(
+                CapacityError::HashTable {
+                    required: hash_rows,
+                    available: hash_rows - 1,
+                }
+                .into()
+            )
+        );
+
+        // the mpt table of full circuit has a row for each operation
+        assert_eq!(
+            EthTrieCircuit::<Fp, false>::new(0, trie.ops.clone(), Vec::new()).err(),
+            Some(
+                CapacityError::MptTable {
+                    required: 1,
+                    available: 0,
+                }
+                .into()
+            )
+        );
+    }
+
+    // a sequence of chained operations creating accounts
+    fn chained_ops(n: u8) -> Vec<AccountOp<Fp>> {
+        let mut state = crate::trie::StateTrie::<Fp>::default();
+        let traces: Vec<_> = (1..=n)
+            .map(|i| {
+                let account = crate::serde::AccountData {
+                    nonce: i.into(),
+                    balance: Default::default(),
+                    code_hash: Default::default(),
+                    poseidon_code_hash: Default::default(),
+                    code_size: 0,
+                };
+                state
+                    .update_account(crate::serde::HexBytes([i; 20]), account)
+                    .unwrap()
+            })
+            .collect();
+        ops_from_traces(&traces).unwrap()
+    }
+
+    #[test]
+    fn split_eth_trie() {
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(chained_ops(6));
+        // room for two operations in each chunk
+        let capacity = trie.ops[..3].iter().map(|op| op.use_rows()).sum();
+        let tips = vec![MPTProofType::NonceChanged; trie.ops.len()];
+
+        let (circuits, manifest) = trie.split(capacity, &tips).unwrap();
+        assert!(circuits.len() > 1);
+        assert_eq!(circuits.len(), manifest.len());
+        assert_eq!(manifest[0].start_root, trie.start_root());
+        assert_eq!(manifest.last().unwrap().final_root, trie.final_root());
+        assert_eq!(manifest.last().unwrap().ops.end, trie.ops.len());
+        for pair in manifest.windows(2) {
+            assert_eq!(pair[0].final_root, pair[1].start_root);
+            assert_eq!(pair[0].ops.end, pair[1].ops.start);
+        }
+
+        let k = CircuitSize::new::<Fp>(capacity).mpt_k;
+        for ((circuit, _), roots) in circuits.iter().zip(&manifest) {
+            assert_eq!(
+                circuit.instance(),
+                vec![vec![roots.start_root, roots.final_root]]
+            );
+            let prover = MockProver::<Fp>::run(k, circuit, circuit.instance()).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+
+        // an operation can not be split
+        let op_rows = trie.ops[0].use_rows();
+        assert!(matches!(
+            trie.split(op_rows, &tips),
+            Err(CircuitError::Capacity(
+                CapacityError::MptRegion { .. } | CapacityError::HashTable { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn infer_proof_types() {
+        assert_eq!(rand_storage_op().proof_type(), MPTProofType::StorageChanged);
+
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(chained_ops(2));
+        // creating accounts with nonce
+        let size = trie.circuit_size();
+        let (circuit, _) = trie.clone().to_circuits((size.calcs, None), &[]).unwrap();
+        assert_eq!(circuit.mpt_table, vec![MPTProofType::NonceChanged; 2]);
+        let rows = trie.mpt_table_rows(Fp::from(0x100), &[]).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows
+            .iter()
+            .all(|row| row.proof_type == MPTProofType::NonceChanged));
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let tips = [MPTProofType::NonceChanged, MPTProofType::BalanceChanged];
+        assert_eq!(
+            trie.clone().to_circuits((size.calcs, None), &tips).err(),
+            Some(CircuitError::ProofType {
+                index: 1,
+                tip: MPTProofType::BalanceChanged,
+                inferred: MPTProofType::NonceChanged,
+            })
+        );
+        assert_eq!(
+            trie.to_circuits((size.calcs, None), &tips[..1]).err(),
+            Some(CircuitError::TipCount { tips: 1, ops: 2 })
+        );
+
+        // mpt table can not bind the entries of code size to mpt circuit
+        let account = crate::serde::AccountData {
+            nonce: Default::default(),
+            balance: Default::default(),
+            code_hash: Default::default(),
+            poseidon_code_hash: Default::default(),
+            code_size: 5,
+        };
+        let mut state = crate::trie::StateTrie::<Fp>::default();
+        let address = crate::serde::HexBytes([1; 20]);
+        let trace = state.update_account(address, account).unwrap();
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(ops_from_traces(&[trace]).unwrap());
+        assert_eq!(trie.ops[0].proof_type(), MPTProofType::CodeSizeExists);
+        assert_eq!(
+            trie.clone().to_circuits((size.calcs, None), &[]).err(),
+            Some(CircuitError::UnsupportedProofType {
+                index: 0,
+                proof_type: MPTProofType::CodeSizeExists,
+            })
+        );
+        // nor the entries of destructed accounts
+        let trace = state.delete_account(address).unwrap();
+        let mut destructed = EthTrie::<Fp>::default();
+        destructed.add_ops(ops_from_traces(&[trace]).unwrap());
+        assert_eq!(
+            destructed.ops[0].proof_type(),
+            MPTProofType::AccountDestructed
+        );
+        assert_eq!(
+            destructed.mpt_table_rows(Fp::from(0x100), &[]).err(),
+            Some(CircuitError::UnsupportedProofType {
+                index: 0,
+                proof_type: MPTProofType::AccountDestructed,
+            })
+        );
+        // while the lite circuits have no mpt table to bind
+        assert!(trie.to_circuits_lite((size.calcs, None)).is_ok());
+        assert!(destructed.to_circuits_lite((size.calcs, None)).is_ok());
+    }
+
+    #[test]
+    fn checkpoint_roots() {
+        let ops = chained_ops(4);
+        let checkpoint_roots = [ops[0].account_root(), ops[2].account_root()];
+        let mut trie = EthTrie::<Fp>::default();
+        for (i, op) in ops.into_iter().enumerate() {
+            trie.add_op_with_checkpoint(op, i % 2 == 0);
+        }
+        assert_eq!(trie.checkpoints(), &[0, 2]);
+
+        let size = trie.circuit_size();
+        let (start_root, final_root) = (trie.start_root(), trie.final_root());
+        let (circuit, _) = trie.circuits(size.calcs).unwrap();
+        let instance = vec![
+            start_root,
+            final_root,
+            Fp::from(2),
+            Fp::zero(),
+            checkpoint_roots[0],
+            Fp::zero(),
+            checkpoint_roots[1],
+        ];
+        assert_eq!(circuit.instance(), vec![instance.clone()]);
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, vec![instance.clone()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // every checkpoint is bound, and none of them can be dropped
+        let mut wrong_root = instance.clone();
+        wrong_root[6] = checkpoint_roots[0];
+        let mut dropped = instance[..5].to_vec();
+        dropped[2] = Fp::one();
+        for wrong_instance in [wrong_root, dropped, instance[..2].to_vec()] {
+            let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, vec![wrong_instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn segment_roots() {
+        let (first, second) = (chained_ops(2), chained_ops(3));
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(first.clone());
+        trie.start_segment();
+        trie.add_ops(second.clone());
+        assert_eq!(trie.checkpoints(), &[1]);
+        assert_eq!(trie.segments(), &[2]);
+        assert_eq!(
+            trie.segment_roots(),
+            vec![
+                (first[0].account_root_before(), first[1].account_root()),
+                (second[0].account_root_before(), second[2].account_root()),
+            ]
+        );
+
+        let size = trie.circuit_size();
+        let (start_root, final_root) = (trie.start_root(), trie.final_root());
+        let (circuit, _) = trie.circuits(size.calcs).unwrap();
+        let instance = vec![
+            start_root,
+            final_root,
+            Fp::from(2),
+            Fp::zero(),
+            first[1].account_root(),
+            Fp::one(),
+            second[0].account_root_before(),
+        ];
+        assert_eq!(circuit.instance(), vec![instance.clone()]);
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, vec![instance.clone()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // a segment break can not be passed off as an ordinary checkpoint
+        let mut wrong_kind = instance.clone();
+        wrong_kind[5] = Fp::zero();
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, vec![wrong_kind]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // without the break the roots must be chained
+        let mut unmarked = circuit;
+        unmarked.segments.clear();
+        let prover = MockProver::<Fp>::run(size.mpt_k, &unmarked, unmarked.instance()).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn split_at_segment() {
+        let (first, second) = (chained_ops(2), chained_ops(3));
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(first.clone());
+        trie.start_segment();
+        trie.add_ops(second.clone());
+        // room for two operations in each chunk, so the second chunk starts the segment
+        let capacity = trie.ops[..3].iter().map(|op| op.use_rows()).sum();
+
+        let (circuits, manifest) = trie.split(capacity, &[]).unwrap();
+        assert_eq!(manifest[1].ops.start, 2);
+        assert_ne!(manifest[0].final_root, manifest[1].start_root);
+        let (first_chunk, second_chunk) = (&circuits[0].0, &circuits[1].0);
+        assert_eq!(
+            first_chunk.instance()[0][2..],
+            [Fp::one(), Fp::zero(), first[1].account_root()]
+        );
+        assert_eq!(
+            second_chunk.instance()[0][2..],
+            [Fp::one(), Fp::one(), second[0].account_root_before()]
+        );
+
+        let k = CircuitSize::new::<Fp>(capacity).mpt_k;
+        for (circuit, _) in &circuits {
+            let prover = MockProver::<Fp>::run(k, circuit, circuit.instance()).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn concurrent_rand_base() {
+        let op = rand_storage_op();
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+        let (circuit, _) = trie
+            .to_circuits((40, None), &[MPTProofType::StorageChanged])
+            .unwrap();
+
+        let k = 9;
+        let circuit_a = circuit.clone().with_fixed_base::<0x100>();
+        let circuit_b = circuit.with_fixed_base::<0x10000>();
+
+        let prover_a = std::thread::spawn(move || {
+            MockProver::<Fp>::run(k, &circuit_a, circuit_a.instance())
+                .unwrap()
+                .verify()
+        });
+        let prover_b = std::thread::spawn(move || {
+            MockProver::<Fp>::run(k, &circuit_b, circuit_b.instance())
+                .unwrap()
+                .verify()
+        });
+
+        assert_eq!(prover_a.join().unwrap(), Ok(()));
+        assert_eq!(prover_b.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "set_rand_base(0x100) is not supported")]
+    #[allow(deprecated)]
+    fn global_rand_base() {
+        set_rand_base(0x100);
+    }
+
+    const LOOKUP_BASE: u64 = 0x100;
+
+    // an external circuit looking up an entry from the mpt table it shares, which may also
+    // plant a forged row into the table columns below the rows of table
+    #[derive(Clone, Default)]
+    struct LookupCircuit(EthTrieCircuit<Fp, false>, [Fp; 7], Option<[Fp; 7]>);
+
+    impl Circuit<Fp> for LookupCircuit {
+        type Config = (
+            EthTrieConfig,
+            MptTable,
+            [Column<Advice>; 7],
+            halo2_proofs::plonk::Selector,
+        );
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self(self.0.without_witnesses(), [Fp::zero(); 7], None)
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let mpt_tbl = MptTable::construct(meta);
+            let hash_tbl = [0; 5].map(|_| meta.advice_column());
+            let randomness = RlcRandomness::Fixed(LOOKUP_BASE);
+            let instance = meta.instance_column();
+            let config = EthTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl, randomness)
+                .with_instance(meta, instance);
+
+            let inputs = [0; 7].map(|_| meta.advice_column());
+            let sel = meta.complex_selector();
+            mpt_tbl.lookup_any(meta, "external mpt lookup", |meta| {
+                let sel = meta.query_selector(sel);
+                inputs.map(|col| {
+                    sel.clone() * meta.query_advice(col, halo2_proofs::poly::Rotation::cur())
+                })
+            });
+            (config, mpt_tbl, inputs, sel)
+        }
+
+        fn synthesize(
+            &self,
+            (config, mpt_tbl, inputs, sel): Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            self.0.synthesize(config, layouter.namespace(|| "mpt"))?;
+            if let Some(forged) = self.2 {
+                // in the layout of `MptTable::columns`
+                let [proof_type, address, key, old_value, new_value, old_root, new_root] = forged;
+                let row = [
+                    address, key, proof_type, new_root, old_root, new_value, old_value,
+                ];
+                layouter.assign_region(
+                    || "forged mpt row",
+                    |mut region| {
+                        for (col, val) in mpt_tbl.columns().iter().zip(row) {
+                            region.assign_advice(|| "forged", *col, 0, || Value::known(val))?;
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+            layouter.assign_region(
+                || "external lookup",
+                |mut region| {
+                    sel.enable(&mut region, 0)?;
+                    for (col, val) in inputs.iter().zip(self.1) {
+                        region.assign_advice(|| "lookup input", *col, 0, || Value::known(val))?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn external_mpt_lookup() {
+        let op = rand_storage_op();
+        let row = MptTableRow::from_op(MPTProofType::StorageChanged, &op, Fp::from(LOOKUP_BASE));
+        let fp = |h: Hash| Fp::from_bytes(&h.0).unwrap();
+        let entry = [
+            Fp::from(MPTProofType::StorageChanged as u64),
+            fp(row.address),
+            fp(row.storage_key),
+            fp(row.old_value),
+            fp(row.new_value),
+            fp(row.old_root),
+            fp(row.new_root),
+        ];
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+        let (circuit, _) = trie.to_circuits((40, None), &[]).unwrap();
+
+        let k = 9;
+        let lookup = LookupCircuit(circuit.clone(), entry, None);
+        let prover = MockProver::<Fp>::run(k, &lookup, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let mut wrong_entry = entry;
+        wrong_entry[4] += Fp::one();
+        let lookup = LookupCircuit(circuit.clone(), wrong_entry, None);
+        let prover = MockProver::<Fp>::run(k, &lookup, circuit.instance()).unwrap();
+        assert!(prover.verify().is_err());
+
+        // a row planted outside the table can not be looked up
+        let lookup = LookupCircuit(circuit.clone(), wrong_entry, Some(wrong_entry));
+        let prover = MockProver::<Fp>::run(k, &lookup, circuit.instance()).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    // full circuit exposing the permuted copy of mpt table
+    #[derive(Clone, Default)]
+    struct PermutedTableCircuit(EthTrieCircuit<Fp, false>);
+
+    impl Circuit<Fp> for PermutedTableCircuit {
+        type Config = EthTrieConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self(self.0.without_witnesses())
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let mpt_tbl = MptTable::construct(meta);
+            let hash_tbl = [0; 5].map(|_| meta.advice_column());
+            let randomness = RlcRandomness::Fixed(LOOKUP_BASE);
+            let instance = meta.instance_column();
+            EthTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl, randomness)
+                .with_instance(meta, instance)
+                .with_permuted_mpt_table(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            self.0.synthesize(config, layouter)
+        }
+    }
+
+    #[test]
+    fn permuted_mpt_table() {
+        // the accounts are updated out of the order of their addresses
+        let mut state = crate::trie::StateTrie::<Fp>::default();
+        let traces: Vec<_> = [2u8, 1, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, addr)| {
+                let account = crate::serde::AccountData {
+                    nonce: i as u64 + 1,
+                    balance: Default::default(),
+                    code_hash: Default::default(),
+                    poseidon_code_hash: Default::default(),
+                    code_size: 0,
+                };
+                state
+                    .update_account(crate::serde::HexBytes([addr; 20]), account)
+                    .unwrap()
+            })
+            .collect();
+        let mut trie = EthTrie::<Fp>::default();
+        trie.add_ops(ops_from_traces(&traces).unwrap());
+
+        let size = trie.circuit_size();
+        let (circuit, _) = trie.to_circuits((size.calcs, None), &[]).unwrap();
+        let instance = circuit.instance();
+        let circuit = PermutedTableCircuit(circuit);
+        let prover = MockProver::<Fp>::run(size.mpt_k, &circuit, instance).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let mut cs = ConstraintSystem::<Fp>::default();
+        assert!(PermutedTableCircuit::configure(&mut cs)
+            .permuted_mpt_table()
+            .is_some());
+        assert!(cs.degree() <= 9);
+    }
+
+    // an external circuit looking up an entry from the mpt table under word mode
+    #[derive(Clone, Default)]
+    struct WordLookupCircuit(EthTrieCircuit<Fp, false>, [Fp; 10]);
+
+    impl Circuit<Fp> for WordLookupCircuit {
+        type Config = (
+            EthTrieConfig,
+            [Column<Advice>; 10],
+            halo2_proofs::plonk::Selector,
+        );
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self(self.0.without_witnesses(), [Fp::zero(); 10])
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let mpt_tbl = MptTable::construct_word(meta);
+            let hash_tbl = [0; 5].map(|_| meta.advice_column());
+            let instance = meta.instance_column();
+            let config = EthTrieConfig::configure_word(meta, mpt_tbl, hash_tbl)
+                .with_instance(meta, instance);
+
+            let inputs = [0; 10].map(|_| meta.advice_column());
+            let sel = meta.complex_selector();
+            mpt_tbl.lookup_any_word(meta, "external mpt word lookup", |meta| {
+                let sel = meta.query_selector(sel);
+                inputs.map(|col| {
+                    sel.clone() * meta.query_advice(col, halo2_proofs::poly::Rotation::cur())
+                })
+            });
+            (config, inputs, sel)
+        }
+
+        fn synthesize(
+            &self,
+            (config, inputs, sel): Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            self.0.synthesize(config, layouter.namespace(|| "mpt"))?;
+            layouter.assign_region(
+                || "external lookup",
+                |mut region| {
+                    sel.enable(&mut region, 0)?;
+                    for (col, val) in inputs.iter().zip(self.1) {
+                        region.assign_advice(|| "lookup input", *col, 0, || Value::known(val))?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn word_mpt_table() {
+        let op = rand_storage_op();
+        let row = MptTableRow::from_op(MPTProofType::StorageChanged, &op, Fp::zero());
+        let fp = |h: Hash| Fp::from_bytes(&h.0).unwrap();
+        let entry = [
+            Fp::from(MPTProofType::StorageChanged as u64),
+            fp(row.address),
+            fp(row.storage_key_hi),
+            fp(row.storage_key_lo),
+            fp(row.old_value_hi),
+            fp(row.old_value_lo),
+            fp(row.new_value_hi),
+            fp(row.new_value_lo),
+            fp(row.old_root),
+            fp(row.new_root),
+        ];
+        let trie = EthTrie::<Fp> {
+            start_root: op.account_root_before(),
+            final_root: op.account_root(),
+            ops: vec![op],
+            ..Default::default()
+        };
+        let (circuit, _) = trie.to_circuits((40, None), &[]).unwrap();
+
+        let k = 9;
+        let word_circuit = circuit.clone().with_word_mode();
+        let prover = MockProver::<Fp>::run(k, &word_circuit, word_circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let lookup = WordLookupCircuit(circuit.clone(), entry);
+        let prover = MockProver::<Fp>::run(k, &lookup, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let mut wrong_entry = entry;
+        wrong_entry[7] += Fp::one();
+        let lookup = WordLookupCircuit(circuit.clone(), wrong_entry);
+        let prover = MockProver::<Fp>::run(k, &lookup, circuit.instance()).unwrap();
+        assert!(prover.verify().is_err());
+
+        // no byte limbs for the storage key and values
+        let (mut rlc_cs, mut word_cs) = (ConstraintSystem::<Fp>::default(), Default::default());
+        EthTrieCircuit::<Fp, false>::configure(&mut rlc_cs);
+        WordModeCircuit::<Fp>::configure(&mut word_cs);
+        assert!(word_cs.num_advice_columns() + 3 * 32 <= rlc_cs.num_advice_columns());
     }
 }
@@ -354,7 +354,7 @@ impl MPTOpGadget {
         exported: &[Column<Advice>],
         s_ctrl_type: &[Column<Advice>],
         free: &[Column<Advice>],
-        root_index: Option<(Column<Advice>, Column<Advice>)>,
+        root_index: Option<(Column<Advice>, Column<Advice>, Column<Advice>)>,
     ) -> Self {
         let tables = MPTOpTables::configure_create(meta);
         let hash_tbls = HashTable::configure_create(meta);
@@ -383,7 +383,7 @@ impl MPTOpGadget {
         exported: &[Column<Advice>],
         s_ctrl_type: &[Column<Advice>],
         free: &[Column<Advice>],
-        root_index: Option<(Column<Advice>, Column<Advice>)>,
+        root_index: Option<(Column<Advice>, Column<Advice>, Column<Advice>)>,
         tables: MPTOpTables,
         hash_tbl: HashTable,
     ) -> Self {
@@ -417,7 +417,7 @@ impl MPTOpGadget {
             vec![s_row * (Expression::Constant(Fp::one()) - s_enable.clone()) * s_enable]
         });
 
-        if let Some((old_root_index, new_root_index)) = root_index {
+        if let Some((old_root_index, new_root_index, segment_break)) = root_index {
             meta.create_gate("root index", |meta| {
                 let s_row = meta.query_selector(g_config.s_row);
                 let s_enable = s_row
@@ -427,11 +427,14 @@ impl MPTOpGadget {
                         Rotation::cur(),
                     );
                 // constraint root index:
-                // the old root in heading row (START) equal to the new_root_index_prev
+                // the old root in heading row (START) equal to the new_root_index_prev, unless
+                // a new segment is started there
                 // the old root in heading row (START) also equal to the old_root_index_cur
                 // the new root in heading row (START) equal must be equal to new_root_index_cur
                 vec![
                     s_enable.clone()
+                        * (Expression::Constant(Fp::one())
+                            - meta.query_advice(segment_break, Rotation::cur()))
                         * (meta.query_advice(g_config.old_val, Rotation::cur())
                             - meta.query_advice(new_root_index, Rotation::prev())),
                     s_enable.clone()
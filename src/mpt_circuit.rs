@@ -0,0 +1,439 @@
+use crate::{
+    constraint_builder::ConstraintBuilder,
+    gadgets::{
+        byte_bit::ByteBitGadget, byte_representation::ByteRepresentationConfig,
+        canonical_representation::CanonicalRepresentationConfig, key_bit::KeyBitConfig,
+        mpt_update::MptUpdateConfig, poseidon::PoseidonConfig,
+    },
+    operation::TraceError,
+    serde::SMTTrace,
+    types::{account_key, address_halves, proofs_from_traces, Proof},
+    Hashable, MPTProofType,
+};
+use ethers_core::types::Address;
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    halo2curves::bn256::Fr,
+    plonk::{Challenge, Circuit, ConstraintSystem, Error, FirstPhase},
+};
+use itertools::Itertools;
+
+/// Config for MptCircuit
+#[derive(Clone)]
+pub struct MptCircuitConfig {
+    mpt_update: MptUpdateConfig,
+    poseidon: PoseidonConfig,
+    canonical_representation: CanonicalRepresentationConfig,
+    key_bit: KeyBitConfig,
+    byte_bit: ByteBitGadget,
+    byte_representation: ByteRepresentationConfig,
+    randomness: Challenge,
+}
+
+/// circuit for a sequence of mpt updates, built from the constraint builder gadgets
+#[derive(Clone, Debug, Default)]
+pub struct MptCircuit {
+    /// the traces of updates to be proven, in order
+    pub traces: Vec<SMTTrace>,
+    /// proof types for the leading traces, required for reading fields other than nonce
+    /// since a read can not be told apart from the trace. the rest are inferred
+    pub proof_types: Vec<MPTProofType>,
+}
+
+impl MptCircuit {
+    /// create circuit from the traces of updates, inferring the proof types
+    pub fn new(traces: Vec<SMTTrace>) -> Self {
+        Self::new_with_proof_types(traces, vec![])
+    }
+
+    /// create circuit from the traces of updates and their proof types
+    pub fn new_with_proof_types(traces: Vec<SMTTrace>, proof_types: Vec<MPTProofType>) -> Self {
+        Self {
+            traces,
+            proof_types,
+        }
+    }
+
+    /// check that every trace is well formed, returning the error for the first one which isn't
+    pub fn validate(&self) -> Result<(), TraceError> {
+        self.proofs().map(|_| ())
+    }
+
+    fn proofs(&self) -> Result<Vec<Proof>, TraceError> {
+        proofs_from_traces(&self.traces, &self.proof_types)
+    }
+
+    fn u64s(&self) -> Vec<u64> {
+        // The last 4 bytes of an address are range checked as the first 4 bytes of a u64.
+        let address_lows = self.traces.iter().map(|trace| {
+            let low_bytes: [u8; 4] = trace.address.0[16..].try_into().unwrap();
+            u64::from(u32::from_be_bytes(low_bytes)) << 32
+        });
+        self.traces
+            .iter()
+            .flat_map(|trace| trace.account_update.iter().flatten())
+            .flat_map(|account| [account.nonce, account.code_size])
+            .chain(address_lows)
+            .unique()
+            .collect()
+    }
+}
+
+impl Circuit<Fr> for MptCircuit {
+    type Config = MptCircuitConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let mut cb = ConstraintBuilder::new();
+
+        let poseidon = PoseidonConfig::configure(cs, &mut cb);
+        let byte_bit = ByteBitGadget::configure(cs, &mut cb);
+        let randomness = cs.challenge_usable_after(FirstPhase);
+        let byte_representation =
+            ByteRepresentationConfig::configure(cs, &mut cb, &byte_bit, randomness);
+        let canonical_representation =
+            CanonicalRepresentationConfig::configure(cs, &mut cb, &byte_bit);
+        let key_bit = KeyBitConfig::configure(
+            cs,
+            &mut cb,
+            &canonical_representation,
+            &byte_bit,
+            &byte_bit,
+            &byte_bit,
+        );
+        let mpt_update = MptUpdateConfig::configure(
+            cs,
+            &mut cb,
+            &poseidon,
+            &key_bit,
+            &byte_representation,
+            &byte_representation,
+        );
+
+        cb.build(cs);
+
+        MptCircuitConfig {
+            mpt_update,
+            poseidon,
+            canonical_representation,
+            key_bit,
+            byte_bit,
+            byte_representation,
+            randomness,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        let proofs = self.proofs().map_err(|_| Error::Synthesis)?;
+        assign_proofs(&config, &mut layouter, &proofs, &self.u64s())
+    }
+}
+
+fn assign_proofs(
+    config: &MptCircuitConfig,
+    layouter: &mut impl Layouter<Fr>,
+    proofs: &[Proof],
+    u64s: &[u64],
+) -> Result<(), Error> {
+    let hash_traces = hash_traces(proofs);
+    let key_bit_lookups = key_bit_lookups(proofs);
+    let keys: Vec<_> = key_bit_lookups
+        .iter()
+        .map(|(key, _, _)| *key)
+        .unique_by(|key| key.to_bytes())
+        .collect();
+    let addresses: Vec<Address> = proofs.iter().map(|proof| proof.claim.address).collect();
+    let randomness = layouter.get_challenge(config.randomness);
+
+    layouter.assign_region(
+        || "mpt circuit",
+        |mut region| {
+            config.mpt_update.assign(&mut region, proofs);
+            config.poseidon.assign(&mut region, &hash_traces);
+            config.canonical_representation.assign(&mut region, &keys);
+            config.key_bit.assign(&mut region, &key_bit_lookups);
+            config.byte_bit.assign(&mut region);
+            config
+                .byte_representation
+                .assign(&mut region, u64s, &addresses, &[], &[], randomness);
+            Ok(())
+        },
+    )
+}
+
+fn hash_traces(proofs: &[Proof]) -> Vec<(Fr, Fr, Fr)> {
+    let mut hash_traces = vec![];
+    for proof in proofs {
+        let (address_high, address_low) = address_halves(proof.claim.address);
+        hash_traces.push((address_high, address_low, account_key(proof.claim.address)));
+        for (direction, old_hash, new_hash, sibling, is_padding_open, is_padding_close) in proof
+            .address_hash_traces
+            .iter()
+            .chain(proof.storage_hash_traces.iter().flatten())
+        {
+            if !is_padding_open {
+                hash_traces.push(hash_trace(*direction, *old_hash, *sibling));
+            }
+            if !is_padding_close {
+                hash_traces.push(hash_trace(*direction, *new_hash, *sibling));
+            }
+        }
+        // For a created or deleted account, these hold the leaf of the account in its place on
+        // the other path, if any, such as the one moved up after a deletion.
+        for [left, right, hash] in proof
+            .old_account_hash_traces
+            .iter()
+            .chain(&proof.new_account_hash_traces)
+        {
+            if *hash != Fr::zero() {
+                hash_traces.push((*left, *right, *hash));
+            }
+        }
+        for leaf in proof.storage_leafs.iter().flatten() {
+            let key_hash = Hashable::hash([Fr::one(), leaf.key]);
+            hash_traces.push((Fr::one(), leaf.key, key_hash));
+            hash_traces.push((key_hash, leaf.value_hash, leaf.hash()));
+        }
+    }
+    hash_traces
+}
+
+fn hash_trace(direction: bool, hash: Fr, sibling: Fr) -> (Fr, Fr, Fr) {
+    let (left, right) = if direction {
+        (sibling, hash)
+    } else {
+        (hash, sibling)
+    };
+    (left, right, Hashable::hash([left, right]))
+}
+
+fn key_bit_lookups(proofs: &[Proof]) -> Vec<(Fr, usize, bool)> {
+    let mut lookups = vec![];
+    for proof in proofs {
+        let paths = std::iter::once((account_key(proof.claim.address), &proof.address_hash_traces))
+            .chain(proof.storage_key.zip(proof.storage_hash_traces.as_ref()));
+        for (key, hash_traces) in paths {
+            lookups.extend(
+                hash_traces
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .map(|(depth, (direction, ..))| (key, depth, *direction)),
+            );
+        }
+    }
+    lookups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        serde::{AccountData, HexBytes},
+        trie::StateTrie,
+    };
+    use halo2_proofs::dev::MockProver;
+    use num_bigint::BigUint;
+
+    const READ_TRACES: &str = include_str!("../tests/read_traces.json");
+    const TOKEN_TRACES: &str = include_str!("../tests/token_traces.json");
+    const EMPTY_ACCOUNT_TRACE: &str = include_str!("../tests/empty_account.json");
+    const DUAL_CODE_HASH_TRACE_1: &str = include_str!("../tests/dual_code_hash/trace_1.json");
+    const DUAL_CODE_HASH_TRACE_2: &str = include_str!("../tests/dual_code_hash/trace_2.json");
+    const DUAL_CODE_HASH_TRACES: &str = include_str!("../tests/dual_code_hash/traces_1.json");
+
+    // every trace fixture, either an array of traces or a single one
+    const FIXTURES: [(&str, &str); 11] = [
+        ("deploy_traces", include_str!("../tests/deploy_traces.json")),
+        (
+            "deploy_traces_multiple_fields",
+            include_str!("../tests/deploy_traces_multiple_fields.json"),
+        ),
+        ("empty_account", EMPTY_ACCOUNT_TRACE),
+        ("empty_storage", include_str!("../tests/empty_storage.json")),
+        ("read_traces", READ_TRACES),
+        ("token_traces", TOKEN_TRACES),
+        ("traces", include_str!("../tests/traces.json")),
+        ("dual_code_hash/trace_1", DUAL_CODE_HASH_TRACE_1),
+        ("dual_code_hash/trace_2", DUAL_CODE_HASH_TRACE_2),
+        (
+            "dual_code_hash/trace_3",
+            include_str!("../tests/dual_code_hash/trace_3.json"),
+        ),
+        ("dual_code_hash/traces_1", DUAL_CODE_HASH_TRACES),
+    ];
+
+    fn fixture_traces(s: &str) -> Vec<SMTTrace> {
+        serde_json::from_str(s).unwrap_or_else(|_| vec![serde_json::from_str(s).unwrap()])
+    }
+
+    #[test]
+    fn empty_mpt_circuit() {
+        let circuit = MptCircuit::default();
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn mpt_circuit_all_fixtures() {
+        for (name, s) in FIXTURES {
+            let circuit = MptCircuit::new(fixture_traces(s));
+            let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+            assert_eq!(prover.verify(), Ok(()), "fixture {name}");
+        }
+    }
+
+    #[test]
+    fn mpt_circuit_poseidon_code_hash_and_code_size() {
+        let traces: Vec<SMTTrace> = serde_json::from_str(DUAL_CODE_HASH_TRACES).unwrap();
+        // the first trace is a read on an existing account.
+        let trace = traces[0].clone();
+        for proof_type in [
+            MPTProofType::PoseidonCodeHashExists,
+            MPTProofType::CodeSizeExists,
+        ] {
+            let circuit = MptCircuit::new_with_proof_types(vec![trace.clone()], vec![proof_type]);
+            let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+    }
+
+    // a circuit for proofs altered after they are converted from traces
+    #[derive(Clone, Default)]
+    struct ProofsCircuit {
+        proofs: Vec<Proof>,
+        u64s: Vec<u64>,
+    }
+
+    impl Circuit<Fr> for ProofsCircuit {
+        type Config = MptCircuitConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fr>) -> Self::Config {
+            MptCircuit::configure(cs)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fr>,
+        ) -> Result<(), Error> {
+            assign_proofs(&config, &mut layouter, &self.proofs, &self.u64s)
+        }
+    }
+
+    #[test]
+    fn mpt_circuit_rejects_created_account_as_read() {
+        // an account created with only a balance has a zero nonce, code size and poseidon
+        // code hash, which match the empty node its path ended in
+        let account = AccountData {
+            nonce: 0,
+            balance: BigUint::from(1u64),
+            code_hash: BigUint::default(),
+            poseidon_code_hash: BigUint::default(),
+            code_size: 0,
+        };
+        let trace = StateTrie::<Fr>::default()
+            .update_account(HexBytes([1; 20]), account)
+            .unwrap();
+        let circuit = MptCircuit::new(vec![trace]);
+        let proof = circuit.proofs().unwrap()[0].clone();
+        for proof_type in [
+            MPTProofType::PoseidonCodeHashExists,
+            MPTProofType::CodeSizeExists,
+        ] {
+            let mut read = proof.clone();
+            read.claim = read.claim.zero_read(proof_type);
+            let circuit = ProofsCircuit {
+                proofs: vec![read],
+                u64s: circuit.u64s(),
+            };
+            let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+            assert!(prover.verify().is_err(), "{proof_type:?}");
+        }
+    }
+
+    #[test]
+    fn mpt_circuit_rejects_malformed_trace() {
+        let mut traces: Vec<SMTTrace> = serde_json::from_str(TOKEN_TRACES).unwrap();
+        traces[1].account_path[0].root = Default::default();
+        let circuit = MptCircuit::new(traces);
+        assert!(matches!(
+            circuit.validate(),
+            Err(TraceError::AtTrace { index: 1, .. })
+        ));
+        assert!(MockProver::<Fr>::run(14, &circuit, vec![]).is_err());
+    }
+
+    #[test]
+    fn mpt_circuit_rejects_existing_account_as_empty() {
+        let traces: Vec<SMTTrace> = serde_json::from_str(DUAL_CODE_HASH_TRACES).unwrap();
+        // the first trace is a read on an existing account, whose leaf its path ends in.
+        let mut trace = traces[0].clone();
+        trace.account_update = [None, None];
+        let circuit = MptCircuit::new(vec![trace]);
+        assert!(circuit.validate().is_ok());
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    // creates accounts 1 to 3 with two storage slots each, then clears a slot of account 1
+    // and deletes account 2
+    fn deletion_traces() -> Vec<SMTTrace> {
+        let mut trie = StateTrie::<Fr>::default();
+        let mut traces = vec![];
+        for i in 1..4u8 {
+            let account = AccountData {
+                nonce: i.into(),
+                balance: BigUint::default(),
+                code_hash: BigUint::default(),
+                poseidon_code_hash: BigUint::default(),
+                code_size: 0,
+            };
+            traces.push(trie.update_account(HexBytes([i; 20]), account).unwrap());
+            for key in [i, i + 10] {
+                traces.push(
+                    trie.update_storage(HexBytes([i; 20]), HexBytes([key; 32]), HexBytes([i; 32]))
+                        .unwrap(),
+                );
+            }
+        }
+        traces.push(
+            trie.update_storage(HexBytes([1; 20]), HexBytes([1; 32]), HexBytes::default())
+                .unwrap(),
+        );
+        traces.push(trie.delete_account(HexBytes([2; 20])).unwrap());
+        traces
+    }
+
+    #[test]
+    fn mpt_circuit_deletion() {
+        let circuit = MptCircuit::new(deletion_traces());
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn mpt_circuit_rejects_deletion_leaving_the_leaf() {
+        let mut traces = deletion_traces();
+        // the account is claimed to be deleted, but the new path still ends in its leaf
+        let deletion = traces.last_mut().unwrap();
+        deletion.account_path[1] = deletion.account_path[0].clone();
+        let circuit = MptCircuit::new(traces);
+        assert!(circuit.validate().is_ok());
+        let prover = MockProver::<Fr>::run(14, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
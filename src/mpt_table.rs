@@ -1,10 +1,16 @@
-use crate::operation::{AccountOp, KeyValue};
+use crate::operation::{to_hash, AccountOp, KeyValue};
+use crate::serde::Hash;
 use halo2_proofs::{
     arithmetic::{Field, FieldExt},
-    circuit::{Layouter, Value},
-    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Selector, VirtualCells},
+    circuit::{Layouter, Region, Value},
+    halo2curves::group::ff::PrimeField,
+    plonk::{
+        Advice, Challenge, Column, ConstraintSystem, Error, Expression, Fixed, SecondPhase,
+        Selector, ThirdPhase, VirtualCells,
+    },
     poly::Rotation,
 };
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 mod byte32;
@@ -18,9 +24,281 @@ use value_rep::Config as RepCfg;
 type RepConfig = RepCfg<32, 8>;
 type RangeCheckConfig = RangeCheckCfg<8>;
 
+/// The columns of mpt table, which can be shared between the mpt circuit and other circuits
+/// configured in the same constraint system, so they can lookup the entries directly
+///
+/// Under word mode, the columns of storage key and values carry the high 128 bits of words
+/// and the low 128 bits are in 3 additional columns, so no randomness is involved. The
+/// halves are NOT range checked: mpt circuit binds the poseidon hash of (hi, lo) for the
+/// storage key and values, and only hi * 2^128 + lo for nonce and balance. Consumers must
+/// range check both halves to 128 bits before they rely on them as the limbs of a word
+///
+/// The fixed enable column is set on the rows the table is assigned in, where the rows
+/// without entry are constrained to be zero. Queries of the table are gated by it, so the
+/// rows outside the table are matched as zero whatever they are assigned with
+#[derive(Clone, Copy, Debug)]
+pub struct MptTable {
+    columns: [Column<Advice>; 7],
+    words_lo: Option<[Column<Advice>; 3]>,
+    enable: Column<Fixed>,
+}
+
+impl MptTable {
+    /// allocate the columns, the ones for storage key and values carry rlc over a challenge
+    /// and are allocated in the second phase
+    pub fn construct<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+        Self {
+            columns: [
+                meta.advice_column(),
+                meta.advice_column_in(SecondPhase),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column(),
+                meta.advice_column_in(SecondPhase),
+                meta.advice_column_in(SecondPhase),
+            ],
+            words_lo: None,
+            enable: meta.fixed_column(),
+        }
+    }
+
+    /// allocate the columns for word mode, all in the first phase
+    pub fn construct_word<F: Field>(meta: &mut ConstraintSystem<F>) -> Self {
+        Self {
+            columns: [0; 7].map(|_| meta.advice_column()),
+            words_lo: Some([0; 3].map(|_| meta.advice_column())),
+            enable: meta.fixed_column(),
+        }
+    }
+
+    /// use the columns allocated elsewhere, in the order of `columns`
+    pub fn new(columns: [Column<Advice>; 7], enable: Column<Fixed>) -> Self {
+        Self {
+            columns,
+            words_lo: None,
+            enable,
+        }
+    }
+
+    /// use the columns allocated elsewhere for word mode, in the order of `columns`
+    /// and `word_columns`
+    pub fn new_word(
+        columns: [Column<Advice>; 7],
+        words_lo: [Column<Advice>; 3],
+        enable: Column<Fixed>,
+    ) -> Self {
+        Self {
+            columns,
+            words_lo: Some(words_lo),
+            enable,
+        }
+    }
+
+    /// if the table is under word mode
+    pub fn is_word_mode(&self) -> bool {
+        self.words_lo.is_some()
+    }
+
+    /// the columns in the order the mpt circuit is configured with, i.e. (address,
+    /// storage key, proof type, new root, old root, new value, old value)
+    pub fn columns(&self) -> [Column<Advice>; 7] {
+        self.columns
+    }
+
+    /// the columns for the low 128 bits of (storage key, new value, old value) under word mode
+    pub fn word_columns(&self) -> Option<[Column<Advice>; 3]> {
+        self.words_lo
+    }
+
+    /// the fixed column enabling the rows of table
+    pub fn enable(&self) -> Column<Fixed> {
+        self.enable
+    }
+
+    // query the column gated by the enable column
+    fn query<F: Field>(
+        &self,
+        meta: &mut VirtualCells<'_, F>,
+        col: Column<Advice>,
+    ) -> Expression<F> {
+        meta.query_fixed(self.enable, Rotation::cur()) * meta.query_advice(col, Rotation::cur())
+    }
+
+    /// query the table as (proof type, address, storage key, old value, new value,
+    /// old root, new root), or as `word_table_exprs` under word mode. all of them are
+    /// zero on the rows outside the table
+    pub fn table_exprs<F: Field>(&self, meta: &mut VirtualCells<'_, F>) -> Vec<Expression<F>> {
+        if self.is_word_mode() {
+            self.word_table_exprs(meta).into()
+        } else {
+            self.rlc_table_exprs(meta).into()
+        }
+    }
+
+    // the exprs of `table_exprs` for the table not under word mode
+    fn rlc_table_exprs<F: Field>(&self, meta: &mut VirtualCells<'_, F>) -> [Expression<F>; 7] {
+        let [address, storage_key, proof_type, new_root, old_root, new_value, old_value] =
+            self.columns;
+        [
+            proof_type,
+            address,
+            storage_key,
+            old_value,
+            new_value,
+            old_root,
+            new_root,
+        ]
+        .map(|col| self.query(meta, col))
+    }
+
+    /// lookup the tuple of (proof type, address, storage key, old value, new value,
+    /// old root, new root) built by [inputs] from the table, which must not be under
+    /// word mode, see `lookup_any_word`. the inputs on disabled rows should be all zero,
+    /// which are matched by the padding rows of table
+    pub fn lookup_any<F: Field>(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        inputs: impl FnOnce(&mut VirtualCells<'_, F>) -> [Expression<F>; 7],
+    ) {
+        assert!(!self.is_word_mode(), "use lookup_any_word under word mode");
+        meta.lookup_any(name, |meta| {
+            let table = self.rlc_table_exprs(meta);
+            inputs(meta).into_iter().zip(table).collect()
+        });
+    }
+
+    /// query the table under word mode as (proof type, address, storage key hi, storage key lo,
+    /// old value hi, old value lo, new value hi, new value lo, old root, new root), gated
+    /// as `table_exprs`
+    pub fn word_table_exprs<F: Field>(
+        &self,
+        meta: &mut VirtualCells<'_, F>,
+    ) -> [Expression<F>; 10] {
+        let [key_lo, new_value_lo, old_value_lo] = self.words_lo.expect("table is under word mode");
+        let [address, key_hi, proof_type, new_root, old_root, new_value_hi, old_value_hi] =
+            self.columns;
+        [
+            proof_type,
+            address,
+            key_hi,
+            key_lo,
+            old_value_hi,
+            old_value_lo,
+            new_value_hi,
+            new_value_lo,
+            old_root,
+            new_root,
+        ]
+        .map(|col| self.query(meta, col))
+    }
+
+    /// lookup the tuple in the order of `word_table_exprs` built by [inputs] from the table
+    /// under word mode, the inputs on disabled rows should be all zero. the halves in
+    /// [inputs] must be range checked to 128 bits by the caller, see `MptTable`
+    pub fn lookup_any_word<F: Field>(
+        &self,
+        meta: &mut ConstraintSystem<F>,
+        name: &'static str,
+        inputs: impl FnOnce(&mut VirtualCells<'_, F>) -> [Expression<F>; 10],
+    ) {
+        meta.lookup_any(name, |meta| {
+            let table = self.word_table_exprs(meta);
+            inputs(meta).into_iter().zip(table).collect()
+        });
+    }
+
+    // all the columns, with the low word columns following `columns` under word mode
+    fn all_columns(&self) -> Vec<Column<Advice>> {
+        self.columns
+            .into_iter()
+            .chain(self.words_lo.into_iter().flatten())
+            .collect()
+    }
+}
+
+/// A copy of mpt table bound to it by a permutation argument, along with the index of the
+/// row in mpt table each row comes from
+///
+/// The rows of entries are constrained to be strictly increasing in (address, storage key hi,
+/// storage key lo, index), and the padding rows are in the end. The comparison holds as the
+/// compared fields are less than 2^160, which consumers must ensure for the halves of storage
+/// key under word mode, see `MptTable`
+#[derive(Clone, Copy, Debug)]
+pub struct PermutedMptTable {
+    table: MptTable,
+    index: Column<Advice>,
+    // the (hi, lo) of storage key when the table only carries its rlc
+    key_pair: Option<[Column<Advice>; 2]>,
+}
+
+impl PermutedMptTable {
+    /// the permuted columns, in the same layout as mpt table
+    pub fn table(&self) -> MptTable {
+        self.table
+    }
+
+    /// the column of the row in mpt table which each permuted row comes from
+    pub fn index(&self) -> Column<Advice> {
+        self.index
+    }
+
+    /// the columns of (hi, lo) of storage key, which are the ones of table under word mode
+    pub fn storage_key_pair(&self) -> [Column<Advice>; 2] {
+        match (self.key_pair, self.table.word_columns()) {
+            (Some(pair), _) => pair,
+            (None, Some([key_lo, ..])) => [self.table.columns()[1], key_lo],
+            (None, None) => unreachable!("the pair is allocated for the table not under word mode"),
+        }
+    }
+
+    // the fields which the rows are sorted by
+    fn sort_columns(&self) -> [Column<Advice>; 4] {
+        let [key_hi, key_lo] = self.storage_key_pair();
+        [self.table.columns()[0], key_hi, key_lo, self.index]
+    }
+
+    // the columns bound by the permutation argument, in the layout of
+    // `Config::compressed_columns`
+    fn compressed_columns(&self) -> Vec<Column<Advice>> {
+        self.table
+            .all_columns()
+            .into_iter()
+            .chain(self.key_pair.into_iter().flatten())
+            .collect()
+    }
+}
+
+// the differences of sorted fields are less than 2^160, as the addresses are
+const SORT_DIFF_BYTES: usize = 20;
+
+// the permutation argument between mpt table and its permuted copy: rows of both sides are
+// compressed by theta, and the running product of (row + gamma) / (permuted row + gamma) in
+// acc must start from 1 and end with 1.
+// the order of permuted copy: is_entry tells the rows of entries by their non-zero proof
+// type, and between two rows of entries, first_diff flags the first sorted field differing
+// from the next row, whose difference minus 1 is decomposed into range checked diff_bytes
+#[derive(Clone, Debug)]
+struct PermutedConfig {
+    permuted: PermutedMptTable,
+    row_index: Column<Fixed>,
+    acc: Column<Advice>,
+    q_first: Selector,
+    q_trans: Selector,
+    q_last: Selector,
+    theta: Challenge,
+    gamma: Challenge,
+    is_entry: Column<Advice>,
+    proof_type_inv: Column<Advice>,
+    first_diff: [Column<Advice>; 4],
+    diff_bytes: [Column<Advice>; SORT_DIFF_BYTES],
+    range_check_u8: RangeCheckConfig,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Config {
     sel: Selector,
+    enable: Column<Fixed>,
     proof_sel: [Column<Advice>; 9], // one boolean column for each variant of MPTProofType
 
     address: Column<Advice>,
@@ -31,20 +309,114 @@ pub(crate) struct Config {
     new_value: Column<Advice>,
     old_value: Column<Advice>,
 
-    // the rep for current single field values
-    key_rep: RepConfig,
-    new_val_rep: RepConfig,
-    old_val_rep: RepConfig,
-
-    range_check_u8: RangeCheckConfig,
+    value_rep: ValueRep,
+    permuted: Option<PermutedConfig>,
+}
 
-    // turn into pair represent (hi, lo)
-    storage_key_2: PairRepConfig,
-    new_value_2: PairRepConfig,
-    old_value_2: PairRepConfig,
+// how storage key and values are represented in the table
+#[derive(Clone, Debug)]
+enum ValueRep {
+    // the columns of storage key and values carry rlc over their 32 bytes, which are
+    // bound to the byte represents and turned into pair represent (hi, lo)
+    Rlc {
+        key_rep: RepConfig,
+        new_val_rep: RepConfig,
+        old_val_rep: RepConfig,
+
+        range_check_u8: RangeCheckConfig,
+
+        storage_key_2: PairRepConfig,
+        new_value_2: PairRepConfig,
+        old_value_2: PairRepConfig,
+    },
+    // the columns of storage key and values carry the high 128 bits of words and these
+    // columns carry the low 128 bits
+    Word {
+        storage_key_lo: Column<Advice>,
+        new_value_lo: Column<Advice>,
+        old_value_lo: Column<Advice>,
+    },
 }
 
 impl Config {
+    pub fn is_word_mode(&self) -> bool {
+        matches!(self.value_rep, ValueRep::Word { .. })
+    }
+
+    // the (hi, lo) pairs of storage key, old value and new value
+    fn word_pairs<F: FieldExt>(&self, meta: &mut VirtualCells<'_, F>) -> [[Expression<F>; 2]; 3] {
+        let pairs = match &self.value_rep {
+            ValueRep::Rlc {
+                storage_key_2,
+                new_value_2,
+                old_value_2,
+                ..
+            } => [storage_key_2, old_value_2, new_value_2].map(|rep| [rep.rep_hi, rep.rep_lo]),
+            ValueRep::Word {
+                storage_key_lo,
+                new_value_lo,
+                old_value_lo,
+            } => [
+                [self.storage_key, *storage_key_lo],
+                [self.old_value, *old_value_lo],
+                [self.new_value, *new_value_lo],
+            ],
+        };
+        pairs.map(|pair| pair.map(|col| meta.query_advice(col, Rotation::cur())))
+    }
+
+    // the old and new value which are single fields (like nonce and balance)
+    fn field_values<F: FieldExt>(&self, meta: &mut VirtualCells<'_, F>) -> [Expression<F>; 2] {
+        match &self.value_rep {
+            ValueRep::Rlc { .. } => {
+                [self.old_value, self.new_value].map(|col| meta.query_advice(col, Rotation::cur()))
+            }
+            ValueRep::Word { .. } => {
+                let [_, old_value, new_value] = self.word_pairs(meta);
+                let factor = Expression::Constant(F::from_u128(1 << 64).square());
+                [old_value, new_value].map(|[hi, lo]| hi * factor.clone() + lo)
+            }
+        }
+    }
+
+    pub fn permuted_table(&self) -> Option<PermutedMptTable> {
+        self.permuted.as_ref().map(|cfg| cfg.permuted)
+    }
+
+    // the columns bound to the permuted copy, i.e. the columns of table with the (hi, lo)
+    // of storage key when the table only carries its rlc
+    fn compressed_columns(&self) -> Vec<Column<Advice>> {
+        let key_pair = match &self.value_rep {
+            ValueRep::Rlc { storage_key_2, .. } => vec![storage_key_2.rep_hi, storage_key_2.rep_lo],
+            ValueRep::Word { .. } => Vec::new(),
+        };
+        self.table_columns().into_iter().chain(key_pair).collect()
+    }
+
+    // the columns of table, in the same layout as `MptTable::all_columns`
+    fn table_columns(&self) -> Vec<Column<Advice>> {
+        let lo = match &self.value_rep {
+            ValueRep::Rlc { .. } => Vec::new(),
+            ValueRep::Word {
+                storage_key_lo,
+                new_value_lo,
+                old_value_lo,
+            } => vec![*storage_key_lo, *new_value_lo, *old_value_lo],
+        };
+        [
+            self.address,
+            self.storage_key,
+            self.proof_type,
+            self.new_root,
+            self.old_root,
+            self.new_value,
+            self.old_value,
+        ]
+        .into_iter()
+        .chain(lo)
+        .collect()
+    }
+
     pub fn mpt_table_begin_index(&self) -> usize {
         self.address.index()
     }
@@ -89,58 +461,37 @@ impl Config {
             };
 
         let build_entry_lookup_value = |meta: &mut VirtualCells<'_, F>| {
+            // values
+            let [old_val, new_val] = self.field_values(meta);
             [
-                // values
-                (
-                    meta.query_advice(self.old_value, Rotation::cur()),
-                    meta.query_advice(old_value[0], Rotation::cur()),
-                ),
-                (
-                    meta.query_advice(self.new_value, Rotation::cur()),
-                    meta.query_advice(new_value[0], Rotation::cur()),
-                ),
+                (old_val, meta.query_advice(old_value[0], Rotation::cur())),
+                (new_val, meta.query_advice(new_value[0], Rotation::cur())),
             ]
         };
 
         let build_entry_lookup_rep_value = |meta: &mut VirtualCells<'_, F>| {
+            // values rep
+            let [_, [old_hi, old_lo], [new_hi, new_lo]] = self.word_pairs(meta);
             [
-                // values rep
-                (
-                    meta.query_advice(self.old_value_2.rep_hi, Rotation::cur()),
-                    meta.query_advice(old_value[0], Rotation::cur()),
-                ),
-                (
-                    meta.query_advice(self.old_value_2.rep_lo, Rotation::cur()),
-                    meta.query_advice(old_value[1], Rotation::cur()),
-                ),
-                (
-                    meta.query_advice(self.new_value_2.rep_hi, Rotation::cur()),
-                    meta.query_advice(new_value[0], Rotation::cur()),
-                ),
-                (
-                    meta.query_advice(self.new_value_2.rep_lo, Rotation::cur()),
-                    meta.query_advice(new_value[1], Rotation::cur()),
-                ),
+                (old_hi, meta.query_advice(old_value[0], Rotation::cur())),
+                (old_lo, meta.query_advice(old_value[1], Rotation::cur())),
+                (new_hi, meta.query_advice(new_value[0], Rotation::cur())),
+                (new_lo, meta.query_advice(new_value[1], Rotation::cur())),
             ]
         };
 
         let build_entry_lookup_storage_key = |meta: &mut VirtualCells<'_, F>| {
+            let [[key_hi, key_lo], _, _] = self.word_pairs(meta);
             [
-                (
-                    meta.query_advice(self.storage_key_2.rep_hi, Rotation::cur()),
-                    meta.query_advice(key[0], Rotation::cur()),
-                ),
-                (
-                    meta.query_advice(self.storage_key_2.rep_lo, Rotation::cur()),
-                    meta.query_advice(key[1], Rotation::cur()),
-                ),
+                (key_hi, meta.query_advice(key[0], Rotation::cur())),
+                (key_lo, meta.query_advice(key[1], Rotation::cur())),
             ]
         };
 
         let build_entry_lookup_not_exist = |meta: &mut VirtualCells<'_, F>| {
             [
                 // it lookup the mpt gadget above target gadget (only the hash type of old trie is looked up,
-                // it is mpt_table's responsibiliy to ensure old_root == new_root here)
+                // old_root == new_root is ensured by the "not exist rows keep the root" gate)
                 (
                     Expression::Constant(F::from(super::HashType::Empty as u64)),
                     meta.query_advice(ctrl_id, Rotation::prev()),
@@ -194,7 +545,8 @@ impl Config {
         meta.lookup_any("mpt account destroy entry lookup", |meta| {
             let s_enable = meta.query_advice(self.proof_sel[6], Rotation::cur());
 
-            // TODO: not handle AccountDestructed yet (this entry has no lookup: i.e. no verification)
+            // TODO: not handle AccountDestructed yet (this entry has no lookup: i.e. no verification),
+            // so the rows are refused by the "disabled rows are empty" gate
             build_entry_lookup_common(meta, (3, 2))
                 .into_iter()
                 .map(|(fst, snd)| (fst * s_enable.clone(), snd))
@@ -227,7 +579,7 @@ impl Config {
 }
 
 /// The defination is greped from state-circuit
-#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
 pub enum MPTProofType {
     /// nonce
     NonceChanged = 1,
@@ -249,42 +601,74 @@ pub enum MPTProofType {
     StorageDoesNotExist,
 }
 
+/// A row of mpt table, as the entry of an operation being looked up by other circuits.
+/// fields are the little-endian repr of field elements, and the rlc fields are computed
+/// under the randomness given on creation. the 32-byte words (storage key, codehash and
+/// storage value) are also given as their 128-bit (hi, lo) halves, while a value being a
+/// single field (nonce, balance) is split into the high and low 128 bits of its repr
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MptTableRow {
+    /// proof type of the entry
+    pub proof_type: MPTProofType,
+    /// address of account
+    pub address: Hash,
+    /// rlc of storage key
+    pub storage_key: Hash,
+    /// high 128 bits of storage key
+    pub storage_key_hi: Hash,
+    /// low 128 bits of storage key
+    pub storage_key_lo: Hash,
+    /// account root before operation
+    pub old_root: Hash,
+    /// account root after operation
+    pub new_root: Hash,
+    /// value before operation, rlc for a 32-byte word
+    pub old_value: Hash,
+    /// high 128 bits of the value before operation
+    pub old_value_hi: Hash,
+    /// low 128 bits of the value before operation
+    pub old_value_lo: Hash,
+    /// value after operation, rlc for a 32-byte word
+    pub new_value: Hash,
+    /// high 128 bits of the value after operation
+    pub new_value_hi: Hash,
+    /// low 128 bits of the value after operation
+    pub new_value_lo: Hash,
+}
+
+impl MptTableRow {
+    /// create the row for an operation under specified proof type and randomness
+    pub fn from_op<F: FieldExt>(
+        proof_type: MPTProofType,
+        op: &AccountOp<F>,
+        randomness: F,
+    ) -> Self {
+        MPTEntry::from_op(proof_type, op, Value::known(randomness)).table_row()
+    }
+}
+
+// split a field into the high and low 128 bits of its repr
+fn split_word<F: FieldExt>(v: F) -> (F, F) {
+    let repr = v.to_repr();
+    let half = |bytes: &[u8]| F::from_u128(u128::from_le_bytes(bytes.try_into().unwrap()));
+    (half(&repr.as_ref()[16..32]), half(&repr.as_ref()[0..16]))
+}
+
 /// the Entry for mpt table
 #[derive(Clone, Debug)]
 pub(crate) struct MPTEntry<F: Field> {
     proof_type: MPTProofType,
-    base: [Option<F>; 7],
+    base: [Value<F>; 7],
     storage_key: KeyValue<F>,
     new_value: KeyValue<F>,
     old_value: KeyValue<F>,
 }
 
 impl<F: FieldExt> MPTEntry<F> {
-    // detect proof type from op data itself, just mocking,
-    // not always correct
-    pub fn mock_from_op(op: &AccountOp<F>, randomness: F) -> Self {
-        if op.state_trie.is_some() {
-            return if op.store_after.is_none() && op.store_before.is_none() {
-                Self::from_op(MPTProofType::StorageDoesNotExist, op, randomness)
-            } else {
-                Self::from_op(MPTProofType::StorageChanged, op, randomness)
-            };
-        }
-
-        match (&op.account_before, &op.account_after) {
-            (Some(before), Some(after)) => {
-                if before.balance != after.balance {
-                    Self::from_op(MPTProofType::BalanceChanged, op, randomness)
-                } else if before.nonce != after.nonce {
-                    Self::from_op(MPTProofType::NonceChanged, op, randomness)
-                } else {
-                    Self::from_op(MPTProofType::CodeHashExists, op, randomness)
-                }
-            }
-            (None, Some(_)) => Self::from_op(MPTProofType::CodeHashExists, op, randomness),
-            (Some(_), None) => Self::from_op(MPTProofType::AccountDestructed, op, randomness),
-            (None, None) => Self::from_op(MPTProofType::AccountDoesNotExist, op, randomness),
-        }
+    // detect proof type from op data itself, a read is always taken as on nonce
+    pub fn mock_from_op(op: &AccountOp<F>, randomness: Value<F>) -> Self {
+        Self::from_op(op.proof_type(), op, randomness)
     }
 
     pub fn from_op_no_base(proof_type: MPTProofType, op: &AccountOp<F>) -> Self {
@@ -312,13 +696,13 @@ impl<F: FieldExt> MPTEntry<F> {
         Self {
             proof_type,
             base: [
-                Some(op.address),
-                None,
-                Some(F::from(proof_type as u64)),
-                None,
-                None,
-                None,
-                None,
+                Value::known(op.address),
+                Value::unknown(),
+                Value::known(F::from(proof_type as u64)),
+                Value::unknown(),
+                Value::unknown(),
+                Value::unknown(),
+                Value::unknown(),
             ],
             storage_key,
             new_value,
@@ -326,50 +710,138 @@ impl<F: FieldExt> MPTEntry<F> {
         }
     }
 
-    pub fn from_op(proof_type: MPTProofType, op: &AccountOp<F>, randomness: F) -> Self {
+    // the rlc fields are only known once the challenge is available (i.e. in the
+    // second phase), the other fields are always assigned
+    pub fn from_op(proof_type: MPTProofType, op: &AccountOp<F>, randomness: Value<F>) -> Self {
         let mut ret = Self::from_op_no_base(proof_type, op);
 
         let (old_value_f, new_value_f) = match proof_type {
             MPTProofType::NonceChanged => (
-                op.account_before
-                    .as_ref()
-                    .map(|acc| acc.nonce)
-                    .unwrap_or_default(),
-                op.account_after
-                    .as_ref()
-                    .map(|acc| acc.nonce)
-                    .unwrap_or_default(),
+                Value::known(
+                    op.account_before
+                        .as_ref()
+                        .map(|acc| acc.nonce)
+                        .unwrap_or_default(),
+                ),
+                Value::known(
+                    op.account_after
+                        .as_ref()
+                        .map(|acc| acc.nonce)
+                        .unwrap_or_default(),
+                ),
             ),
             MPTProofType::BalanceChanged => (
-                op.account_before
-                    .as_ref()
-                    .map(|acc| acc.balance)
-                    .unwrap_or_default(),
-                op.account_after
-                    .as_ref()
-                    .map(|acc| acc.balance)
-                    .unwrap_or_default(),
+                Value::known(
+                    op.account_before
+                        .as_ref()
+                        .map(|acc| acc.balance)
+                        .unwrap_or_default(),
+                ),
+                Value::known(
+                    op.account_after
+                        .as_ref()
+                        .map(|acc| acc.balance)
+                        .unwrap_or_default(),
+                ),
             ),
             MPTProofType::StorageChanged | MPTProofType::CodeHashExists => (
-                ret.old_value.u8_rlc(randomness),
-                ret.new_value.u8_rlc(randomness),
+                randomness.map(|r| ret.old_value.u8_rlc(r)),
+                randomness.map(|r| ret.new_value.u8_rlc(r)),
             ),
-            _ => (F::zero(), F::zero()),
+            _ => (Value::known(F::zero()), Value::known(F::zero())),
         };
 
         ret.base = [
             ret.base[0],
-            Some(ret.storage_key.u8_rlc(randomness)),
+            randomness.map(|r| ret.storage_key.u8_rlc(r)),
             ret.base[2],
-            Some(op.account_root()),
-            Some(op.account_root_before()),
-            Some(new_value_f),
-            Some(old_value_f),
+            Value::known(op.account_root()),
+            Value::known(op.account_root_before()),
+            new_value_f,
+            old_value_f,
         ];
 
         ret
     }
 
+    // the fields in base, the unknown ones are taken as zero
+    fn known_base(&self) -> [F; 7] {
+        self.base.map(|v| {
+            let mut out = F::zero();
+            v.map(|v| out = v);
+            out
+        })
+    }
+
+    // the (hi, lo) pairs of storage key, old value and new value, the value being a
+    // single field (like nonce and balance) is split by its repr
+    fn words(&self) -> [(F, F); 3] {
+        let [.., new_value, old_value] = self.known_base();
+        let is_word = matches!(
+            self.proof_type,
+            MPTProofType::StorageChanged | MPTProofType::CodeHashExists
+        );
+        let word = |kv: &KeyValue<F>, v: F| {
+            if is_word {
+                (kv.limb_0(), kv.limb_1())
+            } else {
+                split_word(v)
+            }
+        };
+        [
+            (self.storage_key.limb_0(), self.storage_key.limb_1()),
+            word(&self.old_value, old_value),
+            word(&self.new_value, new_value),
+        ]
+    }
+
+    // the exported row of entry
+    fn table_row(&self) -> MptTableRow {
+        let [address, storage_key, _, new_root, old_root, new_value, old_value] = self.known_base();
+        let [_, (old_value_hi, old_value_lo), (new_value_hi, new_value_lo)] = self.words();
+
+        MptTableRow {
+            proof_type: self.proof_type,
+            address: to_hash(address),
+            storage_key: to_hash(storage_key),
+            storage_key_hi: to_hash(self.storage_key.limb_0()),
+            storage_key_lo: to_hash(self.storage_key.limb_1()),
+            old_root: to_hash(old_root),
+            new_root: to_hash(new_root),
+            old_value: to_hash(old_value),
+            old_value_hi: to_hash(old_value_hi),
+            old_value_lo: to_hash(old_value_lo),
+            new_value: to_hash(new_value),
+            new_value_hi: to_hash(new_value_hi),
+            new_value_lo: to_hash(new_value_lo),
+        }
+    }
+
+    // the fields which entries are sorted by, i.e. (address, storage key hi, storage key lo)
+    fn sort_fields(&self) -> [F; 3] {
+        let [address, ..] = self.known_base();
+        [
+            address,
+            self.storage_key.limb_0(),
+            self.storage_key.limb_1(),
+        ]
+    }
+
+    // the big-endian bytes of `sort_fields`
+    fn sort_key(&self) -> Vec<u8> {
+        self.sort_fields()
+            .into_iter()
+            .flat_map(|v| {
+                v.to_repr()
+                    .as_ref()
+                    .iter()
+                    .rev()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     // this method construct entry without randomness (challenge)
     pub fn from_op_and_table_entries(
         op: &AccountOp<F>,
@@ -382,12 +854,12 @@ impl<F: FieldExt> MPTEntry<F> {
 
         ret.base = [
             ret.base[0],
-            store_key,
+            store_key.map(Value::known).unwrap_or_else(Value::unknown),
             ret.base[1],
-            Some(op.account_root()),
-            Some(op.account_root_before()),
-            Some(new_value_f),
-            Some(old_value_f),
+            Value::known(op.account_root()),
+            Value::known(op.account_root_before()),
+            Value::known(new_value_f),
+            Value::known(old_value_f),
         ];
 
         ret
@@ -399,6 +871,8 @@ pub(crate) struct MPTTable<F: Field> {
     entries: Vec<MPTEntry<F>>,
     config: Config,
     rows: usize,
+    // the rows of table in the order of its permuted copy
+    order: Vec<usize>,
 }
 
 impl<F: FieldExt> MPTTable<F> {
@@ -407,24 +881,30 @@ impl<F: FieldExt> MPTTable<F> {
         entries: impl IntoIterator<Item = MPTEntry<F>>,
         rows: usize,
     ) -> Self {
+        let entries: Vec<_> = entries.into_iter().collect();
+        // stable sorting keeps the entries under the same key in their index order
+        let mut order: Vec<_> = (0..entries.len()).collect();
+        order.sort_by_cached_key(|i| entries[*i].sort_key());
+        order.extend(entries.len()..rows);
+
         Self {
             config,
             rows,
-            entries: entries.into_iter().collect(),
+            entries,
+            order,
         }
     }
 
+    // when randomness is a challenge, the columns for storage key and values
+    // (tbl_base[1], [5], [6]) carry rlc and must be allocated in the later phase
     pub fn configure(
         meta: &mut ConstraintSystem<F>,
-        tbl_base: [Column<Advice>; 7],
+        tbl: MptTable,
         randomness: Expression<F>,
     ) -> Config {
         let sel = meta.selector();
-        let address = tbl_base[0];
+        let tbl_base = tbl.columns();
         let storage_key = tbl_base[1];
-        let proof_type = tbl_base[2];
-        let new_root = tbl_base[3];
-        let old_root = tbl_base[4];
         let new_value = tbl_base[5];
         let old_value = tbl_base[6];
 
@@ -457,6 +937,54 @@ impl<F: FieldExt> MPTTable<F> {
         let new_value_2 = PairRepConfig::configure(meta, sel, &new_val_rep.limbs);
         let old_value_2 = PairRepConfig::configure(meta, sel, &old_val_rep.limbs);
 
+        Self::configure_proof_sel(
+            meta,
+            sel,
+            proof_sel,
+            tbl,
+            ValueRep::Rlc {
+                key_rep,
+                new_val_rep,
+                old_val_rep,
+                range_check_u8,
+                storage_key_2,
+                new_value_2,
+                old_value_2,
+            },
+        )
+    }
+
+    // under word mode, the storage key and values are represented by the (hi, lo) pairs
+    // in the columns and word columns of [tbl] and no randomness is involved
+    pub fn configure_word(meta: &mut ConstraintSystem<F>, tbl: MptTable) -> Config {
+        let sel = meta.selector();
+        let proof_sel = [0; 9].map(|_| meta.advice_column());
+        let [storage_key_lo, new_value_lo, old_value_lo] =
+            tbl.word_columns().expect("table is under word mode");
+
+        Self::configure_proof_sel(
+            meta,
+            sel,
+            proof_sel,
+            tbl,
+            ValueRep::Word {
+                storage_key_lo,
+                new_value_lo,
+                old_value_lo,
+            },
+        )
+    }
+
+    fn configure_proof_sel(
+        meta: &mut ConstraintSystem<F>,
+        sel: Selector,
+        proof_sel: [Column<Advice>; 9],
+        tbl: MptTable,
+        value_rep: ValueRep,
+    ) -> Config {
+        let [address, storage_key, proof_type, new_root, old_root, new_value, old_value] =
+            tbl.columns();
+
         proof_sel
             .as_slice()
             .iter()
@@ -490,8 +1018,43 @@ impl<F: FieldExt> MPTTable<F> {
             vec![sel * total_enalbed.clone() * (Expression::Constant(F::one()) - total_enalbed)]
         });
 
+        // rows of table can only be read through the enabled proof types, which are looked
+        // up from mpt circuit, so the other rows are all zero. the poseidon codehash, code
+        // size and account destructed (proof_sel[3], [4] and [6]) have no lookup binding
+        // their values yet, so they are refused
+        meta.create_gate("disabled rows are empty", |meta| {
+            let sel = meta.query_selector(sel);
+            let unsupported = meta.query_advice(proof_sel[3], Rotation::cur())
+                + meta.query_advice(proof_sel[4], Rotation::cur())
+                + meta.query_advice(proof_sel[6], Rotation::cur());
+            let disabled = Expression::Constant(F::one())
+                - proof_sel
+                    .iter()
+                    .map(|col| meta.query_advice(*col, Rotation::cur()))
+                    .reduce(|acc, e| acc + e)
+                    .expect("not null");
+            let mut constraints = vec![sel.clone() * unsupported];
+            for col in tbl.all_columns() {
+                let value = meta.query_advice(col, Rotation::cur());
+                constraints.push(sel.clone() * disabled.clone() * value);
+            }
+            constraints
+        });
+
+        // the lookups of AccountDoesNotExist and StorageDoesNotExist (proof_sel[5] and [8])
+        // only bind the old trie, so the root must not be changed by them
+        meta.create_gate("not exist rows keep the root", |meta| {
+            let sel = meta.query_selector(sel);
+            let not_exist = meta.query_advice(proof_sel[5], Rotation::cur())
+                + meta.query_advice(proof_sel[8], Rotation::cur());
+            let old_root = meta.query_advice(old_root, Rotation::cur());
+            let new_root = meta.query_advice(new_root, Rotation::cur());
+            vec![sel * not_exist * (old_root - new_root)]
+        });
+
         Config {
             sel,
+            enable: tbl.enable(),
             proof_sel,
             address,
             storage_key,
@@ -500,25 +1063,184 @@ impl<F: FieldExt> MPTTable<F> {
             proof_type,
             new_root,
             old_root,
-            range_check_u8,
-            key_rep,
-            new_val_rep,
-            old_val_rep,
-            storage_key_2,
-            new_value_2,
-            old_value_2,
+            value_rep,
+            permuted: None,
+        }
+    }
+
+    // also configure a permuted copy of the table, bound by a permutation argument
+    pub fn configure_permuted(meta: &mut ConstraintSystem<F>, config: &mut Config) {
+        let (table, key_pair, range_check_u8) = match &config.value_rep {
+            ValueRep::Rlc { range_check_u8, .. } => (
+                MptTable::construct(meta),
+                Some([0; 2].map(|_| meta.advice_column())),
+                range_check_u8.clone(),
+            ),
+            ValueRep::Word { .. } => (
+                MptTable::construct_word(meta),
+                None,
+                RangeCheckChip::<F, 8>::configure(meta),
+            ),
+        };
+        let index = meta.advice_column();
+        let permuted = PermutedMptTable {
+            table,
+            index,
+            key_pair,
+        };
+        let row_index = meta.fixed_column();
+        let acc = meta.advice_column_in(ThirdPhase);
+        let [q_first, q_trans, q_last] = [0; 3].map(|_| meta.selector());
+        // the rlc columns are only committed in the second phase
+        let theta = meta.challenge_usable_after(SecondPhase);
+        let gamma = meta.challenge_usable_after(SecondPhase);
+        let is_entry = meta.advice_column();
+        let proof_type_inv = meta.advice_column();
+        let first_diff = [0; 4].map(|_| meta.advice_column());
+        let diff_bytes = [0; SORT_DIFF_BYTES].map(|_| meta.advice_column());
+
+        let orig_cols = config.compressed_columns();
+        let permuted_cols = permuted.compressed_columns();
+        let compress =
+            |meta: &mut VirtualCells<'_, F>, index: Expression<F>, cols: &[Column<Advice>]| {
+                cols.iter().fold(index, |acc, col| {
+                    acc * Expression::Challenge(theta) + meta.query_advice(*col, Rotation::cur())
+                }) + Expression::Challenge(gamma)
+            };
+
+        meta.create_gate("permuted mpt table start", |meta| {
+            let q_first = meta.query_selector(q_first);
+            let acc = meta.query_advice(acc, Rotation::cur());
+            vec![q_first * (acc - Expression::Constant(F::one()))]
+        });
+
+        meta.create_gate("permuted mpt table permutation", |meta| {
+            let q_trans = meta.query_selector(q_trans);
+            let q_last = meta.query_selector(q_last);
+            let row_index = meta.query_fixed(row_index, Rotation::cur());
+            let orig = compress(meta, row_index, &orig_cols);
+            let index = meta.query_advice(index, Rotation::cur());
+            let permuted = compress(meta, index, &permuted_cols);
+            let acc_cur = meta.query_advice(acc, Rotation::cur());
+            let acc_next = meta.query_advice(acc, Rotation::next());
+
+            vec![
+                q_trans * (acc_next * permuted.clone() - acc_cur.clone() * orig.clone()),
+                q_last * (acc_cur * orig - permuted),
+            ]
+        });
+
+        meta.create_gate("permuted mpt table entries first", |meta| {
+            let enable = meta.query_fixed(table.enable(), Rotation::cur());
+            let q_trans = meta.query_selector(q_trans);
+            let proof_type = meta.query_advice(table.columns()[2], Rotation::cur());
+            let proof_type_inv = meta.query_advice(proof_type_inv, Rotation::cur());
+            let is_entry_next = meta.query_advice(is_entry, Rotation::next());
+            let is_entry = meta.query_advice(is_entry, Rotation::cur());
+            let one = Expression::Constant(F::one());
+
+            vec![
+                enable.clone() * (is_entry.clone() - proof_type.clone() * proof_type_inv),
+                enable * (one.clone() - is_entry.clone()) * proof_type,
+                q_trans * is_entry_next * (one - is_entry),
+            ]
+        });
+
+        let sort_cols = permuted.sort_columns();
+        meta.create_gate("permuted mpt table sorted", |meta| {
+            let enable =
+                meta.query_selector(q_trans) * meta.query_advice(is_entry, Rotation::next());
+            let flags = first_diff.map(|col| meta.query_advice(col, Rotation::cur()));
+            let deltas = sort_cols.map(|col| {
+                meta.query_advice(col, Rotation::next()) - meta.query_advice(col, Rotation::cur())
+            });
+            let bytes =
+                diff_bytes
+                    .iter()
+                    .rev()
+                    .fold(Expression::Constant(F::zero()), |acc, col| {
+                        acc * Expression::Constant(F::from(256))
+                            + meta.query_advice(*col, Rotation::cur())
+                    });
+            let one = Expression::Constant(F::one());
+
+            let mut constraints: Vec<_> = flags
+                .iter()
+                .map(|flag| enable.clone() * flag.clone() * (one.clone() - flag.clone()))
+                .collect();
+            let flag_sum = flags
+                .iter()
+                .cloned()
+                .reduce(|acc, flag| acc + flag)
+                .expect("not null");
+            constraints.push(enable.clone() * (one.clone() - flag_sum));
+            // the fields before the flagged one are equal
+            for i in 0..flags.len() - 1 {
+                let flagged_later = flags[i + 1..]
+                    .iter()
+                    .cloned()
+                    .reduce(|acc, flag| acc + flag)
+                    .expect("not null");
+                constraints.push(enable.clone() * flagged_later * deltas[i].clone());
+            }
+            let delta = flags
+                .into_iter()
+                .zip(deltas)
+                .map(|(flag, delta)| flag * delta)
+                .reduce(|acc, e| acc + e)
+                .expect("not null");
+            constraints.push(enable * (delta - one - bytes));
+            constraints
+        });
+        for col in diff_bytes {
+            range_check_u8.range_check_col(meta, "permuted mpt table sort byte", col);
         }
+
+        config.permuted.replace(PermutedConfig {
+            permuted,
+            row_index,
+            acc,
+            q_first,
+            q_trans,
+            q_last,
+            theta,
+            gamma,
+            is_entry,
+            proof_type_inv,
+            first_diff,
+            diff_bytes,
+            range_check_u8,
+        });
     }
 
     pub fn load(&self, layouter: &mut impl Layouter<F>) -> Result<(), Error> {
         assert!(self.entries.len() <= self.rows);
 
         let config = &self.config;
-        RangeCheckChip::construct(config.range_check_u8.clone()).load(layouter)?;
+        match (&config.value_rep, &config.permuted) {
+            (ValueRep::Rlc { range_check_u8, .. }, _) => {
+                RangeCheckChip::construct(range_check_u8.clone()).load(layouter)?
+            }
+            // the byte table is only configured for the order of permuted copy
+            (ValueRep::Word { .. }, Some(cfg)) => {
+                RangeCheckChip::construct(cfg.range_check_u8.clone()).load(layouter)?
+            }
+            (ValueRep::Word { .. }, None) => (),
+        }
+
+        let challenges = config.permuted.as_ref().map(|cfg| {
+            (
+                layouter.get_challenge(cfg.theta),
+                layouter.get_challenge(cfg.gamma),
+            )
+        });
 
         layouter.assign_region(
             || "mpt table",
             |mut region| {
+                // the values of each row, in the order of `Config::compressed_columns`
+                let mut row_values = Vec::with_capacity(self.rows);
+
                 for (offset, entry) in self.entries.iter().enumerate() {
                     for (index, col) in config.proof_sel.as_slice().iter().copied().enumerate() {
                         region.assign_advice(
@@ -535,11 +1257,40 @@ impl<F: FieldExt> MPTTable<F> {
                         )?;
                     }
 
-                    let base_entries = entry
-                        .base
-                        .map(|entry| entry.map(Value::known).unwrap_or_else(Value::unknown));
+                    let mut base = entry.base;
+                    // the values of the columns following base in `Config::compressed_columns`
+                    let mut tail = Vec::new();
+                    if let ValueRep::Word {
+                        storage_key_lo,
+                        new_value_lo,
+                        old_value_lo,
+                    } = &config.value_rep
+                    {
+                        let [key, old_value, new_value] = entry.words();
+                        base[1] = Value::known(key.0);
+                        base[5] = Value::known(new_value.0);
+                        base[6] = Value::known(old_value.0);
+                        for (col, v) in [
+                            (*storage_key_lo, key.1),
+                            (*new_value_lo, new_value.1),
+                            (*old_value_lo, old_value.1),
+                        ] {
+                            region.assign_advice(
+                                || format!("assign for mpt table lo word {offset}"),
+                                col,
+                                offset,
+                                || Value::known(v),
+                            )?;
+                            tail.push(Value::known(v));
+                        }
+                    } else {
+                        tail.extend(
+                            [entry.storage_key.limb_0(), entry.storage_key.limb_1()]
+                                .map(Value::known),
+                        );
+                    }
 
-                    for (val, col) in base_entries.into_iter().zip([
+                    for (val, col) in base.into_iter().zip([
                         config.address,
                         config.storage_key,
                         config.proof_type,
@@ -555,64 +1306,45 @@ impl<F: FieldExt> MPTTable<F> {
                             || val,
                         )?;
                     }
-
-                    config.storage_key_2.assign(
-                        &mut region,
-                        offset,
-                        &(entry.storage_key.limb_0(), entry.storage_key.limb_1()),
-                    )?;
-                    config.new_value_2.assign(
-                        &mut region,
-                        offset,
-                        &(entry.new_value.limb_0(), entry.new_value.limb_1()),
-                    )?;
-                    config.old_value_2.assign(
-                        &mut region,
-                        offset,
-                        &(entry.old_value.limb_0(), entry.old_value.limb_1()),
-                    )?;
-
-                    config.key_rep.assign(
-                        &mut region,
-                        offset,
-                        RepCfg::<16, 8>::le_value_to_limbs(entry.storage_key.limb_0())
-                            .as_slice()
-                            .iter()
-                            .chain(
-                                RepCfg::<16, 8>::le_value_to_limbs(entry.storage_key.limb_1())
-                                    .as_slice()
-                                    .iter(),
-                            ),
-                    )?;
-
-                    config.new_val_rep.assign(
-                        &mut region,
-                        offset,
-                        RepCfg::<16, 8>::le_value_to_limbs(entry.new_value.limb_0())
-                            .as_slice()
-                            .iter()
-                            .chain(
-                                RepCfg::<16, 8>::le_value_to_limbs(entry.new_value.limb_1())
-                                    .as_slice()
-                                    .iter(),
-                            ),
-                    )?;
-
-                    config.old_val_rep.assign(
-                        &mut region,
-                        offset,
-                        RepCfg::<16, 8>::le_value_to_limbs(entry.old_value.limb_0())
-                            .as_slice()
-                            .iter()
-                            .chain(
-                                RepCfg::<16, 8>::le_value_to_limbs(entry.old_value.limb_1())
+                    row_values.push(base.into_iter().chain(tail).collect::<Vec<_>>());
+
+                    if let ValueRep::Rlc {
+                        key_rep,
+                        new_val_rep,
+                        old_val_rep,
+                        storage_key_2,
+                        new_value_2,
+                        old_value_2,
+                        ..
+                    } = &config.value_rep
+                    {
+                        for (pair, rep, kv) in [
+                            (storage_key_2, key_rep, &entry.storage_key),
+                            (new_value_2, new_val_rep, &entry.new_value),
+                            (old_value_2, old_val_rep, &entry.old_value),
+                        ] {
+                            pair.assign(&mut region, offset, &(kv.limb_0(), kv.limb_1()))?;
+                            rep.assign(
+                                &mut region,
+                                offset,
+                                RepCfg::<16, 8>::le_value_to_limbs(kv.limb_0())
                                     .as_slice()
-                                    .iter(),
-                            ),
-                    )?;
+                                    .iter()
+                                    .chain(
+                                        RepCfg::<16, 8>::le_value_to_limbs(kv.limb_1())
+                                            .as_slice()
+                                            .iter(),
+                                    ),
+                            )?;
+                        }
+                    }
                 }
 
                 for row in self.entries.len()..self.rows {
+                    row_values.push(vec![
+                        Value::known(F::zero());
+                        config.compressed_columns().len()
+                    ]);
                     for col in config.proof_sel.into_iter().chain([
                         config.proof_type,
                         config.address,
@@ -630,16 +1362,52 @@ impl<F: FieldExt> MPTTable<F> {
                         )?;
                     }
 
-                    config.storage_key_2.flush(&mut region, row)?;
-                    config.new_value_2.flush(&mut region, row)?;
-                    config.old_value_2.flush(&mut region, row)?;
-                    config.key_rep.flush(&mut region, row)?;
-                    config.new_val_rep.flush(&mut region, row)?;
-                    config.old_val_rep.flush(&mut region, row)?;
+                    match &config.value_rep {
+                        ValueRep::Rlc {
+                            key_rep,
+                            new_val_rep,
+                            old_val_rep,
+                            storage_key_2,
+                            new_value_2,
+                            old_value_2,
+                            ..
+                        } => {
+                            for pair in [storage_key_2, new_value_2, old_value_2] {
+                                pair.flush(&mut region, row)?;
+                            }
+                            for rep in [key_rep, new_val_rep, old_val_rep] {
+                                rep.flush(&mut region, row)?;
+                            }
+                        }
+                        ValueRep::Word {
+                            storage_key_lo,
+                            new_value_lo,
+                            old_value_lo,
+                        } => {
+                            for col in [*storage_key_lo, *new_value_lo, *old_value_lo] {
+                                region.assign_advice(
+                                    || "flush rows",
+                                    col,
+                                    row,
+                                    || Value::known(F::zero()),
+                                )?;
+                            }
+                        }
+                    }
                 }
 
                 for offset in 0..self.rows {
                     config.sel.enable(&mut region, offset)?;
+                    region.assign_fixed(
+                        || "mpt table enable",
+                        config.enable,
+                        offset,
+                        || Value::known(F::one()),
+                    )?;
+                }
+
+                if let (Some(cfg), Some((theta, gamma))) = (&config.permuted, challenges) {
+                    self.assign_permuted(&mut region, cfg, &row_values, theta, gamma)?;
                 }
 
                 Ok(())
@@ -648,6 +1416,123 @@ impl<F: FieldExt> MPTTable<F> {
 
         Ok(())
     }
+
+    fn assign_permuted(
+        &self,
+        region: &mut Region<'_, F>,
+        cfg: &PermutedConfig,
+        row_values: &[Vec<Value<F>>],
+        theta: Value<F>,
+        gamma: Value<F>,
+    ) -> Result<(), Error> {
+        let compress = |index: usize, values: &[Value<F>]| {
+            values
+                .iter()
+                .fold(Value::known(F::from(index as u64)), |acc, v| {
+                    acc * theta + *v
+                })
+                + gamma
+        };
+        let permuted_cols = cfg.permuted.compressed_columns();
+        let mut acc = Value::known(F::one());
+        let entry_of = |src: usize| self.entries.get(src);
+        let sort_fields = |src: usize| {
+            let [address, key_hi, key_lo] =
+                entry_of(src).map_or([F::zero(); 3], |entry| entry.sort_fields());
+            [address, key_hi, key_lo, F::from(src as u64)]
+        };
+
+        for (offset, &src) in self.order.iter().enumerate() {
+            region.assign_fixed(
+                || "mpt table row index",
+                cfg.row_index,
+                offset,
+                || Value::known(F::from(offset as u64)),
+            )?;
+            region.assign_fixed(
+                || "permuted mpt table enable",
+                cfg.permuted.table.enable(),
+                offset,
+                || Value::known(F::one()),
+            )?;
+            region.assign_advice(
+                || format!("permuted mpt table index {offset}"),
+                cfg.permuted.index,
+                offset,
+                || Value::known(F::from(src as u64)),
+            )?;
+            for (col, val) in permuted_cols.iter().zip(&row_values[src]) {
+                region.assign_advice(
+                    || format!("permuted mpt table offset {offset}"),
+                    *col,
+                    offset,
+                    || *val,
+                )?;
+            }
+            region.assign_advice(|| "permuted mpt table acc", cfg.acc, offset, || acc)?;
+
+            let proof_type =
+                entry_of(src).map_or(F::zero(), |entry| F::from(entry.proof_type as u64));
+            for (col, val) in [
+                (cfg.is_entry, F::from(entry_of(src).is_some() as u64)),
+                (cfg.proof_type_inv, proof_type.invert().unwrap_or(F::zero())),
+            ] {
+                region.assign_advice(
+                    || format!("permuted mpt table entry {offset}"),
+                    col,
+                    offset,
+                    || Value::known(val),
+                )?;
+            }
+            // the first field differing from the next row of entry, and the bytes of its
+            // difference minus 1, which can not be decomposed if the rows are out of order
+            let mut flags = [F::zero(); 4];
+            let mut bytes = [F::zero(); SORT_DIFF_BYTES];
+            if let Some(&next) = self
+                .order
+                .get(offset + 1)
+                .filter(|next| entry_of(**next).is_some())
+            {
+                let (cur, next) = (sort_fields(src), sort_fields(next));
+                let first = (0..cur.len())
+                    .find(|i| cur[*i] != next[*i])
+                    .unwrap_or(cur.len() - 1);
+                flags[first] = F::one();
+                let delta = next[first] - cur[first] - F::one();
+                for (byte, v) in bytes.iter_mut().zip(delta.to_repr().as_ref()) {
+                    *byte = F::from(*v as u64);
+                }
+            }
+            for (col, val) in cfg
+                .first_diff
+                .iter()
+                .zip(flags)
+                .chain(cfg.diff_bytes.iter().zip(bytes))
+            {
+                region.assign_advice(
+                    || format!("permuted mpt table order {offset}"),
+                    *col,
+                    offset,
+                    || Value::known(val),
+                )?;
+            }
+
+            if offset == 0 {
+                cfg.q_first.enable(region, offset)?;
+            }
+            if offset + 1 == self.rows {
+                cfg.q_last.enable(region, offset)?;
+            } else {
+                cfg.q_trans.enable(region, offset)?;
+            }
+
+            let orig = compress(offset, &row_values[offset]);
+            let permuted = compress(src, &row_values[src]);
+            acc = acc * orig * permuted.map(|v| v.invert().unwrap());
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -675,7 +1560,7 @@ mod test {
 
         fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
             let dummy_randomness = Expression::Constant(Fp::from(0x100u64));
-            let base_tbl = [0; 7].map(|_| meta.advice_column());
+            let base_tbl = MptTable::new([0; 7].map(|_| meta.advice_column()), meta.fixed_column());
             MPTTable::<Fp>::configure(meta, base_tbl, dummy_randomness)
         }
 
@@ -691,6 +1576,42 @@ mod test {
         }
     }
 
+    // the mpt table with its permuted copy, the order of copy can be overridden
+    #[derive(Clone)]
+    struct TestPermutedTableCircuit {
+        entries: Vec<MPTEntry<Fp>>,
+        order: Option<Vec<usize>>,
+    }
+
+    impl Circuit<Fp> for TestPermutedTableCircuit {
+        type Config = Config;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let mut config = TestMPTTableCircuit::configure(meta);
+            MPTTable::<Fp>::configure_permuted(meta, &mut config);
+            config
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let layout_range = self.entries.len() + 1;
+            let mut mpt_table = MPTTable::construct(config, self.entries.clone(), layout_range);
+            if let Some(order) = &self.order {
+                mpt_table.order = order.clone();
+            }
+            mpt_table.load(&mut layouter)?;
+            Ok(())
+        }
+    }
+
     #[test]
     fn circuit_degrees() {
         let mut cs: ConstraintSystem<Fp> = Default::default();
@@ -759,20 +1680,37 @@ mod test {
         };
 
         let randomness = Fp::from(0x10000u64);
-        let entry = MPTEntry::from_op(MPTProofType::StorageChanged, &op, randomness);
-        let base = entry.base.map(|v| v.unwrap());
-
-        assert_eq!(base[0], address);
-        assert_eq!(base[1], store_key.u8_rlc(randomness));
-        assert_eq!(base[3], op.account_root());
-        assert_eq!(base[4], op.account_root_before());
-        assert_eq!(base[5], store_after.u8_rlc(randomness));
-        assert_eq!(base[6], store_before.u8_rlc(randomness));
+        let entry = MPTEntry::from_op(MPTProofType::StorageChanged, &op, Value::known(randomness));
+        let base = entry.base;
+
+        base[0].assert_if_known(|v| *v == address);
+        base[1].assert_if_known(|v| *v == store_key.u8_rlc(randomness));
+        base[3].assert_if_known(|v| *v == op.account_root());
+        base[4].assert_if_known(|v| *v == op.account_root_before());
+        base[5].assert_if_known(|v| *v == store_after.u8_rlc(randomness));
+        base[6].assert_if_known(|v| *v == store_before.u8_rlc(randomness));
+
+        // the exported row carries the same values, and survives a round trip in json
+        let row = MptTableRow::from_op(MPTProofType::StorageChanged, &op, randomness);
+        assert_eq!(row.storage_key, to_hash(store_key.u8_rlc(randomness)));
+        assert_eq!(row.storage_key_hi, to_hash(store_key.limb_0()));
+        assert_eq!(row.old_value_lo, to_hash(store_before.limb_1()));
+        assert_eq!(row.new_value_hi, to_hash(store_after.limb_0()));
+        assert_eq!(row.new_root, to_hash(op.account_root()));
+        let json = serde_json::to_string(&row).unwrap();
+        assert_eq!(serde_json::from_str::<MptTableRow>(&json).unwrap(), row);
+
+        // without the challenge only the fields not relying on rlc are known
+        let entry = MPTEntry::from_op(MPTProofType::StorageChanged, &op, Value::unknown());
+        entry.base[3].assert_if_known(|v| *v == op.account_root());
+        entry.base[1].assert_if_known(|_| false);
+        entry.base[5].assert_if_known(|_| false);
     }
 
-    #[test]
-    fn solo_mpt_table() {
+    fn solo_entries() -> Vec<MPTEntry<Fp>> {
         let randomness = Fp::from(0x100u64);
+        let root_before = rand_fp();
+        let root_after = rand_fp();
         let address =
             Fp::from_str_vartime("1024405194924367004341088897210496901613465825763").unwrap(); //0xb36feaeaf76c2a33335b73bef9aef7a23d9af1e3
         let storage_key = KeyValue::create_base((
@@ -786,12 +1724,12 @@ mod test {
                 address,
                 Fp::zero(),
                 Fp::from(MPTProofType::BalanceChanged as u64),
-                rand_fp(),
-                rand_fp(),
+                root_after,
+                root_before,
                 Fp::from(123456789u64),
                 Fp::from(123456790u64),
             ]
-            .map(Some),
+            .map(Value::known),
             storage_key: Default::default(),
             new_value: Default::default(),
             old_value: Default::default(),
@@ -806,11 +1744,11 @@ mod test {
                 storage_key.u8_rlc(randomness),
                 Fp::from(MPTProofType::StorageChanged as u64),
                 rand_fp(),
-                entry1.base[4].unwrap(),
+                root_before,
                 Fp::from(10u64) + (Fp::from(3u64) * bit128),
                 Fp::from(1u64) + (Fp::from(3u64) * bit128),
             ]
-            .map(Some),
+            .map(Value::known),
             storage_key: storage_key.clone(),
             new_value: KeyValue::create_base((Fp::from(3u64), Fp::from(10u64))),
             old_value: KeyValue::create_base((Fp::from(3u64), Fp::from(1u64))),
@@ -822,40 +1760,136 @@ mod test {
                 address + Fp::one(),
                 Fp::zero(),
                 Fp::from(MPTProofType::AccountDoesNotExist as u64),
-                entry2.base[4].unwrap(),
-                entry2.base[4].unwrap(),
+                root_before,
+                root_before,
                 Fp::zero(),
                 Fp::zero(),
             ]
-            .map(Some),
+            .map(Value::known),
             storage_key: Default::default(),
             new_value: Default::default(),
             old_value: Default::default(),
         };
 
         let entry4 = MPTEntry {
-            proof_type: MPTProofType::PoseidonCodeHashExists,
+            proof_type: MPTProofType::NonceChanged,
             base: [
                 address + Fp::one(),
                 Fp::zero(),
-                Fp::from(MPTProofType::PoseidonCodeHashExists as u64),
+                Fp::from(MPTProofType::NonceChanged as u64),
                 rand_fp(),
                 rand_fp(),
-                Fp::one(),
+                Fp::from(2u64),
                 Fp::one(),
             ]
-            .map(Some),
+            .map(Value::known),
             storage_key: Default::default(),
             new_value: Default::default(),
             old_value: Default::default(),
         };
 
+        vec![entry1, entry2, entry3, entry4]
+    }
+
+    #[test]
+    fn solo_mpt_table() {
         let circuit = TestMPTTableCircuit {
-            entries: vec![entry1, entry2, entry3, entry4],
+            entries: solo_entries(),
         };
         let k = 9;
         let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
         let ret = prover.verify();
         assert_eq!(ret, Ok(()), "{:#?}", ret);
     }
+
+    #[test]
+    fn mpt_table_refuses_unbound_rows() {
+        let root = rand_fp();
+        let entry = |proof_type: MPTProofType, new_root: Fp| MPTEntry {
+            proof_type,
+            base: [
+                Fp::from(0x1234u64),
+                Fp::zero(),
+                Fp::from(proof_type as u64),
+                new_root,
+                root,
+                Fp::zero(),
+                Fp::zero(),
+            ]
+            .map(Value::known),
+            storage_key: Default::default(),
+            new_value: Default::default(),
+            old_value: Default::default(),
+        };
+
+        for entry in [
+            // the root is changed by a not exist proof
+            entry(MPTProofType::AccountDoesNotExist, root + Fp::one()),
+            // the types without any lookup binding their values
+            entry(MPTProofType::PoseidonCodeHashExists, root),
+            entry(MPTProofType::CodeSizeExists, root),
+            entry(MPTProofType::AccountDestructed, rand_fp()),
+        ] {
+            let circuit = TestMPTTableCircuit {
+                entries: vec![entry],
+            };
+            let prover = MockProver::<Fp>::run(9, &circuit, vec![]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn permuted_mpt_table() {
+        let mut entries = solo_entries();
+        entries.reverse();
+
+        // (address, no storage key) comes first, and the padding row is kept in the end
+        let config = TestPermutedTableCircuit::configure(&mut Default::default());
+        let mpt_table = MPTTable::construct(config, entries.clone(), entries.len() + 1);
+        assert_eq!(mpt_table.order, vec![3, 2, 0, 1, 4]);
+
+        let circuit = TestPermutedTableCircuit {
+            entries,
+            order: None,
+        };
+        let k = 9;
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        let ret = prover.verify();
+        assert_eq!(ret, Ok(()), "{:#?}", ret);
+
+        // a copy which is not a permutation of the table
+        let circuit = TestPermutedTableCircuit {
+            order: Some(vec![3, 3, 0, 1, 4]),
+            ..circuit
+        };
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // permutations out of the order, with two entries swapped or a padding row
+        // before the entries
+        for order in [vec![2, 3, 0, 1, 4], vec![3, 2, 4, 0, 1]] {
+            let circuit = TestPermutedTableCircuit {
+                order: Some(order),
+                ..circuit.clone()
+            };
+            let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn table_exprs_of_modes() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let tables = [
+            MptTable::construct(&mut cs),
+            MptTable::construct_word(&mut cs),
+        ];
+        for (table, len) in tables.into_iter().zip([7, 10]) {
+            cs.lookup_any("table exprs", |meta| {
+                let exprs = table.table_exprs(meta);
+                assert_eq!(exprs.len(), len);
+                exprs.into_iter().map(|expr| (expr.clone(), expr)).collect()
+            });
+        }
+    }
 }
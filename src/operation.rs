@@ -1,8 +1,8 @@
 //! represent the data for a single operation on the MPT
 
 use super::{eth, serde, HashType};
-use crate::hash::Hashable;
-use halo2_proofs::arithmetic::FieldExt;
+use crate::{hash::Hashable, MPTProofType};
+use halo2_proofs::{arithmetic::FieldExt, halo2curves::group::ff::PrimeField};
 use num_bigint::BigUint;
 use std::cmp::Ordering;
 use std::convert::TryFrom;
@@ -103,22 +103,26 @@ impl<Fp: FieldExt> MPTPath<Fp> {
         l: usize,
         new_key: Fp,
         mut hasher: impl FnMut(&Fp, &Fp) -> Fp,
-    ) -> Self {
+    ) -> Result<Self, TraceError> {
         if l == 0 {
-            return self;
+            return Ok(self);
         }
 
-        assert!(self.hash_types.len() > 1, "can not extend empty path");
-        let ins_pos = self.hash_types.len() - 1;
         // can only extend a path with leaf
-        let new_key_immediate = hasher(&Fp::one(), &self.key().expect("can only extend leaf"));
-        let status = match self.status {
-            MPTPathStatus::Leaf((fp, fp_immediate)) => MPTPathStatus::Extended((
-                (new_key, new_key_immediate),
-                (fp, fp_immediate, self.hashes[ins_pos]),
-            )),
-            _ => panic!("can only extend leaf path"),
+        let (fp, fp_immediate) = match self.status {
+            MPTPathStatus::Leaf(leaf) if self.hash_types.len() > 1 => leaf,
+            _ => {
+                return Err(TraceError::DataErr(
+                    "can only extend a path ending in leaf".to_string(),
+                ))
+            }
         };
+        let ins_pos = self.hash_types.len() - 1;
+        let new_key_immediate = hasher(&Fp::one(), &fp);
+        let status = MPTPathStatus::Extended((
+            (new_key, new_key_immediate),
+            (fp, fp_immediate, self.hashes[ins_pos]),
+        ));
 
         let mut hash_types = self.hash_types;
         let mut addi_types = vec![HashType::LeafExt; l - 1];
@@ -135,12 +139,12 @@ impl<Fp: FieldExt> MPTPath<Fp> {
         hashes[ins_pos] = Fp::zero();
         drop(hashes.splice(ins_pos..ins_pos, addi_hashes));
 
-        Self {
+        Ok(Self {
             hash_types,
             hashes,
             status,
             ..self
-        }
+        })
     }
 
     pub(crate) fn create_with_hasher(
@@ -212,8 +216,9 @@ impl<Fp: Hashable> MPTPath<Fp> {
     }
 
     /// extend a common path (contain only midle and leaf/empty) to under extended status,
-    /// it require caller to calc how many level should be extended and what the new key is
-    pub fn extend(self, l: usize, new_key: Fp) -> Self {
+    /// it require caller to calc how many level should be extended and what the new key is,
+    /// and fails if the path does not end in a leaf
+    pub fn extend(self, l: usize, new_key: Fp) -> Result<Self, TraceError> {
         self.extend_with_hasher(l, new_key, |a, b| <Fp as Hashable>::hash([*a, *b]))
     }
 }
@@ -626,6 +631,80 @@ impl<Fp: FieldExt> AccountOp<Fp> {
     pub fn account_root_before(&self) -> Fp {
         self.acc_trie.start_root()
     }
+
+    /// the proof type of operation in mpt table, inferred from the changes of account
+    /// or storage. an operation changing nothing in an existed account is taken as a
+    /// read on nonce
+    pub fn proof_type(&self) -> MPTProofType {
+        if self.state_trie.is_some() {
+            return if self.store_before.is_none() && self.store_after.is_none() {
+                MPTProofType::StorageDoesNotExist
+            } else {
+                MPTProofType::StorageChanged
+            };
+        }
+        match (&self.account_before, &self.account_after) {
+            (None, None) => MPTProofType::AccountDoesNotExist,
+            (Some(_), None) => MPTProofType::AccountDestructed,
+            _ => self
+                .changed_fields()
+                .first()
+                .copied()
+                .unwrap_or(MPTProofType::NonceChanged),
+        }
+    }
+
+    /// check if the proof type is consistent with the operation, i.e. it is the inferred one,
+    /// or another field of account which has been changed (or read, if nothing is changed)
+    pub fn accepts_proof_type(&self, proof_type: MPTProofType) -> bool {
+        if proof_type == self.proof_type() {
+            return true;
+        }
+        if self.state_trie.is_some() || self.account_after.is_none() {
+            return false;
+        }
+        let changed = self.changed_fields();
+        let is_field = matches!(
+            proof_type,
+            MPTProofType::NonceChanged
+                | MPTProofType::BalanceChanged
+                | MPTProofType::CodeHashExists
+                | MPTProofType::PoseidonCodeHashExists
+                | MPTProofType::CodeSizeExists
+        );
+        is_field
+            && (changed.contains(&proof_type)
+                || (changed.is_empty() && self.account_before.is_some()))
+    }
+
+    // the fields of account changed by operation, an absent account is taken as an empty one
+    fn changed_fields(&self) -> Vec<MPTProofType> {
+        let empty = Account::default();
+        let before = self.account_before.as_ref().unwrap_or(&empty);
+        let after = self.account_after.as_ref().unwrap_or(&empty);
+        [
+            (MPTProofType::NonceChanged, before.nonce != after.nonce),
+            (
+                MPTProofType::BalanceChanged,
+                before.balance != after.balance,
+            ),
+            (
+                MPTProofType::CodeHashExists,
+                before.codehash != after.codehash,
+            ),
+            (
+                MPTProofType::PoseidonCodeHashExists,
+                before.poseidon_codehash != after.poseidon_codehash,
+            ),
+            (
+                MPTProofType::CodeSizeExists,
+                before.code_size != after.code_size,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(proof_type, changed)| changed.then_some(proof_type))
+        .collect()
+    }
 }
 
 impl<Fp: Hashable> AccountOp<Fp> {
@@ -657,12 +736,93 @@ impl<Fp: Hashable> AccountOp<Fp> {
 }
 
 /// include error raised in deserialize or data verification
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum TraceError {
     /// error in deserialize
+    #[error("deserialize error: {0}")]
     DeErr(std::io::Error),
     /// error for malform data
+    #[error("malformed data: {0}")]
     DataErr(String),
+    /// the root recovered from a path differs from the root in the trace
+    #[error("root mismatch: expected {expected:?}, got {actual:?}")]
+    RootMismatch {
+        /// root given in the trace
+        expected: serde::Hash,
+        /// root recovered from the path
+        actual: serde::Hash,
+    },
+    /// the path bits do not fit in the length of the path
+    #[error("bad path bits {path_part:#x} for path of length {len}")]
+    BadPathBits {
+        /// path bits given in the trace
+        path_part: BigUint,
+        /// number of nodes in the path
+        len: usize,
+    },
+    /// the account data does not hash to the leaf of the account path
+    #[error("account hash mismatch: leaf is {expected:?}, account hashes to {actual:?}")]
+    AccountHashMismatch {
+        /// value of the leaf in the account trie
+        expected: serde::Hash,
+        /// hash of the account data
+        actual: serde::Hash,
+    },
+    /// the storage value does not hash to the leaf of the storage path
+    #[error("storage hash mismatch: leaf is {expected:?}, value hashes to {actual:?}")]
+    StorageHashMismatch {
+        /// value of the leaf in the storage trie
+        expected: serde::Hash,
+        /// hash of the storage value
+        actual: serde::Hash,
+    },
+    /// the old and new paths have different siblings at a depth they share
+    #[error("siblings of old and new path differ at depth {depth}")]
+    SiblingMismatch {
+        /// depth of the sibling, from the root
+        depth: usize,
+    },
+    /// a hash in the trace is not a canonical field element
+    #[error("{0:?} is not a canonical field element")]
+    NonCanonical(serde::Hash),
+    /// more than one field of an account is changed by a single trace
+    #[error("more than one account field changed in a single trace")]
+    MultiFieldAccountChange,
+    /// the trace has a storage path but no storage key
+    #[error("storage key is missing")]
+    MissingStorageKey,
+    /// the trace has an account leaf but no account data for it
+    #[error("account data is missing for existing leaf")]
+    MissingAccount,
+    /// error raised while handling one trace of a sequence
+    #[error("trace {index}: {source}")]
+    AtTrace {
+        /// index of the offending trace
+        index: usize,
+        /// error raised for the trace
+        #[source]
+        source: Box<TraceError>,
+    },
+}
+
+impl TraceError {
+    /// attach the index of the offending trace to the error
+    pub fn at(self, index: usize) -> Self {
+        Self::AtTrace {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
+
+/// the little-endian repr of a field element, as the hashes in traces
+pub fn to_hash<Fp: FieldExt>(fp: Fp) -> serde::Hash {
+    let mut bytes = [0u8; 32];
+    bytes
+        .iter_mut()
+        .zip(fp.to_repr().as_ref())
+        .for_each(|(o, i)| *o = *i);
+    serde::HexBytes(bytes)
 }
 
 // parse Trace data into MPTPath and additional data (siblings and path)
@@ -677,6 +837,13 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTPath> for SMTPathParse<Fp> {
             siblings.push(s);
         }
 
+        if path_trace.path_part.bits() > siblings.len() as u64 {
+            return Err(TraceError::BadPathBits {
+                path_part: path_trace.path_part.clone(),
+                len: siblings.len(),
+            });
+        }
+
         let mut path_bits: Vec<bool> = Vec::new();
         let mut path: Vec<Fp> = Vec::new();
 
@@ -697,8 +864,12 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTPath> for SMTPathParse<Fp> {
         let mpt_path = MPTPath::create(&path_bits, &siblings, key, leaf);
         // sanity check
         let root = Fp::from_bytes_wide(&path_trace.root.cast());
-        // dbg!(root, mpt_path.root());
-        assert_eq!(root, mpt_path.root()); // trying to recreate this logic....
+        if root != mpt_path.root() {
+            return Err(TraceError::RootMismatch {
+                expected: path_trace.root,
+                actual: to_hash(mpt_path.root()),
+            });
+        }
 
         Ok(SMTPathParse(mpt_path, siblings, path))
     }
@@ -720,38 +891,51 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
         let mut new = after_parsed.0;
 
         // sanity check
-        for (a, b) in after_parsed.1.iter().zip(&before_parsed.1) {
+        for (depth, (a, b)) in after_parsed.1.iter().zip(&before_parsed.1).enumerate() {
             if a != b {
-                println!("compare {a:?} {b:?}");
-                return Err(TraceError::DataErr("unmatch siblings".to_string()));
+                return Err(TraceError::SiblingMismatch { depth });
             }
         }
 
         // update for inserting op
         let (siblings, path, key_immediate) = match old.depth().cmp(&new.depth()) {
             Ordering::Less => {
-                assert_eq!(new.key(), Some(key));
+                if new.key() != Some(key) {
+                    return Err(TraceError::DataErr(
+                        "inserted leaf does not have the key of the trace".to_string(),
+                    ));
+                }
                 let ext_dist = new.depth() - old.depth();
-                old = old.extend(ext_dist, key);
+                old = old.extend(ext_dist, key)?;
                 (
                     after_parsed.1,
                     after_parsed.2,
-                    new.key_immediate().expect("should be leaf node"),
+                    new.key_immediate()
+                        .ok_or_else(|| TraceError::DataErr("should be leaf node".to_string()))?,
                 )
             }
             Ordering::Greater => {
-                assert_eq!(old.key(), Some(key));
+                if old.key() != Some(key) {
+                    return Err(TraceError::DataErr(
+                        "removed leaf does not have the key of the trace".to_string(),
+                    ));
+                }
                 let ext_dist = old.depth() - new.depth();
-                new = new.extend(ext_dist, key);
+                new = new.extend(ext_dist, key)?;
                 (
                     before_parsed.1,
                     before_parsed.2,
-                    old.key_immediate().expect("should be leaf node"),
+                    old.key_immediate()
+                        .ok_or_else(|| TraceError::DataErr("should be leaf node".to_string()))?,
                 )
             }
             Ordering::Equal => {
                 if old.key() != Some(key) && new.key() != Some(key) {
-                    assert_eq!(old.key(), new.key());
+                    if old.key() != new.key() {
+                        return Err(TraceError::DataErr(
+                            "leaves of both paths should be the same".to_string(),
+                        ));
+                    }
                     let mut siblings = before_parsed.1;
                     let mut path = before_parsed.2;
 
@@ -777,15 +961,23 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
                                 path.push(Fp::from(k2_bit as u64));
                                 siblings.push(Fp::zero());
                             }
-                            assert_ne!(k1, k2);
+                            if k1 == k2 {
+                                return Err(TraceError::DataErr(
+                                    "key of trace collides with existing leaf".to_string(),
+                                ));
+                            }
                             k2_bit = k2.is_odd().unwrap_u8();
                         }
 
-                        assert!(common_prefix_depth >= old.depth());
+                        if common_prefix_depth < old.depth() {
+                            return Err(TraceError::DataErr(
+                                "existing leaf is deeper than the common prefix".to_string(),
+                            ));
+                        }
                         let ext_dist = common_prefix_depth - old.depth() + 1;
                         let last_node_hash = old.hashes[old.hashes.len() - 2];
-                        old = old.extend(ext_dist, key);
-                        new = new.extend(ext_dist, key);
+                        old = old.extend(ext_dist, key)?;
+                        new = new.extend(ext_dist, key)?;
 
                         path.push(Fp::from(k2_bit as u64));
                         siblings.push(last_node_hash);
@@ -800,13 +992,17 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
                     (
                         before_parsed.1,
                         before_parsed.2,
-                        old.key_immediate().expect("should be leaf node"),
+                        old.key_immediate().ok_or_else(|| {
+                            TraceError::DataErr("should be leaf node".to_string())
+                        })?,
                     )
                 } else {
                     (
                         after_parsed.1,
                         after_parsed.2,
-                        new.key_immediate().expect("should be leaf node"),
+                        new.key_immediate().ok_or_else(|| {
+                            TraceError::DataErr("should be leaf node".to_string())
+                        })?,
                     )
                 }
             }
@@ -898,19 +1094,17 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
         )
             .try_into()?;
 
-        let state_trie: Option<SingleOp<Fp>> =
-            if trace.state_path[0].is_some() && trace.state_path[1].is_some() {
-                Some(
-                    (
-                        trace.state_path[0].as_ref().unwrap(),
-                        trace.state_path[1].as_ref().unwrap(),
-                        trace.state_key.unwrap(),
-                    )
-                        .try_into()?,
+        let state_trie: Option<SingleOp<Fp>> = match &trace.state_path {
+            [Some(before), Some(after)] => Some(
+                (
+                    before,
+                    after,
+                    trace.state_key.ok_or(TraceError::MissingStorageKey)?,
                 )
-            } else {
-                None
-            };
+                    .try_into()?,
+            ),
+            _ => None,
+        };
 
         let comm_state_root = match trace.common_state_root {
             Some(h) => Fp::from_bytes_wide(&h.cast()),
@@ -920,15 +1114,13 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
         let account_before = if let Some(leaf) = acc_trie.old.leaf() {
             let account_data = trace.account_update[0]
                 .as_ref()
-                .expect("account should exist when there is leaf");
+                .ok_or(TraceError::MissingAccount)?;
             let old_state_root = state_trie
                 .as_ref()
                 .map(|s| s.start_root())
                 .unwrap_or(comm_state_root);
             let account: Account<Fp> = (account_data, old_state_root).try_into()?;
-            // sanity check
-            dbg!("try_from SMTTrace to Account", account.account_hash(), leaf);
-            assert_eq!(account.account_hash(), leaf);
+            check_account_hash(&account, leaf)?;
 
             Some(account)
         } else {
@@ -938,15 +1130,14 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
         let account_after = if let Some(leaf) = acc_trie.new.leaf() {
             let account_data = trace.account_update[1]
                 .as_ref()
-                .expect("account should exist when there is leaf");
+                .ok_or(TraceError::MissingAccount)?;
             let new_state_root = state_trie
                 .as_ref()
                 .map(|s| s.new_root())
                 .unwrap_or(comm_state_root);
             let account: Account<Fp> = (account_data, new_state_root).try_into()?;
 
-            // sanity check
-            assert_eq!(account.account_hash(), leaf);
+            check_account_hash(&account, leaf)?;
             Some(account)
         } else {
             None
@@ -962,13 +1153,18 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
         let address_rep = KeyValue::from(&trace.address);
 
         let (store_key, store_before, store_after) = if state_trie.is_some() {
-            let update_pair = trace.state_update.as_ref().expect("state trie has existed");
+            let update_pair = trace
+                .state_update
+                .as_ref()
+                .ok_or_else(|| TraceError::DataErr("state update is missing".to_string()))?;
             (
                 Some(KeyValue::from(
                     &update_pair[0]
                         .as_ref()
                         .or(update_pair[1].as_ref())
-                        .expect("one of state update should not NONE")
+                        .ok_or_else(|| {
+                            TraceError::DataErr("one of state update should not NONE".to_string())
+                        })?
                         .key,
                 )),
                 update_pair[0].as_ref().map(|st| KeyValue::from(&st.value)),
@@ -992,8 +1188,130 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
     }
 }
 
+fn check_account_hash<Fp: FieldExt>(account: &Account<Fp>, leaf: Fp) -> Result<(), TraceError> {
+    if account.account_hash() != leaf {
+        return Err(TraceError::AccountHashMismatch {
+            expected: to_hash(leaf),
+            actual: to_hash(account.account_hash()),
+        });
+    }
+    Ok(())
+}
+
+/// parse a sequence of traces into operations, reporting the index of the first malformed trace.
+/// traces changing several account fields are split with `split_trace`
+pub fn ops_from_traces<Fp: Hashable>(
+    traces: &[serde::SMTTrace],
+) -> Result<Vec<AccountOp<Fp>>, TraceError> {
+    let mut ops = Vec::new();
+    for (i, trace) in traces.iter().enumerate() {
+        for trace in split_trace::<Fp>(trace.clone()).map_err(|e| e.at(i))? {
+            ops.push(AccountOp::try_from(&trace).map_err(|e| e.at(i))?);
+        }
+    }
+    Ok(ops)
+}
+
+/// split a trace which changes several fields of an account into a chain of traces changing
+/// one field each, in the order nonce, balance, code (code hash, poseidon code hash and code
+/// size are always changed together). the intermediate account roots are recomputed from the
+/// path of the updated account. any other trace is returned as is
+pub fn split_trace<Fp: Hashable>(
+    trace: serde::SMTTrace,
+) -> Result<Vec<serde::SMTTrace>, TraceError> {
+    let new_account = match &trace.account_update {
+        [_, Some(new_account)] if trace.state_path == [None, None] => new_account.clone(),
+        _ => return Ok(vec![trace]),
+    };
+    let mut account = trace.account_update[0]
+        .clone()
+        .unwrap_or_else(|| serde::AccountData {
+            nonce: 0,
+            balance: BigUint::default(),
+            code_hash: BigUint::default(),
+            poseidon_code_hash: BigUint::default(),
+            code_size: 0,
+        });
+
+    let mut steps = Vec::new();
+    if account.nonce != new_account.nonce {
+        account.nonce = new_account.nonce;
+        steps.push(account.clone());
+    }
+    if account.balance != new_account.balance {
+        account.balance = new_account.balance.clone();
+        steps.push(account.clone());
+    }
+    if account.code_hash != new_account.code_hash
+        || account.poseidon_code_hash != new_account.poseidon_code_hash
+        || account.code_size != new_account.code_size
+    {
+        account.code_hash = new_account.code_hash.clone();
+        account.poseidon_code_hash = new_account.poseidon_code_hash.clone();
+        account.code_size = new_account.code_size;
+        steps.push(account.clone());
+    }
+    if steps.len() < 2 {
+        return Ok(vec![trace]);
+    }
+
+    let storage_root = trace
+        .common_state_root
+        .map(|h| Fp::from_bytes_wide(&h.cast()))
+        .unwrap_or_else(Fp::zero);
+    let last = steps.len() - 1;
+    let mut traces = Vec::with_capacity(steps.len());
+    let (mut path, mut account) = (
+        trace.account_path[0].clone(),
+        trace.account_update[0].clone(),
+    );
+    for (i, step) in steps.into_iter().enumerate() {
+        let next_path = if i == last {
+            trace.account_path[1].clone()
+        } else {
+            let account_hash = Account::<Fp>::try_from((&step, storage_root))?.account_hash();
+            rehash_path(&trace.account_path[1], account_hash)?
+        };
+        traces.push(serde::SMTTrace {
+            account_path: [path, next_path.clone()],
+            account_update: [account, Some(step.clone())],
+            ..trace.clone()
+        });
+        path = next_path;
+        account = Some(step);
+    }
+    Ok(traces)
+}
+
+// replace the value of the leaf at the end of the path, and recompute the path up to the root.
+fn rehash_path<Fp: Hashable>(
+    path: &serde::SMTPath,
+    leaf_value: Fp,
+) -> Result<serde::SMTPath, TraceError> {
+    let mut path = path.clone();
+    let leaf = path
+        .leaf
+        .as_mut()
+        .ok_or_else(|| TraceError::DataErr("path should end in a leaf".to_string()))?;
+    leaf.value = to_hash(leaf_value);
+
+    let key = Fp::from_bytes_wide(&leaf.sibling.cast());
+    let mut digest = <Fp as Hashable>::hash([<Fp as Hashable>::hash([Fp::one(), key]), leaf_value]);
+    for (i, node) in path.path.iter_mut().enumerate().rev() {
+        node.value = to_hash(digest);
+        let sibling = Fp::from_bytes_wide(&node.sibling.cast());
+        digest = if (BigUint::from(1u64) << i) & &path.path_part != BigUint::from(0u64) {
+            <Fp as Hashable>::hash([sibling, digest])
+        } else {
+            <Fp as Hashable>::hash([digest, sibling])
+        };
+    }
+    path.root = to_hash(digest);
+    Ok(path)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
-struct HashableField<Fp: FieldExt>(Fp);
+pub(crate) struct HashableField<Fp: FieldExt>(Fp);
 
 impl<Fp: FieldExt> std::hash::Hash for HashableField<Fp> {
     fn hash<H>(&self, state: &mut H)
@@ -1196,6 +1514,67 @@ mod tests {
         println!("{:?}", final_data);
     }
 
+    #[test]
+    fn split_multi_field_trace() {
+        let traces: Vec<serde::SMTTrace> =
+            serde_json::from_str(include_str!("../tests/token_traces.json")).unwrap();
+        let mut trace = traces
+            .into_iter()
+            .find(|trace| match &trace.account_update {
+                [Some(old), Some(new)] => old.nonce != new.nonce,
+                _ => false,
+            })
+            .unwrap();
+
+        // make the balance change along with the nonce.
+        let new_account = trace.account_update[1].as_mut().unwrap();
+        new_account.balance += 1u64;
+        let storage_root = Fp::from_bytes_wide(&trace.common_state_root.unwrap().cast());
+        let account_hash = Account::<Fp>::try_from((&*new_account, storage_root))
+            .unwrap()
+            .account_hash();
+        trace.account_path[1] = rehash_path(&trace.account_path[1], account_hash).unwrap();
+
+        let split = split_trace::<Fp>(trace.clone()).unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].account_path[0], trace.account_path[0]);
+        assert_eq!(split[0].account_path[1], split[1].account_path[0]);
+        assert_eq!(split[1].account_path[1], trace.account_path[1]);
+
+        let ops = ops_from_traces::<Fp>(&[trace]).unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].account_root(), ops[1].account_root_before());
+        let [before, middle, after] = [
+            &ops[0].account_before,
+            &ops[0].account_after,
+            &ops[1].account_after,
+        ]
+        .map(|account| account.as_ref().unwrap());
+        assert_eq!(before.balance, middle.balance);
+        assert_ne!(before.nonce, middle.nonce);
+        assert_eq!(middle.nonce, after.nonce);
+        assert_ne!(middle.balance, after.balance);
+    }
+
+    #[test]
+    fn extend_malformed_path() {
+        let traces: Vec<serde::SMTTrace> =
+            serde_json::from_str(include_str!("../tests/token_traces.json")).unwrap();
+        let trace = &traces[0];
+
+        // an insertion into an empty trie, but ending deeper than the root
+        let empty = serde::SMTPath {
+            root: serde::HexBytes([0; 32]),
+            leaf: None,
+            path: Vec::new(),
+            path_part: BigUint::from(0u64),
+        };
+        let inserted = &trace.account_path[1];
+        assert!(!inserted.path.is_empty());
+        let ret = SingleOp::<Fp>::try_from((&empty, inserted, trace.account_key));
+        assert!(matches!(ret, Err(TraceError::DataErr(_))));
+    }
+
     // verify the calculation of account data's root
     #[test]
     fn trace_account_data() {
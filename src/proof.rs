@@ -3,7 +3,7 @@ use halo2_proofs::halo2curves::bn256::Fr;
 use itertools::{EitherOrBoth, Itertools};
 
 use crate::{
-    operation::Account,
+    operation::{to_hash, Account, TraceError},
     serde::{AccountData, SMTNode, SMTPath, SMTTrace, StateData},
     types::Claim,
     util::{balance_convert, fr, hash, hi_lo, split_word, u256_from_hex, Bit},
@@ -27,12 +27,14 @@ impl AccountHashTraces {
         paths: [SMTPath; 2],
         [old_update, new_update]: [Option<AccountData>; 2],
         [old_storage_root, new_storage_root]: [Fr; 2],
-    ) -> Self {
+    ) -> Result<Self, TraceError> {
         let [old_leaf, new_leaf] = paths.clone().map(|p| p.leaf);
-        let old_account =
-            old_leaf.map(|leaf| get_account_hash_traces(key, old_update, old_storage_root, leaf));
-        let new_account =
-            new_leaf.map(|leaf| get_account_hash_traces(key, new_update, new_storage_root, leaf));
+        let old_account = old_leaf
+            .map(|leaf| get_account_hash_traces(key, old_update, old_storage_root, leaf))
+            .transpose()?;
+        let new_account = new_leaf
+            .map(|leaf| get_account_hash_traces(key, new_update, new_storage_root, leaf))
+            .transpose()?;
 
         let parent_nodes = get_internal_hash_traces(
             key,
@@ -40,13 +42,13 @@ impl AccountHashTraces {
                 .map(|hash_traces| hash_traces.map(|x| x[3].out).unwrap_or_default()),
             &paths[0].path,
             &paths[1].path,
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             parent_nodes,
             old_account,
             new_account,
-        }
+        })
     }
 }
 
@@ -57,11 +59,19 @@ struct StorageHashTraces {
 }
 
 impl StorageHashTraces {
-    fn new(key: Fr, paths: [&SMTPath; 2], updates: [Option<StateData>; 2]) -> Self {
+    fn new(
+        key: Fr,
+        paths: [&SMTPath; 2],
+        updates: [Option<StateData>; 2],
+    ) -> Result<Self, TraceError> {
         let [old_leaf, new_leaf] = paths.clone().map(|p| p.leaf);
         let [old_update, new_update] = updates;
-        let old_storage = old_leaf.map(|leaf| get_storage_leaf_hash_traces(old_update, leaf));
-        let new_storage = new_leaf.map(|leaf| get_storage_leaf_hash_traces(new_update, leaf));
+        let old_storage = old_leaf
+            .map(|leaf| get_storage_leaf_hash_traces(old_update, leaf))
+            .transpose()?;
+        let new_storage = new_leaf
+            .map(|leaf| get_storage_leaf_hash_traces(new_update, leaf))
+            .transpose()?;
 
         let parent_nodes = get_internal_hash_traces(
             key,
@@ -69,13 +79,13 @@ impl StorageHashTraces {
                 .map(|hash_traces| hash_traces.map(|x| x[3].out).unwrap_or_default()),
             &paths[0].path,
             &paths[1].path,
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             parent_nodes,
             old_storage,
             new_storage,
-        }
+        })
     }
 
     fn roots(&self) -> [Fr; 2] {
@@ -111,10 +121,11 @@ impl HashTrace {
     }
 }
 
-impl From<SMTTrace> for Proof {
-    fn from(trace: SMTTrace) -> Self {
-        dbg!(&trace);
-        let claim = Claim::from(&trace);
+impl TryFrom<SMTTrace> for Proof {
+    type Error = TraceError;
+
+    fn try_from(trace: SMTTrace) -> Result<Self, Self::Error> {
+        let claim = Claim::try_from(&trace)?;
 
         let (storage_roots, storage) = match (
             trace.common_state_root,
@@ -126,10 +137,15 @@ impl From<SMTTrace> for Proof {
                 ([storage_root; 2].map(fr), None)
             }
             (None, Some(key), [Some(open), Some(close)], Some(storage_updates)) => {
-                let storage = StorageHashTraces::new(fr(key), [open, close], storage_updates);
+                let storage = StorageHashTraces::new(fr(key), [open, close], storage_updates)?;
                 (storage.roots(), Some(storage))
             }
-            _ => unreachable!(),
+            (None, None, [Some(_), Some(_)], _) => return Err(TraceError::MissingStorageKey),
+            _ => {
+                return Err(TraceError::DataErr(
+                    "unexpected combination of storage paths and updates".to_string(),
+                ))
+            }
         };
 
         let account = AccountHashTraces::new(
@@ -137,13 +153,13 @@ impl From<SMTTrace> for Proof {
             trace.account_path,
             trace.account_update,
             storage_roots,
-        );
+        )?;
 
-        Self {
+        Ok(Self {
             claim,
             account,
             storage,
-        }
+        })
     }
 }
 
@@ -152,7 +168,7 @@ fn get_internal_hash_traces(
     leaf_hashes: [Fr; 2],
     open_hash_traces: &[SMTNode],
     close_hash_traces: &[SMTNode],
-) -> Vec<(bool, Fr, Fr, Fr, bool, bool)> {
+) -> Result<Vec<(bool, Fr, Fr, Fr, bool, bool)>, TraceError> {
     let _path_length = std::cmp::max(open_hash_traces.len(), close_hash_traces.len());
 
     let mut address_hash_traces = vec![];
@@ -163,7 +179,9 @@ fn get_internal_hash_traces(
     {
         address_hash_traces.push(match e {
             EitherOrBoth::Both(open, close) => {
-                assert_eq!(open.sibling, close.sibling);
+                if open.sibling != close.sibling {
+                    return Err(TraceError::SiblingMismatch { depth: i });
+                }
                 (
                     key.bit(i),
                     fr(open.value),
@@ -192,7 +210,7 @@ fn get_internal_hash_traces(
         });
     }
     address_hash_traces.reverse();
-    address_hash_traces
+    Ok(address_hash_traces)
 }
 
 fn get_root(direction: bool, value: Fr, sibling: Fr, is_padding: bool) -> Fr {
@@ -205,7 +223,10 @@ fn get_root(direction: bool, value: Fr, sibling: Fr, is_padding: bool) -> Fr {
     }
 }
 
-fn get_storage_leaf_hash_traces(state_data: Option<StateData>, leaf: SMTNode) -> [HashTrace; 4] {
+fn get_storage_leaf_hash_traces(
+    state_data: Option<StateData>,
+    leaf: SMTNode,
+) -> Result<[HashTrace; 4], TraceError> {
     let mut hash_traces = [HashTrace::default(); 4];
     if let Some(state_data) = state_data {
         let (key_high, key_low) = split_word(u256_from_hex(state_data.key));
@@ -213,19 +234,19 @@ fn get_storage_leaf_hash_traces(state_data: Option<StateData>, leaf: SMTNode) ->
         hash_traces[0] = HashTrace::new(key_high, key_low);
         hash_traces[1] = HashTrace::new(value_high, value_low);
 
-        // Sanity check that the leaf matches the value hash, if present.
-        dbg!(state_data, leaf);
-
-        if hash_traces[1].out != fr(leaf.value) {
-            assert_eq!(u256_from_hex(state_data.value), U256::zero());
-        } else {
-            assert_eq!(hash_traces[1].out, fr(leaf.value));
+        // The leaf matches the value hash, unless the value is zero and the leaf belongs to
+        // another key.
+        if hash_traces[1].out != fr(leaf.value) && u256_from_hex(state_data.value) != U256::zero() {
+            return Err(TraceError::StorageHashMismatch {
+                expected: leaf.value,
+                actual: to_hash(hash_traces[1].out),
+            });
         }
     }
     hash_traces[2] = HashTrace::new(Fr::one(), fr(leaf.sibling));
     hash_traces[3] = HashTrace::new(hash_traces[2].out, fr(leaf.value));
 
-    hash_traces
+    Ok(hash_traces)
 }
 
 fn get_account_hash_traces(
@@ -233,7 +254,7 @@ fn get_account_hash_traces(
     account_data: Option<AccountData>,
     storage_root: Fr, // this is actually optional? what if there is no account?
     leaf: SMTNode,
-) -> [HashTrace; 6] {
+) -> Result<[HashTrace; 6], TraceError> {
     let mut hash_traces = [HashTrace::default(); 6];
     if let Some(account_data) = account_data {
         let (codehash_hi, codehash_lo) = hi_lo(&account_data.code_hash);
@@ -245,17 +266,20 @@ fn get_account_hash_traces(
         hash_traces[2] = HashTrace::new(nonce, balance);
         hash_traces[3] = HashTrace::new(hash_traces[2].out, hash_traces[1].out);
 
-        // Sanity check we calculated the account hash correctly.
-        let real_account: Account<Fr> = (&account_data, storage_root).try_into().unwrap();
-        assert_eq!(real_account.account_hash(), hash_traces[3].out);
-
-        // Sanity check that the leaf matches the value hash, if present.
-        dbg!(hash_traces, leaf);
-        assert_eq!(hash_traces[3].out, fr(leaf.value));
+        // The account hash computed here agrees with the one of Account, and matches the leaf.
+        let real_account: Account<Fr> = (&account_data, storage_root).try_into()?;
+        for account_hash in [real_account.account_hash(), hash_traces[3].out] {
+            if account_hash != fr(leaf.value) {
+                return Err(TraceError::AccountHashMismatch {
+                    expected: leaf.value,
+                    actual: to_hash(account_hash),
+                });
+            }
+        }
     }
     hash_traces[4] = HashTrace::new(Fr::one(), fr(leaf.sibling));
     hash_traces[5] = HashTrace::new(hash_traces[4].out, fr(leaf.value));
-    hash_traces
+    Ok(hash_traces)
 }
 
 #[cfg(test)]
@@ -273,14 +297,14 @@ mod test {
     fn check_empty_storage() {
         let empty_storage = include_str!("../tests/empty_storage.json");
         let trace: SMTTrace = serde_json::from_str(empty_storage).unwrap();
-        let _proof = Proof::from(trace);
+        let _proof = Proof::try_from(trace).unwrap();
     }
 
     #[test]
     fn empty_account() {
         let empty_storage = include_str!("../tests/empty_account.json");
         let trace: SMTTrace = serde_json::from_str(empty_storage).unwrap();
-        let _proof = Proof::from(trace);
+        let _proof = Proof::try_from(trace).unwrap();
     }
 
     #[test]
@@ -288,7 +312,7 @@ mod test {
         for s in [READ_TRACES] {
             let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
             for trace in traces {
-                let _proof = Proof::from(trace);
+                let _proof = Proof::try_from(trace).unwrap();
             }
         }
     }
@@ -298,7 +322,7 @@ mod test {
         for s in [DEPLOY_TRACES] {
             let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
             for trace in traces {
-                let _proof = Proof::from(trace);
+                let _proof = Proof::try_from(trace).unwrap();
             }
         }
     }
@@ -308,8 +332,27 @@ mod test {
         for s in [TOKEN_TRACES] {
             let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
             for trace in traces {
-                let _proof = Proof::from(trace);
+                let _proof = Proof::try_from(trace).unwrap();
             }
         }
     }
+
+    #[test]
+    fn mismatched_siblings() {
+        let mut trace: SMTTrace = serde_json::from_str(EMPTY_ACCOUNT_TRACE).unwrap();
+        let node = trace.account_path[1].path.first_mut().unwrap();
+        node.sibling.0[0] ^= 1;
+        assert!(Proof::try_from(trace).is_err());
+    }
+
+    #[test]
+    fn mismatched_account_hash() {
+        let traces: Vec<SMTTrace> = serde_json::from_str(TRACES).unwrap();
+        let mut trace = traces
+            .into_iter()
+            .find(|trace| trace.account_update[1].is_some() && trace.account_path[1].leaf.is_some())
+            .unwrap();
+        trace.account_path[1].leaf.as_mut().unwrap().value.0[0] ^= 1;
+        assert!(Proof::try_from(trace).is_err());
+    }
 }
@@ -0,0 +1,593 @@
+//! in-memory sparse binary merkle trie (zktrie) with poseidon hashes, which can
+//! generate `SMTTrace` witnesses for the operations applied on it
+
+use crate::{
+    hash::Hashable,
+    operation::{to_hash, Account, KeyValue, TraceError},
+    serde::{AccountData, Address, HexBytes, SMTNode, SMTPath, SMTTrace, StateData},
+};
+use num_bigint::BigUint;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+enum Node<Fp> {
+    Empty,
+    Leaf {
+        key: Fp,
+        value: Fp,
+        hash: Fp,
+    },
+    Branch {
+        children: Box<[Node<Fp>; 2]>,
+        hash: Fp,
+    },
+}
+
+impl<Fp: Hashable> Node<Fp> {
+    fn leaf(key: Fp, value: Fp) -> Self {
+        let hash = <Fp as Hashable>::hash([<Fp as Hashable>::hash([Fp::one(), key]), value]);
+        Self::Leaf { key, value, hash }
+    }
+
+    fn branch(left: Self, right: Self) -> Self {
+        let hash = <Fp as Hashable>::hash([left.hash(), right.hash()]);
+        Self::Branch {
+            children: Box::new([left, right]),
+            hash,
+        }
+    }
+
+    fn hash(&self) -> Fp {
+        match self {
+            Self::Empty => Fp::zero(),
+            Self::Leaf { hash, .. } | Self::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn insert(self, key: Fp, value: Fp, depth: usize) -> Self {
+        match self {
+            Self::Empty => Self::leaf(key, value),
+            Self::Leaf { key: leaf_key, .. } if leaf_key == key => Self::leaf(key, value),
+            Self::Leaf { key: leaf_key, .. } => {
+                Self::split(self, leaf_key, Self::leaf(key, value), key, depth)
+            }
+            Self::Branch { children, .. } => {
+                let [left, right] = *children;
+                if key_bit(&key, depth) {
+                    Self::branch(left, right.insert(key, value, depth + 1))
+                } else {
+                    Self::branch(left.insert(key, value, depth + 1), right)
+                }
+            }
+        }
+    }
+
+    // push both leaves down until their keys diverge
+    fn split(existing: Self, existing_key: Fp, new: Self, new_key: Fp, depth: usize) -> Self {
+        match (key_bit(&existing_key, depth), key_bit(&new_key, depth)) {
+            (true, true) => Self::branch(
+                Self::Empty,
+                Self::split(existing, existing_key, new, new_key, depth + 1),
+            ),
+            (false, false) => Self::branch(
+                Self::split(existing, existing_key, new, new_key, depth + 1),
+                Self::Empty,
+            ),
+            (true, false) => Self::branch(new, existing),
+            (false, true) => Self::branch(existing, new),
+        }
+    }
+
+    fn delete(self, key: Fp, depth: usize) -> Self {
+        match self {
+            Self::Leaf { key: leaf_key, .. } if leaf_key == key => Self::Empty,
+            Self::Empty | Self::Leaf { .. } => self,
+            Self::Branch { children, .. } => {
+                let [left, right] = *children;
+                let (left, right) = if key_bit(&key, depth) {
+                    (left, right.delete(key, depth + 1))
+                } else {
+                    (left.delete(key, depth + 1), right)
+                };
+                // a leaf left alone in a branch is lifted up
+                match (left, right) {
+                    (Self::Empty, Self::Empty) => Self::Empty,
+                    (leaf @ Self::Leaf { .. }, Self::Empty) => leaf,
+                    (Self::Empty, leaf @ Self::Leaf { .. }) => leaf,
+                    (left, right) => Self::branch(left, right),
+                }
+            }
+        }
+    }
+}
+
+// the bit of key which decides the direction at depth, from the least significant one
+fn key_bit<Fp: Hashable>(key: &Fp, depth: usize) -> bool {
+    let repr = key.to_repr();
+    let bytes = repr.as_ref();
+    assert!(depth < bytes.len() * 8, "keys can not share all bits");
+    bytes[depth / 8] >> (depth % 8) & 1 == 1
+}
+
+/// A sparse binary merkle trie, in which branch node is hashed as `H(left, right)`
+/// and leaf node is hashed as `H(H(1, key), value)`
+#[derive(Clone, Debug)]
+pub struct ZkTrie<Fp> {
+    root: Node<Fp>,
+}
+
+impl<Fp> Default for ZkTrie<Fp> {
+    fn default() -> Self {
+        Self { root: Node::Empty }
+    }
+}
+
+impl<Fp: Hashable> ZkTrie<Fp> {
+    /// the root of trie, zero for an empty trie
+    pub fn root(&self) -> Fp {
+        self.root.hash()
+    }
+
+    /// the value of leaf under key
+    pub fn get(&self, key: Fp) -> Option<Fp> {
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            match node {
+                Node::Empty => return None,
+                Node::Leaf {
+                    key: leaf_key,
+                    value,
+                    ..
+                } => return (*leaf_key == key).then_some(*value),
+                Node::Branch { children, .. } => {
+                    node = &children[key_bit(&key, depth) as usize];
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// insert a leaf, or update the value of leaf if key has existed
+    pub fn insert(&mut self, key: Fp, value: Fp) {
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = root.insert(key, value, 0);
+    }
+
+    /// update the value of an existed leaf, return false if the key is not found
+    pub fn update(&mut self, key: Fp, value: Fp) -> bool {
+        let found = self.get(key).is_some();
+        if found {
+            self.insert(key, value);
+        }
+        found
+    }
+
+    /// delete the leaf under key, the trie is not changed if the key is not found
+    pub fn delete(&mut self, key: Fp) {
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = root.delete(key, 0);
+    }
+
+    /// the path from root to the node where key resides, it ends in an empty node
+    /// or a leaf with another key if key is not found
+    pub fn prove(&self, key: Fp) -> SMTPath {
+        let mut path = Vec::new();
+        let mut node = &self.root;
+        let leaf = loop {
+            match node {
+                Node::Empty => break None,
+                Node::Leaf {
+                    key: leaf_key,
+                    value,
+                    ..
+                } => {
+                    break Some(SMTNode {
+                        value: to_hash(*value),
+                        sibling: to_hash(*leaf_key),
+                    })
+                }
+                Node::Branch { children, .. } => {
+                    let bit = key_bit(&key, path.len()) as usize;
+                    path.push(SMTNode {
+                        value: to_hash(children[bit].hash()),
+                        sibling: to_hash(children[1 - bit].hash()),
+                    });
+                    node = &children[bit];
+                }
+            }
+        };
+
+        let mask = (BigUint::from(1u64) << path.len()) - 1u64;
+        SMTPath {
+            root: to_hash(self.root()),
+            leaf,
+            path_part: BigUint::from_bytes_le(key.to_repr().as_ref()) & mask,
+            path,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Storage<Fp> {
+    trie: ZkTrie<Fp>,
+    values: HashMap<HexBytes<32>, HexBytes<32>>,
+}
+
+/// The world state, i.e. an account trie which each account has its own storage trie.
+/// every operation on it returns the `SMTTrace` for the operation
+#[derive(Clone, Debug, Default)]
+pub struct StateTrie<Fp> {
+    accounts: ZkTrie<Fp>,
+    account_data: HashMap<Address, AccountData>,
+    storages: HashMap<Address, Storage<Fp>>,
+}
+
+impl<Fp: Hashable> StateTrie<Fp> {
+    /// the root of account trie
+    pub fn root(&self) -> Fp {
+        self.accounts.root()
+    }
+
+    /// the data of account
+    pub fn get_account(&self, address: &Address) -> Option<&AccountData> {
+        self.account_data.get(address)
+    }
+
+    /// the stored value under key for account, None for a zero value
+    pub fn get_storage(&self, address: &Address, key: &HexBytes<32>) -> Option<&HexBytes<32>> {
+        self.storages.get(address)?.values.get(key)
+    }
+
+    /// the root of storage trie for account
+    pub fn storage_root(&self, address: &Address) -> Fp {
+        self.storages
+            .get(address)
+            .map(|storage| storage.trie.root())
+            .unwrap_or_else(Fp::zero)
+    }
+
+    /// create the account or update its data
+    pub fn update_account(
+        &mut self,
+        address: Address,
+        data: AccountData,
+    ) -> Result<SMTTrace, TraceError> {
+        let key = KeyValue::<Fp>::from(&address).hash();
+        let storage_root = self.storage_root(&address);
+        let account_hash = Account::<Fp>::try_from((&data, storage_root))?.account_hash();
+
+        let path_before = self.accounts.prove(key);
+        self.accounts.insert(key, account_hash);
+        let data_before = self.account_data.insert(address, data.clone());
+
+        Ok(SMTTrace {
+            address,
+            account_key: to_hash(key),
+            account_path: [path_before, self.accounts.prove(key)],
+            account_update: [data_before, Some(data)],
+            state_path: [None, None],
+            common_state_root: Some(to_hash(storage_root)),
+            state_key: None,
+            state_update: Some([None, None]),
+        })
+    }
+
+    /// delete the account along with its storage
+    pub fn delete_account(&mut self, address: Address) -> Result<SMTTrace, TraceError> {
+        let key = KeyValue::<Fp>::from(&address).hash();
+        let storage_root = self.storage_root(&address);
+        let data_before = self
+            .account_data
+            .remove(&address)
+            .ok_or(TraceError::MissingAccount)?;
+
+        let path_before = self.accounts.prove(key);
+        self.accounts.delete(key);
+        self.storages.remove(&address);
+
+        Ok(SMTTrace {
+            address,
+            account_key: to_hash(key),
+            account_path: [path_before, self.accounts.prove(key)],
+            account_update: [Some(data_before), None],
+            state_path: [None, None],
+            common_state_root: Some(to_hash(storage_root)),
+            state_key: None,
+            state_update: None,
+        })
+    }
+
+    /// the trace for reading account, which is a proof of non-existence if the account is not found
+    pub fn read_account(&self, address: Address) -> SMTTrace {
+        let key = KeyValue::<Fp>::from(&address).hash();
+        let path = self.accounts.prove(key);
+        let data = self.account_data.get(&address).cloned();
+
+        SMTTrace {
+            address,
+            account_key: to_hash(key),
+            account_path: [path.clone(), path],
+            account_update: [data.clone(), data],
+            state_path: [None, None],
+            common_state_root: Some(to_hash(self.storage_root(&address))),
+            state_key: None,
+            state_update: Some([None, None]),
+        }
+    }
+
+    /// write value under key into the storage of account, writing zero deletes the key
+    pub fn update_storage(
+        &mut self,
+        address: Address,
+        key: HexBytes<32>,
+        value: HexBytes<32>,
+    ) -> Result<SMTTrace, TraceError> {
+        let data = self
+            .account_data
+            .get(&address)
+            .cloned()
+            .ok_or(TraceError::MissingAccount)?;
+        let account_key = KeyValue::<Fp>::from(&address).hash();
+        let storage_key = KeyValue::<Fp>::from(&key).hash();
+
+        let account_path_before = self.accounts.prove(account_key);
+        let storage = self.storages.entry(address).or_default();
+        let state_path_before = storage.trie.prove(storage_key);
+        let value_before = if value == HexBytes::default() {
+            storage.trie.delete(storage_key);
+            storage.values.remove(&key)
+        } else {
+            storage
+                .trie
+                .insert(storage_key, KeyValue::<Fp>::from(&value).hash());
+            storage.values.insert(key, value)
+        };
+        let state_path_after = storage.trie.prove(storage_key);
+
+        let account_hash = Account::<Fp>::try_from((&data, storage.trie.root()))?.account_hash();
+        self.accounts.insert(account_key, account_hash);
+
+        // writing zero to a key not found is the same as reading it
+        let state_update = match (value_before, value != HexBytes::default()) {
+            (None, false) => [Some(StateData { key, value }); 2],
+            (value_before, has_value) => [
+                value_before.map(|value| StateData { key, value }),
+                has_value.then_some(StateData { key, value }),
+            ],
+        };
+        Ok(SMTTrace {
+            address,
+            account_key: to_hash(account_key),
+            account_path: [account_path_before, self.accounts.prove(account_key)],
+            account_update: [Some(data.clone()), Some(data)],
+            state_path: [Some(state_path_before), Some(state_path_after)],
+            common_state_root: None,
+            state_key: Some(to_hash(storage_key)),
+            state_update: Some(state_update),
+        })
+    }
+
+    /// the trace for reading the storage of account, a key not found is read as zero
+    pub fn read_storage(
+        &self,
+        address: Address,
+        key: HexBytes<32>,
+    ) -> Result<SMTTrace, TraceError> {
+        let data = self
+            .account_data
+            .get(&address)
+            .cloned()
+            .ok_or(TraceError::MissingAccount)?;
+        let account_key = KeyValue::<Fp>::from(&address).hash();
+        let storage_key = KeyValue::<Fp>::from(&key).hash();
+
+        let account_path = self.accounts.prove(account_key);
+        let state_path = self
+            .storages
+            .get(&address)
+            .map(|storage| storage.trie.prove(storage_key))
+            .unwrap_or_default();
+        let value = self
+            .get_storage(&address, &key)
+            .copied()
+            .unwrap_or_default();
+
+        Ok(SMTTrace {
+            address,
+            account_key: to_hash(account_key),
+            account_path: [account_path.clone(), account_path],
+            account_update: [Some(data.clone()), Some(data)],
+            state_path: [Some(state_path.clone()), Some(state_path)],
+            common_state_root: None,
+            state_key: Some(to_hash(storage_key)),
+            state_update: Some([Some(StateData { key, value }); 2]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{operation::ops_from_traces, types::Proof, EthTrie};
+    use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr};
+
+    fn fr(x: u64) -> Fr {
+        Fr::from(x)
+    }
+
+    fn account(nonce: u64, balance: u64) -> AccountData {
+        AccountData {
+            nonce,
+            balance: BigUint::from(balance),
+            code_hash: BigUint::default(),
+            poseidon_code_hash: BigUint::default(),
+            code_size: 0,
+        }
+    }
+
+    fn word(x: u8) -> HexBytes<32> {
+        let mut bytes = [0; 32];
+        bytes[31] = x;
+        HexBytes(bytes)
+    }
+
+    #[test]
+    fn trie_insert_delete() {
+        let mut trie = ZkTrie::<Fr>::default();
+        let keys: Vec<Fr> = (1..20).map(|i| Hashable::hash([fr(i), fr(0)])).collect();
+
+        for (i, key) in keys.iter().enumerate() {
+            trie.insert(*key, fr(i as u64 + 100));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(trie.get(*key), Some(fr(i as u64 + 100)));
+        }
+        assert_eq!(trie.get(fr(12345)), None);
+        assert!(!trie.update(fr(12345), fr(1)));
+        assert!(trie.update(keys[3], fr(1)));
+        assert_eq!(trie.get(keys[3]), Some(fr(1)));
+
+        // the root does not depend on the order of insertion
+        let mut reversed = ZkTrie::<Fr>::default();
+        for (i, key) in keys.iter().enumerate().rev() {
+            reversed.insert(*key, fr(i as u64 + 100));
+        }
+        reversed.insert(keys[3], fr(1));
+        assert_eq!(trie.root(), reversed.root());
+
+        // and deleting restores the shape of the trie
+        let mut partial = ZkTrie::<Fr>::default();
+        for key in &keys[..10] {
+            partial.insert(*key, fr(7));
+        }
+        for key in &keys[10..] {
+            trie.delete(*key);
+        }
+        for key in &keys[..10] {
+            trie.update(*key, fr(7));
+        }
+        assert_eq!(trie.root(), partial.root());
+
+        for key in &keys {
+            trie.delete(*key);
+        }
+        assert_eq!(trie.root(), Fr::zero());
+    }
+
+    fn sample_traces() -> (Vec<SMTTrace>, Fr) {
+        let mut state = StateTrie::<Fr>::default();
+        let addresses: Vec<Address> = (1..6u8).map(|i| HexBytes([i; 20])).collect();
+        let mut traces = Vec::new();
+
+        for (i, address) in addresses.iter().enumerate() {
+            traces.push(state.read_account(*address));
+            traces.push(
+                state
+                    .update_account(*address, account(i as u64 + 1, 0))
+                    .unwrap(),
+            );
+        }
+        traces.push(
+            state
+                .update_account(addresses[0], account(1, 1000))
+                .unwrap(),
+        );
+        traces.push(state.update_account(addresses[1], account(3, 0)).unwrap());
+        for i in 1..4 {
+            traces.push(
+                state
+                    .update_storage(addresses[2], word(i), word(i + 10))
+                    .unwrap(),
+            );
+        }
+        traces.push(
+            state
+                .update_storage(addresses[2], word(1), word(42))
+                .unwrap(),
+        );
+        traces.push(state.read_storage(addresses[2], word(2)).unwrap());
+        traces.push(state.read_storage(addresses[2], word(9)).unwrap());
+
+        (traces, state.root())
+    }
+
+    #[test]
+    fn state_trie_traces() {
+        let (traces, root) = sample_traces();
+
+        let ops = ops_from_traces::<Fr>(&traces).unwrap();
+        for (op, trace) in ops.iter().zip(&traces) {
+            assert_eq!(
+                to_hash(op.account_root_before()),
+                trace.account_path[0].root
+            );
+            assert_eq!(to_hash(op.account_root()), trace.account_path[1].root);
+            Proof::try_from(trace.clone()).unwrap();
+        }
+        for pair in traces.windows(2) {
+            assert_eq!(pair[0].account_path[1].root, pair[1].account_path[0].root);
+        }
+        assert_eq!(traces.last().unwrap().account_path[1].root, to_hash(root));
+    }
+
+    #[test]
+    fn state_trie_deletions() {
+        let mut state = StateTrie::<Fr>::default();
+        let addresses: Vec<Address> = (1..4u8).map(|i| HexBytes([i; 20])).collect();
+        for address in &addresses {
+            state.update_account(*address, account(1, 0)).unwrap();
+        }
+        let root = state.root();
+        let storage_root = state.storage_root(&addresses[1]);
+
+        let traces = vec![
+            state
+                .update_storage(addresses[1], word(1), word(2))
+                .unwrap(),
+            state
+                .update_storage(addresses[1], word(3), word(4))
+                .unwrap(),
+            state
+                .update_storage(addresses[1], word(1), HexBytes::default())
+                .unwrap(),
+            state
+                .update_storage(addresses[1], word(3), HexBytes::default())
+                .unwrap(),
+        ];
+        assert_eq!(state.storage_root(&addresses[1]), storage_root);
+        assert_eq!(state.root(), root);
+        assert!(state.get_storage(&addresses[1], &word(1)).is_none());
+
+        let mut traces = traces;
+        traces.push(state.delete_account(addresses[0]).unwrap());
+        assert!(state.get_account(&addresses[0]).is_none());
+        assert!(matches!(
+            state.delete_account(addresses[0]),
+            Err(TraceError::MissingAccount)
+        ));
+
+        for trace in &traces {
+            Proof::try_from(trace.clone()).unwrap();
+        }
+        ops_from_traces::<Fr>(&traces).unwrap();
+    }
+
+    #[test]
+    fn state_trie_circuit() {
+        // only the writes, for reading an absent key is not supported by the circuit yet
+        let (traces, _) = sample_traces();
+        let traces: Vec<SMTTrace> = traces
+            .into_iter()
+            .filter(|trace| trace.account_path[0].root != trace.account_path[1].root)
+            .collect();
+        let ops = ops_from_traces::<Fr>(&traces).unwrap();
+
+        let mut data: EthTrie<Fr> = Default::default();
+        data.add_ops(ops);
+        let (circuit, _) = data.circuits(400).unwrap();
+
+        let prover = MockProver::<Fr>::run(10, &circuit, circuit.instance()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+}
@@ -5,9 +5,9 @@ use num_bigint::BigUint;
 use num_traits::identities::Zero;
 
 use crate::{
-    operation::{Account, SMTPathParse},
+    operation::{split_trace, Account, SMTPathParse, TraceError},
     serde::{AccountData, HexBytes, SMTNode, SMTPath, SMTTrace},
-    Hashable,
+    Hashable, MPTProofType,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -31,8 +31,8 @@ enum Read {
     Nonce(u64),
     Balance(U256),
     CodeHash(U256),
-    // CodeSize(u64),
-    // PoseidonCodeHash(Fr),
+    CodeSize(u64),
+    PoseidonCodeHash(Fr),
     Storage { key: U256, value: U256 },
 }
 
@@ -57,17 +57,19 @@ enum Write {
         old_value: Option<U256>,
         new_value: Option<U256>,
     },
+    // The account is removed from the account trie, e.g. by SELFDESTRUCT.
+    Delete,
 }
 
 #[derive(Clone, Copy, Debug)]
-struct LeafNode {
-    key: Fr,
-    value_hash: Fr,
+pub struct LeafNode {
+    pub key: Fr,
+    pub value_hash: Fr,
 }
 
 impl LeafNode {
-    fn hash(&self) -> Fr {
-        hash(hash(Fr::one(), self.key), self.value_hash)
+    pub fn hash(&self) -> Fr {
+        leaf_hash(self.key, self.value_hash)
     }
 }
 
@@ -77,13 +79,17 @@ pub struct Proof {
     // direction, open value, close value, sibling, is_padding_open, is_padding_close
     pub address_hash_traces: Vec<(bool, Fr, Fr, Fr, bool, bool)>,
 
-    // TODO: make this optional
-    leafs: [LeafNode; 2],
+    // None if the path ends in an empty node.
+    pub leafs: [Option<LeafNode>; 2],
 
-    old_account_hash_traces: [[Fr; 3]; 6],
-    new_account_hash_traces: [[Fr; 3]; 6],
+    pub old_account_hash_traces: [[Fr; 3]; 7],
+    pub new_account_hash_traces: [[Fr; 3]; 7],
 
-    storage_hash_traces: Option<Vec<(bool, Fr, Fr, Fr, bool, bool)>>,
+    // The key hash of the storage slot, which is the path key of the storage trie.
+    pub storage_key: Option<Fr>,
+    pub storage_hash_traces: Option<Vec<(bool, Fr, Fr, Fr, bool, bool)>>,
+    // None if the storage path ends in an empty node.
+    pub storage_leafs: [Option<LeafNode>; 2],
     // TODO: make this a struct plz.
     storage_key_value_hash_traces: Option<[[[Fr; 3]; 3]; 2]>,
 }
@@ -98,22 +104,96 @@ enum NodeKind {
     StorageKeyTail(U256),
 }
 
-impl From<&SMTTrace> for Claim {
-    fn from(trace: &SMTTrace) -> Self {
+impl TryFrom<&SMTTrace> for Claim {
+    type Error = TraceError;
+
+    fn try_from(trace: &SMTTrace) -> Result<Self, Self::Error> {
         // TODO: this is doing a lot of extra work!!!!
-        let [old_root, new_root] = trace.account_path.clone().map(path_root);
+        let [old_path, new_path] = &trace.account_path;
+        let (old_root, new_root) = (path_root(old_path)?, path_root(new_path)?);
         let address = trace.address.0.into();
-        Self {
+        Ok(Self {
             new_root,
             old_root,
             address,
-            kind: ClaimKind::from(trace),
+            kind: ClaimKind::try_from(trace)?,
+        })
+    }
+}
+
+impl Claim {
+    pub fn proof_type(&self) -> MPTProofType {
+        match self.kind {
+            ClaimKind::Read(read) => match read {
+                Read::Nonce(_) => MPTProofType::NonceChanged,
+                Read::Balance(_) => MPTProofType::BalanceChanged,
+                Read::CodeHash(_) => MPTProofType::CodeHashExists,
+                Read::CodeSize(_) => MPTProofType::CodeSizeExists,
+                Read::PoseidonCodeHash(_) => MPTProofType::PoseidonCodeHashExists,
+                Read::Storage { .. } => MPTProofType::StorageChanged,
+            },
+            ClaimKind::Write(write) => match write {
+                Write::Nonce { .. } => MPTProofType::NonceChanged,
+                Write::Balance { .. } => MPTProofType::BalanceChanged,
+                Write::CodeHash { .. } => MPTProofType::CodeHashExists,
+                Write::Storage { .. } => MPTProofType::StorageChanged,
+                Write::Delete => MPTProofType::AccountDestructed,
+            },
+            ClaimKind::IsEmpty(None) => MPTProofType::AccountDoesNotExist,
+            ClaimKind::IsEmpty(Some(_)) => MPTProofType::StorageDoesNotExist,
+        }
+    }
+
+    /// The value read or written by the claim, as (old, new). Used for the value columns of
+    /// the account leaf rows.
+    pub fn values(&self) -> (Fr, Fr) {
+        match self.kind {
+            ClaimKind::Read(Read::Nonce(nonce)) => (Fr::from(nonce), Fr::from(nonce)),
+            ClaimKind::Read(Read::CodeSize(code_size)) => {
+                (Fr::from(code_size), Fr::from(code_size))
+            }
+            ClaimKind::Read(Read::PoseidonCodeHash(hash)) => (hash, hash),
+            ClaimKind::Read(Read::Balance(balance)) => (u256_to_fr(balance), u256_to_fr(balance)),
+            ClaimKind::Read(Read::CodeHash(code_hash)) => {
+                (code_hash_value(code_hash), code_hash_value(code_hash))
+            }
+            ClaimKind::Write(Write::Nonce { old, new }) => (
+                Fr::from(old.unwrap_or_default()),
+                Fr::from(new.unwrap_or_default()),
+            ),
+            ClaimKind::Write(Write::Balance { old, new }) => (
+                old.map(u256_to_fr).unwrap_or_default(),
+                new.map(u256_to_fr).unwrap_or_default(),
+            ),
+            ClaimKind::Write(Write::CodeHash { old, new }) => (
+                old.map(code_hash_value).unwrap_or_default(),
+                new.map(code_hash_value).unwrap_or_default(),
+            ),
+            _ => (Fr::zero(), Fr::zero()),
         }
     }
 }
 
-impl From<&SMTTrace> for ClaimKind {
-    fn from(trace: &SMTTrace) -> Self {
+#[cfg(test)]
+impl Claim {
+    // The read of a zero poseidon code hash or code size, for tests forging proofs.
+    pub(crate) fn zero_read(self, proof_type: MPTProofType) -> Self {
+        let read = match proof_type {
+            MPTProofType::PoseidonCodeHashExists => Read::PoseidonCodeHash(Fr::zero()),
+            MPTProofType::CodeSizeExists => Read::CodeSize(0),
+            _ => unimplemented!("zero read of {:?}", proof_type),
+        };
+        Self {
+            kind: ClaimKind::Read(read),
+            ..self
+        }
+    }
+}
+
+impl TryFrom<&SMTTrace> for ClaimKind {
+    type Error = TraceError;
+
+    fn try_from(trace: &SMTTrace) -> Result<Self, Self::Error> {
         let [account_old, account_new] = &trace.account_update;
         let state_update = &trace.state_update;
 
@@ -121,8 +201,8 @@ impl From<&SMTTrace> for ClaimKind {
             match update {
                 [None, None] => (),
                 [Some(old), Some(new)] => {
-                    assert_eq!(account_old, account_new, "{:?}", state_update);
-                    return if old == new {
+                    check_account_unchanged(account_old, account_new)?;
+                    return Ok(if old == new {
                         ClaimKind::Read(Read::Storage {
                             key: u256_from_hex(old.key),
                             value: u256_from_hex(old.value),
@@ -133,29 +213,36 @@ impl From<&SMTTrace> for ClaimKind {
                             old_value: Some(u256_from_hex(old.value)),
                             new_value: Some(u256_from_hex(new.value)),
                         })
-                    };
+                    });
                 }
                 [None, Some(new)] => {
-                    assert_eq!(account_old, account_new, "{:?}", state_update);
-                    return ClaimKind::Write(Write::Storage {
+                    check_account_unchanged(account_old, account_new)?;
+                    return Ok(ClaimKind::Write(Write::Storage {
                         key: u256_from_hex(new.key),
                         old_value: None,
                         new_value: Some(u256_from_hex(new.value)),
-                    });
+                    }));
                 }
-                [Some(_old), None] => {
-                    unimplemented!("SELFDESTRUCT")
+                [Some(old), None] => {
+                    check_account_unchanged(account_old, account_new)?;
+                    return Ok(ClaimKind::Write(Write::Storage {
+                        key: u256_from_hex(old.key),
+                        old_value: Some(u256_from_hex(old.value)),
+                        new_value: None,
+                    }));
                 }
             }
         }
 
-        match &trace.account_update {
+        Ok(match &trace.account_update {
             [None, None] => ClaimKind::IsEmpty(None),
             [None, Some(new)] => {
                 let write = match (
                     !new.nonce.is_zero(),
                     !new.balance.is_zero(),
-                    !new.code_hash.is_zero(),
+                    !new.code_hash.is_zero()
+                        || !new.poseidon_code_hash.is_zero()
+                        || !new.code_size.is_zero(),
                 ) {
                     (true, false, false) => Write::Nonce {
                         old: None,
@@ -170,20 +257,23 @@ impl From<&SMTTrace> for ClaimKind {
                         new: Some(u256(&new.code_hash)),
                     },
                     (false, false, false) => {
-                        dbg!(trace);
-                        // this is a non existance proof? i think??? probably not since it's covered above.
-                        unimplemented!("non-existence proof?")
+                        return Err(TraceError::DataErr(
+                            "account created without any field set".to_string(),
+                        ))
                     }
-                    _ => unreachable!("at most one account field change expected"),
+                    _ => return Err(TraceError::MultiFieldAccountChange),
                 };
                 ClaimKind::Write(write)
             }
-            [Some(_old), None] => unimplemented!("SELFDESTRUCT"),
+            [Some(_old), None] => ClaimKind::Write(Write::Delete),
             [Some(old), Some(new)] => {
+                // code_hash, poseidon_code_hash and code_size are only ever updated together.
                 let write = match (
                     old.nonce != new.nonce,
                     old.balance != new.balance,
-                    old.code_hash != new.code_hash,
+                    old.code_hash != new.code_hash
+                        || old.poseidon_code_hash != new.poseidon_code_hash
+                        || old.code_size != new.code_size,
                 ) {
                     (true, false, false) => Write::Nonce {
                         old: Some(old.nonce.into()),
@@ -199,30 +289,41 @@ impl From<&SMTTrace> for ClaimKind {
                     },
                     (false, false, false) => {
                         // Note that there's no way to tell what kind of account read was done from the trace.
-                        return ClaimKind::Read(Read::Nonce(old.nonce.into()));
-                    }
-                    _ => {
-                        dbg!(old, new);
-                        // apparently it's possible for more than one field to change.....
-                        unreachable!("at most one account field change expected")
+                        return Ok(ClaimKind::Read(Read::Nonce(old.nonce.into())));
                     }
+                    // apparently it's possible for more than one field to change.....
+                    _ => return Err(TraceError::MultiFieldAccountChange),
                 };
                 ClaimKind::Write(write)
             }
-        }
+        })
+    }
+}
+
+fn check_account_unchanged(
+    old: &Option<AccountData>,
+    new: &Option<AccountData>,
+) -> Result<(), TraceError> {
+    if old != new {
+        return Err(TraceError::DataErr(
+            "account changed in a storage update".to_string(),
+        ));
     }
+    Ok(())
 }
 
-impl From<SMTTrace> for Proof {
-    fn from(trace: SMTTrace) -> Self {
-        dbg!(&trace);
+impl TryFrom<SMTTrace> for Proof {
+    type Error = TraceError;
 
-        let claim = Claim::from(&trace);
+    fn try_from(trace: SMTTrace) -> Result<Self, Self::Error> {
+        let claim = Claim::try_from(&trace)?;
 
         // do storage stuff first, if needed.
         let (
             [old_storage_root, new_storage_root],
+            storage_key,
             storage_hash_traces,
+            storage_leafs,
             storage_key_value_hash_traces,
         ) = match (
             trace.common_state_root,
@@ -231,23 +332,32 @@ impl From<SMTTrace> for Proof {
             trace.state_update,
         ) {
             (Some(storage_root), None, [None, None], Some([None, None])) => {
-                ([storage_root; 2].map(fr), None, None)
+                ([fr(storage_root)?; 2], None, None, [None; 2], None)
             }
+            // Deleting an account carries no storage update.
+            (storage_root, None, [None, None], None) => (
+                [fr(storage_root.unwrap_or_default())?; 2],
+                None,
+                None,
+                [None; 2],
+                None,
+            ),
+            (None, None, [Some(_), Some(_)], _) => return Err(TraceError::MissingStorageKey),
             (None, Some(key), [Some(open), Some(close)], Some(_storage_updates)) => {
-                let leaf_hashes = [open, close].map(|path| {
-                    path.leaf
-                        .as_ref()
-                        .map(|leaf| hash(hash(Fr::one(), fr(leaf.sibling)), fr(leaf.value)))
-                        .unwrap_or_default()
-                });
+                let [old_leaf, new_leaf] = [open, close].map(|path| get_leaf(path.clone()));
+                let storage_leafs = [old_leaf?, new_leaf?];
+                let leaf_hashes =
+                    storage_leafs.map(|leaf| leaf.map_or(Fr::zero(), |leaf| leaf.hash()));
                 (
-                    [open.clone(), close.clone()].map(path_root),
+                    [path_root(open)?, path_root(close)?],
+                    Some(fr(key)?),
                     Some(get_internal_hash_traces(
-                        fr(key),
+                        fr(key)?,
                         leaf_hashes,
                         &(open.path),
                         &(close.path),
-                    )),
+                    )?),
+                    storage_leafs,
                     None,
                     // Some([
                     //     storage_key_value_hash_traces(
@@ -262,91 +372,198 @@ impl From<SMTTrace> for Proof {
                 )
             }
             _ => {
-                unreachable!();
+                return Err(TraceError::DataErr(
+                    "unexpected combination of storage paths and updates".to_string(),
+                ))
             }
         };
 
         let account_key = account_key(claim.address);
-        let leafs = trace.account_path.clone().map(get_leaf).map(|x| x.unwrap());
+        let [old_leaf, new_leaf] = trace.account_path.clone().map(get_leaf);
+        let leafs = [old_leaf?, new_leaf?];
         let [open_hash_traces, close_hash_traces] =
             trace.account_path.clone().map(|path| path.path);
-        let leaf_hashes = trace.account_path.clone().map(leaf_hash);
+        let [old_leaf_hash, new_leaf_hash] = trace.account_path.clone().map(path_leaf_hash);
+        let leaf_hashes = [old_leaf_hash?, new_leaf_hash?];
         let address_hash_traces = get_internal_hash_traces(
             account_key,
             leaf_hashes,
             &open_hash_traces,
             &close_hash_traces,
-        );
+        )?;
 
         let [old_account, new_account] = trace.account_update;
+        for (account, leaf) in [&old_account, &new_account].into_iter().zip(&leafs) {
+            if account.is_some() && leaf.map(|leaf| leaf.key) != Some(account_key) {
+                return Err(TraceError::DataErr(
+                    "account path does not end in the leaf of the account".to_string(),
+                ));
+            }
+        }
+        // The rows of the mpt circuit are assigned by the shape of the proof, so the shapes
+        // without rows are rejected here instead of at assignment.
+        if old_account.is_some()
+            && new_account.is_none()
+            && claim.proof_type() != MPTProofType::AccountDestructed
+        {
+            return Err(TraceError::DataErr(
+                "only account deletion removes an account leaf".to_string(),
+            ));
+        }
+        let padded_both = |traces: &[(bool, Fr, Fr, Fr, bool, bool)]| {
+            traces
+                .iter()
+                .any(|(_, _, _, _, is_padding_open, is_padding_close)| {
+                    *is_padding_open && *is_padding_close
+                })
+        };
+        if padded_both(&address_hash_traces)
+            || storage_hash_traces.as_deref().map_or(false, padded_both)
+        {
+            return Err(TraceError::DataErr(
+                "path is padded on both sides".to_string(),
+            ));
+        }
+
         let old_account_hash_traces = match old_account {
-            None => empty_account_hash_traces(leafs[0]),
-            Some(account) => account_hash_traces(claim.address, account, old_storage_root),
+            None => leafs[0].map(empty_account_hash_traces).unwrap_or_default(),
+            Some(account) => account_hash_traces(claim.address, account, old_storage_root)?,
         };
         let new_account_hash_traces = match new_account {
-            None => empty_account_hash_traces(leafs[1]),
-            Some(account) => account_hash_traces(claim.address, account, new_storage_root),
+            None => leafs[1].map(empty_account_hash_traces).unwrap_or_default(),
+            Some(account) => account_hash_traces(claim.address, account, new_storage_root)?,
         };
 
-        Self {
+        Ok(Self {
             claim,
             address_hash_traces,
             old_account_hash_traces,
             new_account_hash_traces,
             leafs,
+            storage_key,
             storage_hash_traces,
+            storage_leafs,
             storage_key_value_hash_traces,
+        })
+    }
+}
+
+// There is no way to tell which field of an account is read from the trace alone, so the
+// proof type has to be given for account reads.
+impl TryFrom<(MPTProofType, SMTTrace)> for Proof {
+    type Error = TraceError;
+
+    fn try_from((proof_type, trace): (MPTProofType, SMTTrace)) -> Result<Self, Self::Error> {
+        let account = trace.account_update[0].clone();
+        let mut proof = Proof::try_from(trace)?;
+        if let (ClaimKind::Read(Read::Nonce(_)), Some(account)) = (proof.claim.kind, account) {
+            let read = match proof_type {
+                MPTProofType::NonceChanged => Read::Nonce(account.nonce),
+                MPTProofType::BalanceChanged => Read::Balance(u256(&account.balance)),
+                MPTProofType::CodeHashExists => Read::CodeHash(u256(&account.code_hash)),
+                MPTProofType::PoseidonCodeHashExists => {
+                    Read::PoseidonCodeHash(balance_convert(account.poseidon_code_hash))
+                }
+                MPTProofType::CodeSizeExists => Read::CodeSize(account.code_size),
+                _ => return Ok(proof),
+            };
+            proof.claim.kind = ClaimKind::Read(read);
+        }
+        Ok(proof)
+    }
+}
+
+/// Converts a sequence of traces into proofs, attaching the index of the offending trace to
+/// any error. Proof types are given for the leading traces and inferred for the rest. Traces
+/// which change more than one account field become one proof per field changed.
+pub fn proofs_from_traces(
+    traces: &[SMTTrace],
+    proof_types: &[MPTProofType],
+) -> Result<Vec<Proof>, TraceError> {
+    let mut proofs = vec![];
+    for (i, trace) in traces.iter().cloned().enumerate() {
+        let split = split_trace::<Fr>(trace).map_err(|e| e.at(i))?;
+        for trace in split {
+            proofs.push(
+                match proof_types.get(i) {
+                    Some(proof_type) => Proof::try_from((*proof_type, trace)),
+                    None => Proof::try_from(trace),
+                }
+                .map_err(|e| e.at(i))?,
+            );
         }
     }
+    Ok(proofs)
 }
 
 // This should be an optional
-fn get_leaf(path: SMTPath) -> Option<LeafNode> {
-    path.leaf.map(|leaf| LeafNode {
-        key: fr(leaf.sibling),
-        value_hash: fr(leaf.value),
-    })
+fn get_leaf(path: SMTPath) -> Result<Option<LeafNode>, TraceError> {
+    path.leaf
+        .map(|leaf| {
+            Ok(LeafNode {
+                key: fr(leaf.sibling)?,
+                value_hash: fr(leaf.value)?,
+            })
+        })
+        .transpose()
 }
 
-fn leaf_hash(path: SMTPath) -> Fr {
+fn path_leaf_hash(path: SMTPath) -> Result<Fr, TraceError> {
     if let Some(leaf) = path.leaf {
-        hash(hash(Fr::one(), fr(leaf.sibling)), fr(leaf.value))
+        Ok(leaf_hash(fr(leaf.sibling)?, fr(leaf.value)?))
     } else {
-        assert_eq!(path, SMTPath::default());
-        Fr::zero()
+        // Without a leaf the path ends in an empty node, which is the root for an empty trie.
+        let end = path.path.last().map_or(path.root, |node| node.value);
+        if fr(end)? != Fr::zero() {
+            return Err(TraceError::DataErr(
+                "path without a leaf does not end in an empty node".to_string(),
+            ));
+        }
+        Ok(Fr::zero())
     }
 }
 
-fn account_hash_traces(address: Address, account: AccountData, storage_root: Fr) -> [[Fr; 3]; 6] {
-    let real_account: Account<Fr> = (&account, storage_root).try_into().unwrap();
+fn account_hash_traces(
+    address: Address,
+    account: AccountData,
+    storage_root: Fr,
+) -> Result<[[Fr; 3]; 7], TraceError> {
+    let real_account: Account<Fr> = (&account, storage_root).try_into()?;
 
     let (codehash_hi, codehash_lo) = hi_lo(account.code_hash);
     let h1 = hash(codehash_hi, codehash_lo);
-    let h2 = hash(h1, storage_root);
+    let h2 = hash(storage_root, h1);
 
-    let nonce = Fr::from(account.nonce);
+    let nonce_and_codesize =
+        Fr::from(account.nonce) + Fr::from(account.code_size) * Fr::from(1 << 32).square();
     let balance = balance_convert(account.balance);
-    let h3 = hash(nonce, balance);
+    let h3 = hash(nonce_and_codesize, balance);
 
     let h4 = hash(h3, h2);
 
+    let poseidon_codehash = balance_convert(account.poseidon_code_hash);
+    let account_hash = hash(h4, poseidon_codehash);
+
     let account_key = account_key(address);
     let h5 = hash(Fr::one(), account_key);
 
-    let h6 = hash(h5, h4);
+    let h6 = hash(h5, account_hash);
 
-    let mut account_hash_traces = [[Fr::zero(); 3]; 6];
+    let mut account_hash_traces = [[Fr::zero(); 3]; 7];
     account_hash_traces[0] = [codehash_hi, codehash_lo, h1];
-    account_hash_traces[1] = [h1, storage_root, h2];
-    account_hash_traces[2] = [nonce, balance, h3];
-    account_hash_traces[3] = [h3, h2, h4]; //
-    account_hash_traces[4] = [Fr::one(), account_key, h5]; // this should be the sibling?
-    account_hash_traces[5] = [h5, h4, h6];
-
-    // h4 is value of node?
-    // h5 is sibling of node?
-    assert_eq!(real_account.account_hash(), h4);
-    account_hash_traces
+    account_hash_traces[1] = [storage_root, h1, h2];
+    account_hash_traces[2] = [nonce_and_codesize, balance, h3];
+    account_hash_traces[3] = [h3, h2, h4];
+    account_hash_traces[4] = [h4, poseidon_codehash, account_hash];
+    account_hash_traces[5] = [Fr::one(), account_key, h5];
+    account_hash_traces[6] = [h5, account_hash, h6];
+
+    if real_account.account_hash() != account_hash {
+        return Err(TraceError::DataErr(
+            "account hash differs from operation::Account".to_string(),
+        ));
+    }
+    Ok(account_hash_traces)
 }
 
 fn get_internal_hash_traces(
@@ -354,7 +571,7 @@ fn get_internal_hash_traces(
     leaf_hashes: [Fr; 2],
     open_hash_traces: &[SMTNode],
     close_hash_traces: &[SMTNode],
-) -> Vec<(bool, Fr, Fr, Fr, bool, bool)> {
+) -> Result<Vec<(bool, Fr, Fr, Fr, bool, bool)>, TraceError> {
     let _path_length = std::cmp::max(open_hash_traces.len(), close_hash_traces.len());
 
     let mut address_hash_traces = vec![];
@@ -365,46 +582,48 @@ fn get_internal_hash_traces(
     {
         address_hash_traces.push(match e {
             EitherOrBoth::Both(open, close) => {
-                assert_eq!(open.sibling, close.sibling);
+                if open.sibling != close.sibling {
+                    return Err(TraceError::SiblingMismatch { depth: i });
+                }
                 (
                     key.bit(i),
-                    fr(open.value),
-                    fr(close.value),
-                    fr(open.sibling),
+                    fr(open.value)?,
+                    fr(close.value)?,
+                    fr(open.sibling)?,
                     false,
                     false,
                 )
             }
             EitherOrBoth::Left(open) => (
                 key.bit(i),
-                fr(open.value),
+                fr(open.value)?,
                 leaf_hashes[1],
-                fr(open.sibling),
+                fr(open.sibling)?,
                 false,
                 true,
             ),
             EitherOrBoth::Right(close) => (
                 key.bit(i),
                 leaf_hashes[0],
-                fr(close.value),
-                fr(close.sibling),
+                fr(close.value)?,
+                fr(close.sibling)?,
                 true,
                 false,
             ),
         });
     }
     address_hash_traces.reverse();
-    address_hash_traces
+    Ok(address_hash_traces)
 }
 
-fn empty_account_hash_traces(leaf: LeafNode) -> [[Fr; 3]; 6] {
-    let mut hash_traces = [[Fr::zero(); 3]; 6];
+fn empty_account_hash_traces(leaf: LeafNode) -> [[Fr; 3]; 7] {
+    let mut hash_traces = [[Fr::zero(); 3]; 7];
 
     let h5 = hash(Fr::one(), leaf.key);
     let h6 = hash(h5, leaf.value_hash);
 
-    hash_traces[4] = [Fr::one(), leaf.key, h5];
-    hash_traces[5] = [h5, leaf.value_hash, h6];
+    hash_traces[5] = [Fr::one(), leaf.key, h5];
+    hash_traces[6] = [h5, leaf.value_hash, h6];
 
     hash_traces
 }
@@ -414,14 +633,6 @@ fn storage_key_value_hash_traces(key: U256, value: U256) -> [[Fr; 3]; 3] {
     let (value_high, value_low) = split_word(value);
     let h0 = hash(key_high, key_low);
     let h1 = hash(value_high, value_low);
-    dbg!(
-        hash(key_high, key_low),
-        hash(value_high, value_low),
-        hash(Fr::one(), hash(key_high, key_low)),
-        hash(Fr::one(), hash(value_high, value_low)),
-        hash(h0, h1),
-        hash(h1, h0),
-    );
 
     let mut hash_traces = [[Fr::zero(); 3]; 3];
     hash_traces[0] = [key_high, key_low, h0];
@@ -462,26 +673,24 @@ impl Proof {
         // this suggests we want something that keeps 1/2 unchanged if something....
         // going to have to add an is padding row or something?
         assert_eq!(
-            self.old_account_hash_traces[5][2],
+            self.old_account_hash_traces[6][2],
             self.address_hash_traces.get(0).unwrap().1
         );
 
         assert_eq!(
-            self.new_account_hash_traces[5][2],
+            self.new_account_hash_traces[6][2],
             self.address_hash_traces.get(0).unwrap().2
         );
 
-        dbg!(self.old_account_hash_traces, self.leafs);
-
-        assert_eq!(
-            hash(hash(Fr::one(), self.leafs[0].key), self.leafs[0].value_hash),
-            self.old_account_hash_traces[5][2],
-        );
-
-        assert_eq!(
-            hash(hash(Fr::one(), self.leafs[1].key), self.leafs[1].value_hash),
-            self.new_account_hash_traces[5][2],
-        );
+        for (leaf, account_hash_traces) in self
+            .leafs
+            .iter()
+            .zip_eq([self.old_account_hash_traces, self.new_account_hash_traces])
+        {
+            if let Some(leaf) = leaf {
+                assert_eq!(leaf.hash(), account_hash_traces[6][2]);
+            }
+        }
 
         // storage poseidon hashes are correct
         self.storage_hash_traces
@@ -516,8 +725,8 @@ impl Proof {
             if let Some((direction, open, close, sibling, _, _)) =
                 self.storage_hash_traces.as_ref().unwrap().last()
             {
-                let old_storage_root = self.old_account_hash_traces[1][1];
-                let new_storage_root = self.new_account_hash_traces[1][1];
+                let old_storage_root = self.old_account_hash_traces[1][0];
+                let new_storage_root = self.new_account_hash_traces[1][0];
                 if *direction {
                     assert_eq!(hash(*sibling, *open), old_storage_root);
                     assert_eq!(hash(*sibling, *close), new_storage_root);
@@ -593,29 +802,46 @@ fn check_hash_traces_new(traces: &[(bool, Fr, Fr, Fr, bool, bool)]) {
     }
 }
 
-fn path_root(path: SMTPath) -> Fr {
-    let parse: SMTPathParse<Fr> = SMTPathParse::try_from(&path).unwrap();
-    for (a, b, c) in parse.0.hash_traces {
-        assert_eq!(hash(a, b), c)
-    }
+fn path_root(path: &SMTPath) -> Result<Fr, TraceError> {
+    // SMTPathParse checks the path bits and that the path hashes to the root.
+    SMTPathParse::<Fr>::try_from(path)?;
 
-    let account_hash = if let Some(node) = path.clone().leaf {
-        hash(hash(Fr::one(), fr(node.sibling)), fr(node.value))
+    let account_hash = if let Some(node) = &path.leaf {
+        leaf_hash(fr(node.sibling)?, fr(node.value)?)
     } else {
         Fr::zero()
     };
 
-    let directions = bits(path.path_part.clone().try_into().unwrap(), path.path.len());
+    let directions = bits(
+        path.path_part
+            .clone()
+            .try_into()
+            .map_err(|_| TraceError::BadPathBits {
+                path_part: path.path_part.clone(),
+                len: path.path.len(),
+            })?,
+        path.path.len(),
+    );
     let mut digest = account_hash;
     for (&bit, node) in directions.iter().zip(path.path.iter().rev()) {
-        assert_eq!(digest, fr(node.value));
+        if digest != fr(node.value)? {
+            return Err(TraceError::DataErr(format!(
+                "node value {:?} does not match its children",
+                node.value
+            )));
+        }
         digest = if bit {
-            hash(fr(node.sibling), digest)
+            hash(fr(node.sibling)?, digest)
         } else {
-            hash(digest, fr(node.sibling))
+            hash(digest, fr(node.sibling)?)
         };
     }
-    assert_eq!(digest, fr(path.root));
+    if digest != fr(path.root)? {
+        return Err(TraceError::RootMismatch {
+            expected: path.root,
+            actual: HexBytes(digest.to_bytes()),
+        });
+    }
     fr(path.root)
 }
 
@@ -631,30 +857,47 @@ fn bits(x: usize, len: usize) -> Vec<bool> {
     bits
 }
 
-fn fr(x: HexBytes<32>) -> Fr {
-    Fr::from_bytes(&x.0).unwrap()
+pub(crate) fn fr(x: HexBytes<32>) -> Result<Fr, TraceError> {
+    Option::from(Fr::from_bytes(&x.0)).ok_or(TraceError::NonCanonical(x))
 }
 
 fn u256(x: &BigUint) -> U256 {
     U256::from_big_endian(&x.to_bytes_be())
 }
 
+fn u256_to_fr(x: U256) -> Fr {
+    x.0.iter().rev().fold(Fr::zero(), |a, b| {
+        a * Fr::from(1 << 32).square() + Fr::from(*b)
+    })
+}
+
 fn u256_from_hex(x: HexBytes<32>) -> U256 {
     U256::from_big_endian(&x.0)
 }
 
-fn hash(x: Fr, y: Fr) -> Fr {
+pub(crate) fn hash(x: Fr, y: Fr) -> Fr {
     Hashable::hash([x, y])
 }
 
-fn account_key(address: Address) -> Fr {
+pub(crate) fn leaf_hash(key: Fr, value_hash: Fr) -> Fr {
+    hash(hash(Fr::one(), key), value_hash)
+}
+
+pub fn account_key(address: Address) -> Fr {
+    let (address_high, address_low) = address_halves(address);
+    hash(address_high, address_low)
+}
+
+/// The first 16 bytes of an address and its last 4 bytes shifted left by 96 bits, which are
+/// hashed into the account key.
+pub fn address_halves(address: Address) -> (Fr, Fr) {
     // TODO: the names of these are reversed
     let high_bytes: [u8; 16] = address.0[..16].try_into().unwrap();
     let low_bytes: [u8; 4] = address.0[16..].try_into().unwrap();
 
     let address_high = Fr::from_u128(u128::from_be_bytes(high_bytes));
     let address_low = Fr::from_u128(u128::from(u32::from_be_bytes(low_bytes)) << 96);
-    hash(address_high, address_low)
+    (address_high, address_low)
 }
 
 fn storage_key_hash(key: U256) -> Fr {
@@ -662,6 +905,12 @@ fn storage_key_hash(key: U256) -> Fr {
     hash(high, low)
 }
 
+// The code hash is wider than a field element, so the value proven for it is H(high, low).
+fn code_hash_value(code_hash: U256) -> Fr {
+    let (high, low) = split_word(code_hash);
+    hash(high, low)
+}
+
 fn split_word(x: U256) -> (Fr, Fr) {
     let mut bytes = [0; 32];
     x.to_big_endian(&mut bytes);
@@ -745,7 +994,7 @@ mod test {
 
                 let directions_1 = bits(open.path_part.try_into().unwrap(), open.path.len());
                 let directions_2: Vec<_> = (0..open.path.len())
-                    .map(|i| fr(trace.account_key).bit(open.path.len() - 1 - i))
+                    .map(|i| fr(trace.account_key).unwrap().bit(open.path.len() - 1 - i))
                     .collect();
                 assert_eq!(directions_1, directions_2);
             }
@@ -758,7 +1007,7 @@ mod test {
             let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
             for trace in traces {
                 let address = Address::from(trace.address.0);
-                assert_eq!(fr(trace.account_key), account_key(address));
+                assert_eq!(fr(trace.account_key).unwrap(), account_key(address));
             }
         }
     }
@@ -769,7 +1018,7 @@ mod test {
     //     for s in [TRACES, READ_TRACES, TOKEN_TRACES] {
     //         let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
     //         for trace in traces {
-    //             let proof = Proof::from(trace);
+    //             let proof = Proof::try_from(trace).unwrap();
     //             proof.check();
     //             // break;
     //         }
@@ -782,16 +1031,42 @@ mod test {
         for s in [READ_TRACES, TOKEN_TRACES] {
             let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(s).unwrap();
             for trace in traces {
-                let proof = Proof::from(trace);
+                let proof = Proof::try_from(trace).unwrap();
                 proof.check();
             }
         }
     }
 
+    #[test]
+    fn self_destruct_claims() {
+        let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(TOKEN_TRACES).unwrap();
+
+        let mut trace = traces[0].clone();
+        trace.account_update[1] = None;
+        trace.state_update = None;
+        assert!(matches!(
+            ClaimKind::try_from(&trace).unwrap(),
+            ClaimKind::Write(Write::Delete)
+        ));
+
+        let mut trace = traces
+            .into_iter()
+            .find(|trace| matches!(trace.state_update, Some([Some(_), Some(_)])))
+            .unwrap();
+        trace.state_update.as_mut().unwrap()[1] = None;
+        assert!(matches!(
+            ClaimKind::try_from(&trace).unwrap(),
+            ClaimKind::Write(Write::Storage {
+                new_value: None,
+                ..
+            })
+        ));
+    }
+
     #[test]
     fn check_empty_account() {
         let trace: SMTTrace = serde_json::from_str(EMPTY_ACCOUNT_TRACE).unwrap();
-        let proof = Proof::from(trace);
+        let proof = Proof::try_from(trace).unwrap();
         proof.check();
     }
 
@@ -799,7 +1074,7 @@ mod test {
     fn check_deploy_traces() {
         let traces: Vec<SMTTrace> = serde_json::from_str::<Vec<_>>(DEPLOY_TRACES).unwrap();
         for trace in traces {
-            let proof = Proof::from(trace);
+            let proof = Proof::try_from(trace).unwrap();
             proof.check();
         }
     }
@@ -807,15 +1082,18 @@ mod test {
     #[test]
     fn check_empty_storage_write() {
         let trace: SMTTrace = serde_json::from_str(EMPTY_STORAGE_TRACE).unwrap();
-        let proof = Proof::from(trace);
+        let proof = Proof::try_from(trace).unwrap();
         proof.check();
     }
 
     fn storage_roots(trace: &SMTTrace) -> [Fr; 2] {
         if let Some(root) = trace.common_state_root {
-            [root, root].map(fr)
+            [fr(root).unwrap(); 2]
         } else {
-            trace.state_path.clone().map(|p| path_root(p.unwrap()))
+            trace
+                .state_path
+                .clone()
+                .map(|p| path_root(&p.unwrap()).unwrap())
         }
     }
 
@@ -0,0 +1,588 @@
+//! native verification of `SMTTrace` sequences, which replays the poseidon hashing out of
+//! circuit so that bad witnesses can be rejected before proving
+
+use crate::{
+    operation::{to_hash, Account, KeyValue, TraceError},
+    serde::{AccountData, Hash, SMTPath, SMTTrace, StateData},
+    types::{fr, hash, leaf_hash},
+};
+use halo2_proofs::halo2curves::{bn256::Fr, group::ff::PrimeField};
+use num_bigint::BigUint;
+use thiserror::Error;
+
+/// Errors raised when verifying traces natively
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    /// there is no trace to verify
+    #[error("no trace to verify")]
+    NoTrace,
+    /// the account data can not be converted into an account
+    #[error(transparent)]
+    Trace(#[from] TraceError),
+    /// the key in the trace differs from the hash of the address or storage key
+    #[error("key mismatch: trace has {expected:?}, hashed key is {actual:?}")]
+    KeyMismatch {
+        /// key given in the trace
+        expected: Hash,
+        /// hash of the address or storage key
+        actual: Hash,
+    },
+    /// the path bits are not the low bits of the key
+    #[error("path bits {path_part:#x} are not the low {len} bits of the key")]
+    BadPathBits {
+        /// path bits given in the trace
+        path_part: BigUint,
+        /// number of nodes in the path
+        len: usize,
+    },
+    /// a node in the path differs from the hash of its children
+    #[error("node mismatch at depth {depth}: path has {expected:?}, children hash to {actual:?}")]
+    NodeMismatch {
+        /// depth of the node, 0 for the root
+        depth: usize,
+        /// value given in the path
+        expected: Hash,
+        /// hash of the children
+        actual: Hash,
+    },
+    /// the leaf at the end of the path differs from the one expected from the data
+    #[error("leaf mismatch: path ends in {expected:?}, data hashes to {actual:?}")]
+    LeafMismatch {
+        /// hash of the leaf in the path, zero for an empty node
+        expected: Hash,
+        /// hash of the leaf expected from the account or storage data
+        actual: Hash,
+    },
+    /// the old and new paths do not share the siblings along their common depth, or the
+    /// longer one has siblings other than empty nodes and the leaf of the shorter one past it
+    #[error("old and new paths have inconsistent siblings at depth {0}")]
+    SiblingMismatch(usize),
+    /// the paths end in the leaf of another key, which differs between the old and new paths
+    #[error("leaf of another key {old:?} at the end of the old path is changed into {new:?}")]
+    OtherLeafChanged {
+        /// hash of the leaf at the end of the old path, zero for an empty node
+        old: Hash,
+        /// hash of the leaf at the end of the new path, zero for an empty node
+        new: Hash,
+    },
+    /// a storage trace changes fields of the account other than its storage root
+    #[error("account fields other than the storage root are changed by a storage trace")]
+    AccountChanged,
+    /// the trace does not start from the root the previous trace ends in
+    #[error("root {actual:?} does not chain from previous root {expected:?}")]
+    Unchained {
+        /// new root of the previous trace
+        expected: Hash,
+        /// old root of the trace
+        actual: Hash,
+    },
+    /// malformed combination of storage paths and updates
+    #[error("malformed data: {0}")]
+    DataErr(String),
+    /// error raised for one of the paths in a trace
+    #[error("{path} path: {source}")]
+    AtPath {
+        /// which one of the paths, e.g. "old account"
+        path: &'static str,
+        /// error raised for the path
+        #[source]
+        source: Box<VerifyError>,
+    },
+    /// error raised while verifying one trace of a sequence
+    #[error("trace {index}: {source}")]
+    AtTrace {
+        /// index of the offending trace
+        index: usize,
+        /// error raised for the trace
+        #[source]
+        source: Box<VerifyError>,
+    },
+}
+
+impl VerifyError {
+    fn at_path(self, path: &'static str) -> Self {
+        Self::AtPath {
+            path,
+            source: Box::new(self),
+        }
+    }
+
+    fn at(self, index: usize) -> Self {
+        Self::AtTrace {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
+
+/// verify a sequence of traces natively: every account and storage path must hash to its
+/// root and end in the leaf expected from the account or storage data, and each trace must
+/// start from the root the previous one ends in. return the final account root
+pub fn verify_traces(traces: &[SMTTrace]) -> Result<Fr, VerifyError> {
+    let mut root = None;
+    for (i, trace) in traces.iter().enumerate() {
+        let [old_root, new_root] = verify_trace(trace).map_err(|e| e.at(i))?;
+        if let Some(prev_root) = root {
+            if prev_root != old_root {
+                return Err(VerifyError::Unchained {
+                    expected: to_hash(prev_root),
+                    actual: to_hash(old_root),
+                }
+                .at(i));
+            }
+        }
+        root = Some(new_root);
+    }
+    root.ok_or(VerifyError::NoTrace)
+}
+
+// verify a single trace, return its old and new account roots
+fn verify_trace(trace: &SMTTrace) -> Result<[Fr; 2], VerifyError> {
+    let account_key = KeyValue::<Fr>::from(&trace.address).hash();
+    check_key(trace.account_key, account_key)?;
+
+    let storage_roots = match (
+        trace.common_state_root,
+        trace.state_key,
+        &trace.state_path,
+        &trace.state_update,
+    ) {
+        (Some(storage_root), None, [None, None], Some([None, None]) | None) => {
+            [fr(storage_root)?; 2]
+        }
+        (None, Some(state_key), [Some(old_path), Some(new_path)], Some(update)) => {
+            if trace.account_update[0] != trace.account_update[1] {
+                return Err(VerifyError::AccountChanged);
+            }
+            verify_storage(state_key, [old_path, new_path], update)?
+        }
+        _ => {
+            return Err(VerifyError::DataErr(
+                "unexpected combination of storage paths and updates".to_string(),
+            ))
+        }
+    };
+
+    let mut leafs = [Fr::zero(); 2];
+    for (i, ((account, storage_root), name)) in trace
+        .account_update
+        .iter()
+        .zip(storage_roots)
+        .zip(["old account", "new account"])
+        .enumerate()
+    {
+        leafs[i] = account_leaf(account.as_ref(), account_key, storage_root)
+            .map_err(|e| e.at_path(name))?;
+    }
+    let [old_path, new_path] = &trace.account_path;
+    verify_paths(
+        [old_path, new_path],
+        account_key,
+        leafs,
+        ["old account", "new account"],
+    )
+}
+
+// verify the storage paths against the storage update, return the old and new storage roots
+fn verify_storage(
+    state_key: Hash,
+    paths: [&SMTPath; 2],
+    update: &[Option<StateData>; 2],
+) -> Result<[Fr; 2], VerifyError> {
+    let key = fr(state_key)?;
+    let mut leafs = [Fr::zero(); 2];
+    for (i, data) in update.iter().enumerate() {
+        if let Some(data) = data {
+            check_key(state_key, KeyValue::<Fr>::from(&data.key).hash())?;
+            // a zero value is never stored, it marks the key as absent
+            if data.value.0 != [0; 32] {
+                leafs[i] = leaf_hash(key, KeyValue::<Fr>::from(&data.value).hash());
+            }
+        }
+    }
+    verify_paths(paths, key, leafs, ["old storage", "new storage"])
+}
+
+// the leaf expected in the account trie for the account data, zero if there is no account
+fn account_leaf(
+    account: Option<&AccountData>,
+    account_key: Fr,
+    storage_root: Fr,
+) -> Result<Fr, VerifyError> {
+    Ok(match account {
+        Some(account) => leaf_hash(
+            account_key,
+            Account::<Fr>::try_from((account, storage_root))?.account_hash(),
+        ),
+        None => Fr::zero(),
+    })
+}
+
+// verify both paths lead to the expected leafs and share their siblings, return the roots
+fn verify_paths(
+    paths: [&SMTPath; 2],
+    key: Fr,
+    leafs: [Fr; 2],
+    names: [&'static str; 2],
+) -> Result<[Fr; 2], VerifyError> {
+    for (depth, (old, new)) in paths[0].path.iter().zip(&paths[1].path).enumerate() {
+        if old.sibling != new.sibling {
+            return Err(VerifyError::SiblingMismatch(depth));
+        }
+    }
+    let mut roots = [Fr::zero(); 2];
+    for (i, (path, name)) in paths.iter().zip(names).enumerate() {
+        roots[i] = verify_path(path, key, leafs[i]).map_err(|e| e.at_path(name))?;
+    }
+    verify_extension(paths, key)?;
+    Ok(roots)
+}
+
+// when the key is inserted or deleted next to the leaf of another key, the longer path
+// extends the shorter one by empty siblings and ends beside that leaf, at the first depth
+// where the two keys branch. when neither path is extended, a leaf of another key at the end
+// of either path must be left as it is
+fn verify_extension(paths: [&SMTPath; 2], key: Fr) -> Result<(), VerifyError> {
+    let [short, long] = if paths[0].path.len() <= paths[1].path.len() {
+        paths
+    } else {
+        [paths[1], paths[0]]
+    };
+    let (common, len) = (short.path.len(), long.path.len());
+    if common == len {
+        let (old, new) = (other_leaf(paths[0], key)?, other_leaf(paths[1], key)?);
+        if (old.is_some() || new.is_some()) && old != new {
+            let hash = |leaf: Option<(Fr, Fr)>| {
+                to_hash(leaf.map_or(Fr::zero(), |(key, value)| leaf_hash(key, value)))
+            };
+            return Err(VerifyError::OtherLeafChanged {
+                old: hash(old),
+                new: hash(new),
+            });
+        }
+        return Ok(());
+    }
+    // the shorter path must end in a leaf of another key, or the longer one would not be
+    // the path of a compact trie
+    let leaf = match &short.leaf {
+        Some(node) if fr(node.sibling)? != key => node,
+        _ => return Err(VerifyError::SiblingMismatch(common)),
+    };
+    let leaf_key = fr(leaf.sibling)?;
+    let bits = |x: Fr| BigUint::from_bytes_le(x.to_repr().as_ref());
+    let (key_bits, leaf_bits) = (bits(key), bits(leaf_key));
+    for depth in common..len {
+        let expected = if depth + 1 == len {
+            if key_bits.bit(depth as u64) == leaf_bits.bit(depth as u64) {
+                return Err(VerifyError::SiblingMismatch(depth));
+            }
+            leaf_hash(leaf_key, fr(leaf.value)?)
+        } else {
+            if key_bits.bit(depth as u64) != leaf_bits.bit(depth as u64) {
+                return Err(VerifyError::SiblingMismatch(depth));
+            }
+            Fr::zero()
+        };
+        if fr(long.path[depth].sibling)? != expected {
+            return Err(VerifyError::SiblingMismatch(depth));
+        }
+    }
+    Ok(())
+}
+
+// the key and value hash of the leaf the path ends in, if it is the leaf of another key
+fn other_leaf(path: &SMTPath, key: Fr) -> Result<Option<(Fr, Fr)>, VerifyError> {
+    match &path.leaf {
+        Some(node) if fr(node.sibling)? != key => Ok(Some((fr(node.sibling)?, fr(node.value)?))),
+        _ => Ok(None),
+    }
+}
+
+// verify the path hashes to its root and ends in the expected leaf, which is zero for an
+// absent key. in that case the path may end in a leaf of another key sharing the path bits
+fn verify_path(path: &SMTPath, key: Fr, leaf: Fr) -> Result<Fr, VerifyError> {
+    let len = path.path.len();
+    let mask = (BigUint::from(1u64) << len) - 1u64;
+    let low_bits = |x: Fr| BigUint::from_bytes_le(x.to_repr().as_ref()) & &mask;
+    if path.path_part != low_bits(key) {
+        return Err(VerifyError::BadPathBits {
+            path_part: path.path_part.clone(),
+            len,
+        });
+    }
+
+    let path_leaf = match &path.leaf {
+        Some(node) => {
+            let leaf_key = fr(node.sibling)?;
+            if leaf_key != key && (leaf != Fr::zero() || low_bits(leaf_key) != path.path_part) {
+                return Err(VerifyError::KeyMismatch {
+                    expected: node.sibling,
+                    actual: to_hash(key),
+                });
+            }
+            leaf_hash(leaf_key, fr(node.value)?)
+        }
+        None => Fr::zero(),
+    };
+    let found = path.leaf.as_ref().map(|node| node.sibling) == Some(to_hash(key));
+    if (found && path_leaf != leaf) || (!found && leaf != Fr::zero()) {
+        return Err(VerifyError::LeafMismatch {
+            expected: to_hash(path_leaf),
+            actual: to_hash(leaf),
+        });
+    }
+
+    let mut digest = path_leaf;
+    for (depth, node) in path.path.iter().enumerate().rev() {
+        if fr(node.value)? != digest {
+            return Err(VerifyError::NodeMismatch {
+                depth: depth + 1,
+                expected: node.value,
+                actual: to_hash(digest),
+            });
+        }
+        let sibling = fr(node.sibling)?;
+        digest = if path.path_part.bit(depth as u64) {
+            hash(sibling, digest)
+        } else {
+            hash(digest, sibling)
+        };
+    }
+    if fr(path.root)? != digest {
+        return Err(VerifyError::NodeMismatch {
+            depth: 0,
+            expected: path.root,
+            actual: to_hash(digest),
+        });
+    }
+    Ok(digest)
+}
+
+fn check_key(expected: Hash, actual: Fr) -> Result<(), VerifyError> {
+    if expected != to_hash(actual) {
+        return Err(VerifyError::KeyMismatch {
+            expected,
+            actual: to_hash(actual),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{serde::HexBytes, trie::StateTrie};
+
+    const TRACES: &str = include_str!("../tests/traces.json");
+    const READ_TRACES: &str = include_str!("../tests/read_traces.json");
+    const TOKEN_TRACES: &str = include_str!("../tests/token_traces.json");
+    const DUAL_CODE_HASH_TRACES: &str = include_str!("../tests/dual_code_hash/traces_1.json");
+
+    fn state_trie_traces() -> (Vec<SMTTrace>, Fr) {
+        let mut trie = StateTrie::<Fr>::default();
+        let mut traces = vec![];
+        for i in 1..4u8 {
+            let address = HexBytes([i; 20]);
+            let account = AccountData {
+                nonce: i.into(),
+                balance: BigUint::from(100u64),
+                code_hash: BigUint::default(),
+                poseidon_code_hash: BigUint::default(),
+                code_size: 0,
+            };
+            traces.push(trie.update_account(address, account).unwrap());
+            traces.push(
+                trie.update_storage(address, HexBytes([i; 32]), HexBytes([i + 1; 32]))
+                    .unwrap(),
+            );
+        }
+        traces.push(
+            trie.read_storage(HexBytes([1; 20]), HexBytes([9; 32]))
+                .unwrap(),
+        );
+        traces.push(trie.delete_account(HexBytes([2; 20])).unwrap());
+        traces.push(trie.read_account(HexBytes([2; 20])));
+        (traces, trie.root())
+    }
+
+    #[test]
+    fn verify_fixtures() {
+        for s in [TRACES, READ_TRACES, TOKEN_TRACES, DUAL_CODE_HASH_TRACES] {
+            let traces: Vec<SMTTrace> = serde_json::from_str(s).unwrap();
+            let root = verify_traces(&traces).unwrap();
+            assert_eq!(to_hash(root), traces.last().unwrap().account_path[1].root);
+        }
+    }
+
+    #[test]
+    fn verify_state_trie_traces() {
+        let (traces, root) = state_trie_traces();
+        assert_eq!(verify_traces(&traces).unwrap(), root);
+        assert!(matches!(verify_traces(&[]), Err(VerifyError::NoTrace)));
+    }
+
+    #[test]
+    fn verify_bad_traces() {
+        let (traces, _) = state_trie_traces();
+
+        let mut unchained = traces.clone();
+        unchained.swap(1, 2);
+        match verify_traces(&unchained) {
+            Err(VerifyError::AtTrace { index, source }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, VerifyError::Unchained { .. }));
+            }
+            r => panic!("expected unchained roots at trace 1, got {:?}", r),
+        }
+
+        let mut bad_sibling = traces.clone();
+        let path = &mut bad_sibling[4].account_path[0];
+        path.path[0].sibling = to_hash(fr(path.path[0].sibling).unwrap() + Fr::one());
+        assert!(matches!(
+            verify_traces(&bad_sibling),
+            Err(VerifyError::AtTrace { index: 4, .. })
+        ));
+
+        let mut bad_account = traces.clone();
+        bad_account[2].account_update[1].as_mut().unwrap().nonce += 1;
+        assert!(matches!(
+            verify_traces(&bad_account),
+            Err(VerifyError::AtTrace { index: 2, .. })
+        ));
+
+        let mut bad_storage = traces;
+        bad_storage[3].state_update.as_mut().unwrap()[1]
+            .as_mut()
+            .unwrap()
+            .value = HexBytes([7; 32]);
+        assert!(matches!(
+            verify_traces(&bad_storage),
+            Err(VerifyError::AtTrace { index: 3, .. })
+        ));
+    }
+
+    // recompute the nodes and the root of the path from its leaf and siblings
+    fn rehash(path: &mut SMTPath) {
+        let mut digest = path.leaf.as_ref().map_or(Fr::zero(), |node| {
+            leaf_hash(fr(node.sibling).unwrap(), fr(node.value).unwrap())
+        });
+        for (depth, node) in path.path.iter_mut().enumerate().rev() {
+            node.value = to_hash(digest);
+            let sibling = fr(node.sibling).unwrap();
+            digest = if path.path_part.bit(depth as u64) {
+                hash(sibling, digest)
+            } else {
+                hash(digest, sibling)
+            };
+        }
+        path.root = to_hash(digest);
+    }
+
+    fn assert_rejected(trace: SMTTrace, check: impl Fn(&VerifyError) -> bool) {
+        match verify_traces(&[trace]) {
+            Err(VerifyError::AtTrace { source, .. }) => assert!(check(&source), "{:?}", source),
+            r => panic!("expected the trace to be rejected, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn verify_bad_extension() {
+        let (traces, _) = state_trie_traces();
+        let inserted = traces
+            .into_iter()
+            .find(|trace| {
+                let [old, new] = &trace.account_path;
+                old.leaf.is_some() && new.path.len() > old.path.len()
+            })
+            .unwrap();
+        assert!(verify_traces(&[inserted.clone()]).is_ok());
+
+        // the sibling beside the new leaf must be the leaf pushed down
+        let mut moved = inserted;
+        let path = &mut moved.account_path[1];
+        let node = path.path.last_mut().unwrap();
+        node.sibling = to_hash(fr(node.sibling).unwrap() + Fr::one());
+        rehash(path);
+        assert_rejected(moved, |e| matches!(e, VerifyError::SiblingMismatch(_)));
+    }
+
+    // a read of an absent account whose path ends in the leaf of another account
+    fn read_beside_other_leaf() -> SMTTrace {
+        let mut trie = StateTrie::<Fr>::default();
+        for i in 1..4u8 {
+            let account = AccountData {
+                nonce: i.into(),
+                balance: BigUint::from(100u64),
+                code_hash: BigUint::default(),
+                poseidon_code_hash: BigUint::default(),
+                code_size: 0,
+            };
+            trie.update_account(HexBytes([i; 20]), account).unwrap();
+        }
+        (4..u8::MAX)
+            .map(|i| trie.read_account(HexBytes([i; 20])))
+            .find(|trace| trace.account_path[0].leaf.is_some())
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_other_leaf_unchanged() {
+        let read = read_beside_other_leaf();
+        assert!(verify_traces(&[read.clone()]).is_ok());
+
+        // the empty account is read while the leaf of another account is rewritten
+        let mut rewritten = read.clone();
+        let path = &mut rewritten.account_path[1];
+        let leaf = path.leaf.as_mut().unwrap();
+        leaf.value = to_hash(fr(leaf.value).unwrap() + Fr::one());
+        rehash(path);
+        assert_rejected(rewritten, |e| {
+            matches!(e, VerifyError::OtherLeafChanged { .. })
+        });
+
+        // the account is inserted in place of the leaf of another account, dropping it
+        let mut replaced = read;
+        let account = AccountData {
+            nonce: 1,
+            balance: BigUint::default(),
+            code_hash: BigUint::default(),
+            poseidon_code_hash: BigUint::default(),
+            code_size: 0,
+        };
+        let storage_root = fr(replaced.common_state_root.unwrap()).unwrap();
+        let account_hash = Account::<Fr>::try_from((&account, storage_root))
+            .unwrap()
+            .account_hash();
+        replaced.account_update[1] = Some(account);
+        let path = &mut replaced.account_path[1];
+        let leaf = path.leaf.as_mut().unwrap();
+        leaf.sibling = replaced.account_key;
+        leaf.value = to_hash(account_hash);
+        rehash(path);
+        assert_rejected(replaced, |e| {
+            matches!(e, VerifyError::OtherLeafChanged { .. })
+        });
+    }
+
+    #[test]
+    fn verify_storage_changing_account() {
+        let (traces, _) = state_trie_traces();
+        let mut trace = traces
+            .into_iter()
+            .find(|trace| trace.state_key.is_some() && trace.account_update[1].is_some())
+            .unwrap();
+        assert!(verify_traces(&[trace.clone()]).is_ok());
+
+        // the new account leaf is recomputed, so the paths alone are consistent
+        let account = trace.account_update[1].as_mut().unwrap();
+        account.balance += 1u64;
+        let storage_root = fr(trace.state_path[1].as_ref().unwrap().root).unwrap();
+        let account_hash = Account::<Fr>::try_from((&*account, storage_root))
+            .unwrap()
+            .account_hash();
+        let path = &mut trace.account_path[1];
+        path.leaf.as_mut().unwrap().value = to_hash(account_hash);
+        rehash(path);
+        assert_rejected(trace, |e| matches!(e, VerifyError::AccountChanged));
+    }
+}
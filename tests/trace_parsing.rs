@@ -1,4 +1,4 @@
-use halo2_mpt_circuits::operation::AccountOp;
+use halo2_mpt_circuits::operation::{ops_from_traces, AccountOp, TraceError};
 use halo2_mpt_circuits::serde::*;
 pub use halo2_proofs::halo2curves::bn256::Fr as Fp;
 
@@ -36,6 +36,21 @@ fn trace_parse() {
     }
 }
 
+#[test]
+fn trace_parse_malformed() {
+    let mut traces: Vec<SMTTrace> = serde_json::from_str(TEST_TRACE1).unwrap();
+    assert!(ops_from_traces::<Fp>(&traces).is_ok());
+
+    traces[2].account_path[1].root = Hash::default();
+    match ops_from_traces::<Fp>(&traces) {
+        Err(TraceError::AtTrace { index, source }) => {
+            assert_eq!(index, 2);
+            assert!(matches!(*source, TraceError::RootMismatch { .. }));
+        }
+        _ => panic!("expected root mismatch at trace 2"),
+    }
+}
+
 #[test]
 fn trace_serialize() {
     let traces: SMTTrace = serde_json::from_str(SMT_TRACE_EXAMPLE).unwrap();
@@ -1,8 +1,13 @@
-use halo2_mpt_circuits::{operation::AccountOp, serde, EthTrie};
+use halo2_mpt_circuits::{
+    artifact::{check_hash_table_commitments, ArtifactError, TranscriptKind},
+    keystore::{CircuitShape, KeyStore, KeyStoreError},
+    operation::AccountOp,
+    serde, CircuitMode, CircuitSize, CombinedCircuit, EthTrie, EthTrieCircuit, MptProofArtifact,
+};
 use halo2_proofs::dev::MockProver;
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr as Fp, G1Affine};
-use halo2_proofs::plonk::{create_proof, keygen_pk, keygen_vk, verify_proof};
-use halo2_proofs::poly::commitment::ParamsProver;
+use halo2_proofs::plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ProvingKey};
+use halo2_proofs::poly::commitment::{Params as _, ParamsProver};
 use halo2_proofs::poly::kzg::commitment::{
     KZGCommitmentScheme, ParamsKZG as Params, ParamsVerifierKZG as ParamsVerifier,
 };
@@ -20,6 +25,17 @@ const TEST_TRACE: &str = include_str!("./dual_code_hash/traces_1.json");
 const TEST_TRACE_SMALL: &str = include_str!("./dual_code_hash/traces_1.json");
 const TEST_TRACE_READONLY: &str = include_str!("./dual_code_hash/traces_1.json");
 
+// a fresh directory per test run, so concurrent runs do not share files
+fn unique_temp_dir(name: &str) -> std::path::PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("{name}-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 #[test]
 fn trace_read_only() {
     let data: Vec<serde::SMTTrace> = serde_json::from_str(TEST_TRACE_READONLY).unwrap();
@@ -34,9 +50,9 @@ fn trace_read_only() {
 
     let mut data: EthTrie<Fp> = Default::default();
     data.add_ops(ops);
-    let (circuit, _) = data.circuits(200);
+    let (circuit, _) = data.circuits(200).unwrap();
 
-    let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+    let prover = MockProver::<Fp>::run(k, &circuit, circuit.instance()).unwrap();
     assert_eq!(prover.verify(), Ok(()));
 }
 
@@ -54,9 +70,9 @@ fn trace_to_eth_trie_each() {
 
         let mut data: EthTrie<Fp> = Default::default();
         data.add_op(op);
-        let (circuit, _) = data.circuits(40);
+        let (circuit, _) = data.circuits(40).unwrap();
 
-        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        let prover = MockProver::<Fp>::run(k, &circuit, circuit.instance()).unwrap();
         assert_eq!(prover.verify(), Ok(()));
     }
 }
@@ -73,9 +89,9 @@ fn trace_to_eth_trie() {
 
     let mut data: EthTrie<Fp> = Default::default();
     data.add_ops(ops);
-    let (circuit, _) = data.circuits(200);
+    let (circuit, _) = data.circuits(200).unwrap();
 
-    let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+    let prover = MockProver::<Fp>::run(k, &circuit, circuit.instance()).unwrap();
     assert_eq!(prover.verify(), Ok(()));
 }
 
@@ -90,7 +106,7 @@ fn vk_validity() {
 
     let mut data: EthTrie<Fp> = Default::default();
     data.add_ops(ops);
-    let (circuit, _) = data.to_circuits((200, None), &[]);
+    let (circuit, _) = data.to_circuits((200, None), &[]).unwrap();
 
     let vk1 = keygen_vk(&params, &circuit).unwrap();
 
@@ -99,7 +115,7 @@ fn vk_validity() {
         .unwrap();
 
     let data: EthTrie<Fp> = Default::default();
-    let (circuit, _) = data.to_circuits((200, None), &[]);
+    let (circuit, _) = data.to_circuits((200, None), &[]).unwrap();
     let vk2 = keygen_vk(&params, &circuit).unwrap();
 
     let mut vk2_buf: Vec<u8> = Vec::new();
@@ -125,9 +141,11 @@ fn proof_and_verify() {
 
     let mut data: EthTrie<Fp> = Default::default();
     data.add_ops(ops);
-    let (circuit, _) = data.to_circuits((200, None), &[]);
+    let instance = vec![data.start_root(), data.final_root()];
+    let (circuit, _) = data.to_circuits((200, None), &[]).unwrap();
+    assert_eq!(circuit.instance(), vec![instance.clone()]);
 
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
+    let prover = MockProver::run(k, &circuit, vec![instance.clone()]).unwrap();
     assert_eq!(prover.verify(), Ok(()));
 
     let vk = keygen_vk(&params, &circuit).unwrap();
@@ -137,29 +155,42 @@ fn proof_and_verify() {
         &params,
         &pk,
         &[circuit],
-        &[&[]],
+        &[&[&instance[..]]],
         os_rng,
         &mut transcript,
     )
     .unwrap();
 
     let proof_script = transcript.finalize();
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof_script[..]);
     let verifier_params: ParamsVerifier<Bn256> = params.verifier_params().clone();
-    let strategy = SingleStrategy::new(&params);
 
     let data: EthTrie<Fp> = Default::default();
-    let (circuit, _) = data.to_circuits((200, None), &[]);
+    let (circuit, _) = data.to_circuits((200, None), &[]).unwrap();
     let vk = keygen_vk(&params, &circuit).unwrap();
 
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof_script[..]);
     verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
         &verifier_params,
         &vk,
-        strategy,
-        &[&[]],
+        SingleStrategy::new(&params),
+        &[&[&instance[..]]],
         &mut transcript,
     )
     .unwrap();
+
+    // the proof does not verify against any other final root
+    let wrong_instance = vec![instance[0], instance[0]];
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof_script[..]);
+    assert!(
+        verify_proof::<KZGCommitmentScheme<Bn256>, VerifierSHPLONK<'_, Bn256>, _, _, _>(
+            &verifier_params,
+            &vk,
+            SingleStrategy::new(&params),
+            &[&[&wrong_instance[..]]],
+            &mut transcript,
+        )
+        .is_err()
+    );
 }
 
 #[test]
@@ -185,26 +216,27 @@ fn circuit_connection() {
     let trie_index = commit_indexs.hash_tbl_begin();
     let hash_index = commit_indexs.hash_tbl_begin_at_accompanied_circuit();
 
-    let (trie_circuit, hash_circuit) = data.circuits(200);
+    let (trie_circuit, hash_circuit) = data.circuits(200).unwrap();
+    let trie_instance = trie_circuit.instance();
     let hash_table_size = [0u8; 5];
 
     let vk = keygen_vk(&params, &trie_circuit).unwrap();
-    let pk = keygen_pk(&params, vk, &trie_circuit).unwrap();
+    let trie_pk = keygen_pk(&params, vk, &trie_circuit).unwrap();
 
     let mut transcript = PoseidonWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
     create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
         &params,
-        &pk,
+        &trie_pk,
         &[trie_circuit],
-        &[&[]],
+        &[&[&trie_instance[0][..]]],
         os_rng.clone(),
         &mut transcript,
     )
     .unwrap();
-    let proof_script = transcript.finalize();
+    let trie_proof = transcript.finalize();
 
     let rw_commitment_state = {
-        let mut transcript = PoseidonRead::<_, _, Challenge255<G1Affine>>::init(&proof_script[..]);
+        let mut transcript = PoseidonRead::<_, _, Challenge255<G1Affine>>::init(&trie_proof[..]);
         (0..trie_index).for_each(|_| {
             transcript.read_point().unwrap();
         });
@@ -213,23 +245,23 @@ fn circuit_connection() {
     //log::info!("rw_commitment_state {:?}", rw_commitment_state);
 
     let vk = keygen_vk(&params, &hash_circuit).unwrap();
-    let pk = keygen_pk(&params, vk, &hash_circuit).unwrap();
+    let hash_pk = keygen_pk(&params, vk, &hash_circuit).unwrap();
 
     dbg!("");
     let mut transcript = PoseidonWrite::<_, _, Challenge255<_>>::init(vec![]);
     create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
         &params,
-        &pk,
+        &hash_pk,
         &[hash_circuit],
         &[&[]],
-        os_rng,
+        os_rng.clone(),
         &mut transcript,
     )
     .unwrap();
-    let proof_script = transcript.finalize();
+    let hash_proof = transcript.finalize();
 
     let rw_commitment_evm = {
-        let mut transcript = PoseidonRead::<_, _, Challenge255<G1Affine>>::init(&proof_script[..]);
+        let mut transcript = PoseidonRead::<_, _, Challenge255<G1Affine>>::init(&hash_proof[..]);
         (0..hash_index).for_each(|_| {
             transcript.read_point().unwrap();
         });
@@ -239,4 +271,310 @@ fn circuit_connection() {
 
     assert_eq!(rw_commitment_evm, rw_commitment_state);
     //log::info!("Same commitment! Test passes!");
+
+    check_hash_table_commitments(
+        (&trie_proof, trie_pk.get_vk()),
+        (&hash_proof, hash_pk.get_vk()),
+        &commit_indexs,
+        TranscriptKind::Poseidon,
+    )
+    .unwrap();
+
+    // the hash circuit of an empty trie commits to another hash table
+    let (_, other_hash_circuit) = EthTrie::<Fp>::default().circuits(200).unwrap();
+    let mut transcript = PoseidonWrite::<_, _, Challenge255<_>>::init(vec![]);
+    create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+        &params,
+        &hash_pk,
+        &[other_hash_circuit],
+        &[&[]],
+        os_rng,
+        &mut transcript,
+    )
+    .unwrap();
+    let other_hash_proof = transcript.finalize();
+
+    let err = check_hash_table_commitments(
+        (&trie_proof, trie_pk.get_vk()),
+        (&other_hash_proof, hash_pk.get_vk()),
+        &commit_indexs,
+        TranscriptKind::Poseidon,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ArtifactError::HashTableMismatch { .. }));
+}
+
+#[test]
+fn full_circuit_connection() {
+    let data: Vec<serde::SMTTrace> = serde_json::from_str(TEST_TRACE).unwrap();
+    let ops: Vec<AccountOp<Fp>> = data
+        .into_iter()
+        .map(|tr| (&tr).try_into().unwrap())
+        .collect();
+
+    let mut data: EthTrie<Fp> = Default::default();
+    data.add_ops(ops);
+    let size = data.circuit_size();
+    let (trie_circuit, hash_circuit) = data.to_circuits((size.calcs, None), &[]).unwrap();
+    let trie_instance = trie_circuit.instance();
+
+    // the rlc columns of mpt table are allocated before the hash table, but committed
+    // after it, so the positions of commitments differ from the column indexes
+    let commit_indexs = halo2_mpt_circuits::CommitmentIndexs::new_full_circuit::<Fp>();
+
+    fn prove<C: Circuit<Fp>>(
+        k: u32,
+        circuit: C,
+        instances: &[&[Fp]],
+    ) -> (Vec<u8>, ProvingKey<G1Affine>) {
+        let params = Params::<Bn256>::unsafe_setup(k);
+        let vk = keygen_vk(&params, &circuit).unwrap();
+        let pk = keygen_pk(&params, vk, &circuit).unwrap();
+        let mut transcript = PoseidonWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
+        create_proof::<KZGCommitmentScheme<Bn256>, ProverSHPLONK<'_, Bn256>, _, _, _, _>(
+            &params,
+            &pk,
+            &[circuit],
+            &[instances],
+            ChaCha8Rng::from_seed([101u8; 32]),
+            &mut transcript,
+        )
+        .unwrap();
+        (transcript.finalize(), pk)
+    }
+    let (trie_proof, trie_pk) = prove(size.mpt_k, trie_circuit, &[&trie_instance[0]]);
+    let (hash_proof, hash_pk) = prove(size.hash_k, hash_circuit, &[]);
+
+    let read_hash_table = |proof: &[u8], begin: usize| {
+        let mut transcript = PoseidonRead::<_, _, Challenge255<G1Affine>>::init(proof);
+        (0..begin).for_each(|_| {
+            transcript.read_point().unwrap();
+        });
+        [0u8; 5].map(|_| transcript.read_point().unwrap())
+    };
+    assert_eq!(
+        read_hash_table(&trie_proof, commit_indexs.hash_tbl_begin()),
+        read_hash_table(
+            &hash_proof,
+            commit_indexs.hash_tbl_begin_at_accompanied_circuit()
+        ),
+    );
+
+    check_hash_table_commitments(
+        (&trie_proof, trie_pk.get_vk()),
+        (&hash_proof, hash_pk.get_vk()),
+        &commit_indexs,
+        TranscriptKind::Poseidon,
+    )
+    .unwrap();
+}
+
+#[test]
+fn proof_artifact() {
+    let data: Vec<serde::SMTTrace> = serde_json::from_str(TEST_TRACE).unwrap();
+    let ops: Vec<AccountOp<Fp>> = data
+        .into_iter()
+        .map(|tr| (&tr).try_into().unwrap())
+        .collect();
+
+    let mut data: EthTrie<Fp> = Default::default();
+    data.add_ops(ops);
+    let size = data.circuit_size();
+    let (mpt_circuit, hash_circuit) = data.to_circuits((size.calcs, None), &[]).unwrap();
+
+    let params = Params::<Bn256>::unsafe_setup(size.mpt_k.max(size.hash_k));
+    let (mut mpt_params, mut hash_params) = (params.clone(), params.clone());
+    mpt_params.downsize(size.mpt_k);
+    hash_params.downsize(size.hash_k);
+    let vk = keygen_vk(&mpt_params, &mpt_circuit).unwrap();
+    let mpt_pk = keygen_pk(&mpt_params, vk, &mpt_circuit).unwrap();
+    let vk = keygen_vk(&hash_params, &hash_circuit).unwrap();
+    let hash_pk = keygen_pk(&hash_params, vk, &hash_circuit).unwrap();
+
+    let artifact = MptProofArtifact::prove(
+        &params,
+        (&mpt_pk, &hash_pk),
+        (mpt_circuit.clone(), hash_circuit),
+        TranscriptKind::Poseidon,
+        ChaCha8Rng::from_seed([101u8; 32]),
+    )
+    .unwrap();
+    assert_eq!(artifact.calcs, size.calcs);
+    assert_eq!(artifact.instance().unwrap(), mpt_circuit.instance()[0]);
+
+    let vks = (mpt_pk.get_vk(), hash_pk.get_vk());
+    let dir = unique_temp_dir("mpt-artifact");
+    for name in ["mpt-artifact.json", "mpt-artifact.bin"] {
+        let path = dir.join(name);
+        artifact.save(&path).unwrap();
+        let loaded = MptProofArtifact::load(&path).unwrap();
+        assert_eq!(loaded, artifact);
+        loaded.verify(&params, vks).unwrap();
+    }
+
+    // the proof does not verify against any other final root
+    let mut wrong_root = artifact.clone();
+    wrong_root.instances[1] = wrong_root.instances[0];
+    assert!(matches!(
+        wrong_root.verify(&params, vks),
+        Err(ArtifactError::Proof { circuit: "mpt", .. })
+    ));
+
+    // nor with commitment indexes other than the ones of circuit
+    let mut wrong_index = artifact.clone();
+    wrong_index.hash_tbl_begin += 1;
+    assert!(matches!(
+        wrong_index.verify(&params, vks),
+        Err(ArtifactError::IndexMismatch { .. })
+    ));
+    let mut wrong_index = artifact.clone();
+    wrong_index.mpt_tbl_begin = None;
+    assert!(matches!(
+        wrong_index.verify(&params, vks),
+        Err(ArtifactError::IndexMismatch { .. })
+    ));
+    let mut wrong_index = artifact.clone();
+    wrong_index.lite = true;
+    assert!(matches!(
+        wrong_index.verify(&params, vks),
+        Err(ArtifactError::IndexMismatch { .. })
+    ));
+
+    // nor under the vks of other circuits
+    assert!(matches!(
+        artifact.verify(&params, (vks.1, vks.0)),
+        Err(ArtifactError::VkMismatch { .. })
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn combined_proof_artifact() {
+    let data: Vec<serde::SMTTrace> = serde_json::from_str(TEST_TRACE).unwrap();
+    let ops: Vec<AccountOp<Fp>> = data
+        .into_iter()
+        .map(|tr| (&tr).try_into().unwrap())
+        .collect();
+
+    let mut data: EthTrie<Fp> = Default::default();
+    data.add_ops(ops);
+    let size = data.circuit_size();
+    let circuit = CombinedCircuit::from(data.to_circuits((size.calcs, None), &[]).unwrap());
+    let instance = circuit.instance();
+
+    let params = Params::<Bn256>::unsafe_setup(size.combined_k);
+    let dir = unique_temp_dir("combined-artifact");
+    let store = KeyStore::new(&dir);
+    let shape = CircuitShape::combined(size.calcs, size.combined_k);
+    let pk = store.get_or_generate(&params, &shape, &circuit).unwrap();
+    let vk = store
+        .load_vk::<CombinedCircuit<Fp>>(&shape)
+        .unwrap()
+        .unwrap();
+
+    let artifact = MptProofArtifact::prove_combined(
+        &params,
+        &pk,
+        circuit,
+        TranscriptKind::Blake2b,
+        ChaCha8Rng::from_seed([102u8; 32]),
+    )
+    .unwrap();
+    assert_eq!(artifact.mode(), CircuitMode::Combined);
+    assert_eq!(artifact.calcs, size.calcs);
+    assert_eq!(artifact.instance().unwrap(), instance[0]);
+
+    for name in ["combined-artifact.json", "combined-artifact.bin"] {
+        let path = dir.join(name);
+        artifact.save(&path).unwrap();
+        let loaded = MptProofArtifact::load(&path).unwrap();
+        assert_eq!(loaded, artifact);
+        loaded.verify_combined(&params, &vk).unwrap();
+    }
+
+    // the proof does not verify against any other final root
+    let mut wrong_root = artifact.clone();
+    wrong_root.instances[1] = wrong_root.instances[0];
+    assert!(matches!(
+        wrong_root.verify_combined(&params, &vk),
+        Err(ArtifactError::Proof {
+            circuit: "combined",
+            ..
+        })
+    ));
+
+    // nor as the proofs of split mode
+    assert!(matches!(
+        artifact.verify(&params, (&vk, &vk)),
+        Err(ArtifactError::Mode(CircuitMode::Combined))
+    ));
+    let mut split = artifact.clone();
+    split.combined = false;
+    assert!(matches!(
+        split.verify_combined(&params, &vk),
+        Err(ArtifactError::Mode(CircuitMode::Split))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn key_store() {
+    let dir = unique_temp_dir("mpt-key-store");
+    let store = KeyStore::new(&dir);
+
+    let size = CircuitSize::new::<Fp>(40);
+    let params = Params::<Bn256>::unsafe_setup(size.mpt_k);
+    let (circuit, _) = EthTrie::<Fp>::default()
+        .to_circuits((size.calcs, None), &[])
+        .unwrap();
+    let shape = CircuitShape::mpt::<false>(size.calcs, size.mpt_k);
+    type C = EthTrieCircuit<Fp, false>;
+
+    let vk_bytes = |vk: &halo2_proofs::plonk::VerifyingKey<G1Affine>| {
+        let mut buf = Vec::new();
+        vk.write(&mut buf, SerdeFormat::RawBytes).unwrap();
+        buf
+    };
+
+    assert!(store.load_pk::<C>(&shape).unwrap().is_none());
+    let generated = store.get_or_generate(&params, &shape, &circuit).unwrap();
+    let loaded = store.get_or_generate(&params, &shape, &circuit).unwrap();
+    assert_eq!(vk_bytes(generated.get_vk()), vk_bytes(loaded.get_vk()));
+    let vk = store.load_vk::<C>(&shape).unwrap().unwrap();
+    assert_eq!(vk_bytes(generated.get_vk()), vk_bytes(&vk));
+
+    // keys are refused under any other k
+    let other = CircuitShape::mpt::<false>(size.calcs, size.mpt_k + 1);
+    for ext in ["pk", "vk", "json"] {
+        std::fs::copy(
+            dir.join(format!("{}.{ext}", shape.key())),
+            dir.join(format!("{}.{ext}", other.key())),
+        )
+        .unwrap();
+    }
+    assert!(matches!(
+        store.load_vk::<C>(&other),
+        Err(KeyStoreError::KMismatch { stored, .. }) if stored == size.mpt_k
+    ));
+
+    // keys differing from the recorded fingerprint are refused
+    let meta_path = dir.join(format!("{}.json", shape.key()));
+    let stored_meta = std::fs::read(&meta_path).unwrap();
+    let mut meta: serde_json::Value = serde_json::from_slice(&stored_meta).unwrap();
+    meta["vk"] = serde_json::to_value(serde::Hash::default()).unwrap();
+    std::fs::write(&meta_path, serde_json::to_vec(&meta).unwrap()).unwrap();
+    assert!(matches!(
+        store.get_or_generate(&params, &shape, &circuit),
+        Err(KeyStoreError::FingerprintMismatch { .. })
+    ));
+
+    // keys for another constraint system are refused
+    let mut meta: serde_json::Value = serde_json::from_slice(&stored_meta).unwrap();
+    meta["digest"] = serde_json::to_value(serde::Hash::default()).unwrap();
+    std::fs::write(&meta_path, serde_json::to_vec(&meta).unwrap()).unwrap();
+    assert!(matches!(
+        store.get_or_generate(&params, &shape, &circuit),
+        Err(KeyStoreError::DigestMismatch { .. })
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
 }